@@ -0,0 +1,2 @@
+pub mod probability;
+pub mod monte_carlo;
\ No newline at end of file
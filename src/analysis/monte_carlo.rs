@@ -0,0 +1,101 @@
+use crate::analysis::probability::Outcome;
+
+// z-score of the 0.999 quantile of the standard normal: the chi-square check
+// fails a correct program about once in a thousand runs
+const Z_999: f64 = 3.090;
+
+// how often each (output, exit status) pair was seen over a number of runs
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    runs: usize,
+    counts: Vec<(Vec<String>, i32, usize)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChiSquare {
+    pub statistic: f64,
+    pub degrees_of_freedom: usize,
+    // the statistic a correct program stays under with probability 0.999
+    pub critical: f64,
+    // observed results the analysis says cannot happen, with their counts
+    pub unexpected: Vec<(Vec<String>, i32, usize)>,
+}
+
+impl ChiSquare {
+    pub fn passed(&self) -> bool {
+        self.unexpected.is_empty() && self.statistic <= self.critical
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, output: Vec<String>, status: i32) {
+        self.runs += 1;
+        match self.counts.iter_mut().find(|(o, s, _)| *o == output && *s == status) {
+            Some((_, _, count)) => *count += 1,
+            None => self.counts.push((output, status, 1)),
+        }
+    }
+
+    pub fn runs(&self) -> usize {
+        self.runs
+    }
+
+    // most frequent first
+    pub fn entries(&self) -> Vec<(Vec<String>, i32, usize)> {
+        let mut entries = self.counts.clone();
+        entries.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+        entries
+    }
+
+    pub fn count(&self, output: &[String], status: i32) -> usize {
+        self.counts
+            .iter()
+            .find(|(o, s, _)| o == output && *s == status)
+            .map_or(0, |(_, _, count)| *count)
+    }
+
+    // Pearson's test of the counts against the exact outcome probabilities.
+    // the statistic is only trustworthy when every outcome is expected at
+    // least five times, so rare outcomes need enough runs
+    pub fn chi_square(&self, expected: &[Outcome]) -> ChiSquare {
+        let statistic = expected
+            .iter()
+            .map(|outcome| {
+                let e = outcome.probability.as_f64() * self.runs as f64;
+                let o = self.count(&outcome.output, outcome.status) as f64;
+                (o - e) * (o - e) / e
+            })
+            .sum();
+
+        let unexpected = self
+            .counts
+            .iter()
+            .filter(|(o, s, _)| !expected.iter().any(|e| e.output == *o && e.status == *s))
+            .cloned()
+            .collect();
+
+        let degrees_of_freedom = expected.len().saturating_sub(1);
+        ChiSquare {
+            statistic,
+            degrees_of_freedom,
+            critical: chi_square_critical(degrees_of_freedom),
+            unexpected,
+        }
+    }
+}
+
+// the 0.999 quantile of the chi-square distribution with k degrees of
+// freedom, by the Wilson-Hilferty approximation. with a single possible
+// outcome any count is exact, so nothing is tolerated
+fn chi_square_critical(k: usize) -> f64 {
+    if k == 0 {
+        return 0.0;
+    }
+    let k = k as f64;
+    let h = 2.0 / (9.0 * k);
+    k * (1.0 - h + Z_999 * h.sqrt()).powi(3)
+}
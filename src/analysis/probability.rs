@@ -0,0 +1,609 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::optimizer::optimizer::{ConstValue, Optimizer};
+use crate::parsing::ast::{Expr, Stmt, MatchPattern};
+use crate::codegen::common::{assert_message, ASSERT_FAILED_STATUS};
+
+// every `maybe` is a fair coin, so a path that flipped k coins has
+// probability 2^-k; past this many flips the exact sums no longer fit
+const MAX_FLIPS: u32 = 120;
+// the analysis gives up instead of enumerating more paths than this at once
+const MAX_PATHS: usize = 1 << 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnalysisError {
+    // the expression needs a value only known when the program runs (input,
+    // arguments, or a runtime error such as a division by zero)
+    NotStatic(Expr),
+    TooManyPaths,
+    // a paywall prompts on some path, so the rest of the output depends on
+    // the key typed at it
+    Paywall,
+}
+
+// an exact probability: numerator / 2^exponent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Probability {
+    numerator: u128,
+    exponent: u32,
+}
+
+impl Probability {
+    fn of_flips(flips: u32) -> Self {
+        Self { numerator: 1, exponent: flips }
+    }
+
+    fn add(self, other: Self) -> Self {
+        let exponent = self.exponent.max(other.exponent);
+        let numerator = (self.numerator << (exponent - self.exponent))
+            + (other.numerator << (exponent - other.exponent));
+        Self { numerator, exponent }.reduced()
+    }
+
+    fn reduced(mut self) -> Self {
+        while self.exponent > 0 && self.numerator.is_multiple_of(2) {
+            self.numerator /= 2;
+            self.exponent -= 1;
+        }
+        self
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        self.numerator as f64 / 2f64.powi(self.exponent as i32)
+    }
+}
+
+// prints as a decimal, followed by the exact fraction when it is not whole
+impl fmt::Display for Probability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_f64())?;
+        if self.exponent > 0 {
+            write!(f, " ({}/{})", self.numerator, 1u128 << self.exponent)?;
+        }
+        Ok(())
+    }
+}
+
+// one distinct way the program can end: what it printed and its exit status
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub output: Vec<String>,
+    pub status: i32,
+    pub probability: Probability,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Scalar(ConstValue),
+    Array(Vec<ConstValue>),
+    Struct(Vec<(String, ConstValue)>),
+}
+
+// one execution in progress; `flips` is how many `maybe`s it has observed
+#[derive(Debug, Clone)]
+struct Path {
+    scopes: Vec<HashMap<String, Value>>,
+    output: Vec<String>,
+    prints: usize,
+    flips: u32,
+    status: Option<i32>,
+}
+
+// a runtime check that stops a path, with the message the runtime prints
+type Trap = &'static str;
+
+// Runs a program symbolically, forking at every `maybe` into the path where
+// it is true and the path where it is false. Everything else has to be known
+// statically: expressions are folded by the optimizer with the path's scalar
+// variables as constants, so a branch that does not depend on `maybe` is
+// followed without forking. Paths that end with the same output and status
+// are merged, and their probabilities added.
+pub struct ProbabilityAnalyzer {
+    // source file named in assertion failure messages
+    source_name: String,
+    enums: HashMap<String, Vec<String>>,
+}
+
+impl Default for ProbabilityAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProbabilityAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            source_name: "<input>".to_string(),
+            enums: HashMap::new(),
+        }
+    }
+
+    pub fn with_source_name(mut self, name: &str) -> Self {
+        self.source_name = name.to_string();
+        self
+    }
+
+    // expects a semantically valid program, ideally already optimized;
+    // outcomes come most likely first
+    pub fn analyze(&mut self, stmts: &[Stmt]) -> Result<Vec<Outcome>, AnalysisError> {
+        for stmt in stmts {
+            if let Stmt::EnumDeclaration { name, variants } = stmt {
+                self.enums.insert(name.clone(), variants.clone());
+            }
+        }
+
+        let start = Path {
+            scopes: vec![HashMap::new()],
+            output: Vec::new(),
+            prints: 0,
+            flips: 0,
+            status: None,
+        };
+        let paths = self.exec_stmts(vec![start], stmts)?;
+
+        let mut outcomes: Vec<Outcome> = Vec::new();
+        for path in paths {
+            let status = path.status.unwrap_or(0);
+            let probability = Probability::of_flips(path.flips);
+            match outcomes.iter_mut().find(|o| o.output == path.output && o.status == status) {
+                Some(outcome) => outcome.probability = outcome.probability.add(probability),
+                None => outcomes.push(Outcome { output: path.output, status, probability }),
+            }
+        }
+        outcomes.sort_by(|a, b| {
+            b.probability
+                .as_f64()
+                .total_cmp(&a.probability.as_f64())
+                .then_with(|| a.output.cmp(&b.output))
+        });
+        Ok(outcomes)
+    }
+
+    // -------- STATEMENTS --------
+
+    fn exec_stmts(&self, mut paths: Vec<Path>, stmts: &[Stmt]) -> Result<Vec<Path>, AnalysisError> {
+        for stmt in stmts {
+            let mut next = Vec::new();
+            for path in paths {
+                if path.status.is_some() {
+                    next.push(path);
+                } else {
+                    next.extend(self.exec_stmt(path, stmt)?);
+                }
+            }
+            if next.len() > MAX_PATHS {
+                return Err(AnalysisError::TooManyPaths);
+            }
+            paths = next;
+        }
+        Ok(paths)
+    }
+
+    fn exec_block(&self, mut path: Path, stmts: &[Stmt]) -> Result<Vec<Path>, AnalysisError> {
+        path.scopes.push(HashMap::new());
+        let mut paths = self.exec_stmts(vec![path], stmts)?;
+        for path in &mut paths {
+            path.scopes.pop();
+        }
+        Ok(paths)
+    }
+
+    fn exec_stmt(&self, path: Path, stmt: &Stmt) -> Result<Vec<Path>, AnalysisError> {
+        let mut paths = Vec::new();
+        match stmt {
+            Stmt::Print(expr) => {
+                for (mut path, value) in self.eval(&path, expr, &mut paths)? {
+                    let text = self.display(&value);
+                    path.output.push(text);
+                    path.prints += 1;
+                    paths.push(path);
+                }
+            }
+
+            Stmt::PrintFormatted { format, args } => {
+                for (mut path, values) in self.eval_all(&path, args, &mut paths)? {
+                    let mut pieces = format.split("{}");
+                    let mut text = pieces.next().unwrap_or_default().to_string();
+                    for (value, piece) in values.iter().zip(pieces) {
+                        text.push_str(&self.display(value));
+                        text.push_str(piece);
+                    }
+                    path.output.push(text);
+                    path.prints += 1;
+                    paths.push(path);
+                }
+            }
+
+            Stmt::VarDeclaration { name, value, .. }
+            | Stmt::ConstDeclaration { name, value, .. }
+            | Stmt::GlobalDeclaration { name, value, .. } => {
+                for (mut path, value) in self.eval_value(&path, value, &mut paths)? {
+                    path.scopes.last_mut().unwrap().insert(name.clone(), value);
+                    paths.push(path);
+                }
+            }
+
+            Stmt::ExprStmt(Expr::Assign { name, value }) => {
+                for (mut path, value) in self.eval_value(&path, value, &mut paths)? {
+                    *path.var_mut(name) = value;
+                    paths.push(path);
+                }
+            }
+
+            Stmt::ExprStmt(Expr::IndexAssign { name, index, value }) => {
+                let operands = [(**index).clone(), (**value).clone()];
+                for (mut path, values) in self.eval_all(&path, &operands, &mut paths)? {
+                    let Value::Array(elements) = path.var_mut(name) else {
+                        unreachable!("'{}' is checked to be an array", name)
+                    };
+                    let ConstValue::Int(i) = values[0] else {
+                        unreachable!("array indices are checked to be ints")
+                    };
+                    match usize::try_from(i).ok().filter(|i| *i < elements.len()) {
+                        Some(i) => elements[i] = values[1].clone(),
+                        None => path.trap("runtime error: array index out of bounds"),
+                    }
+                    paths.push(path);
+                }
+            }
+
+            Stmt::ExprStmt(Expr::FieldAssign { name, field, value }) => {
+                for (mut path, value) in self.eval(&path, value, &mut paths)? {
+                    let Value::Struct(fields) = path.var_mut(name) else {
+                        unreachable!("'{}' is checked to be a struct", name)
+                    };
+                    let slot = fields.iter_mut().find(|(f, _)| f == field).unwrap();
+                    slot.1 = value;
+                    paths.push(path);
+                }
+            }
+
+            Stmt::ExprStmt(expr) => {
+                let results = self.eval(&path, expr, &mut paths)?;
+                paths.extend(results.into_iter().map(|(path, _)| path));
+            }
+
+            Stmt::Block(stmts) => paths = self.exec_block(path, stmts)?,
+
+            Stmt::If { condition, then_block, else_block } => {
+                for (path, value) in self.eval(&path, condition, &mut paths)? {
+                    if value == ConstValue::Bool(true) {
+                        paths.extend(self.exec_block(path, then_block)?);
+                    } else if let Some(else_block) = else_block {
+                        paths.extend(self.exec_block(path, else_block)?);
+                    } else {
+                        paths.push(path);
+                    }
+                }
+            }
+
+            Stmt::Match { scrutinee, arms } => {
+                for (path, value) in self.eval(&path, scrutinee, &mut paths)? {
+                    let ConstValue::Enum { variant, .. } = value else {
+                        unreachable!("match scrutinees are checked to be enums")
+                    };
+                    let arm = arms.iter().find(|arm| match &arm.pattern {
+                        MatchPattern::Variant(v) => *v == variant,
+                        MatchPattern::Wildcard => true,
+                    });
+                    match arm {
+                        Some(arm) => paths.extend(self.exec_block(path, &arm.body)?),
+                        None => paths.push(path),
+                    }
+                }
+            }
+
+            Stmt::StructDeclaration { .. } | Stmt::EnumDeclaration { .. } => paths.push(path),
+
+            Stmt::Paywall(n) => {
+                if path.prints as i64 >= *n {
+                    return Err(AnalysisError::Paywall);
+                }
+                paths.push(path);
+            }
+
+            Stmt::Exit(code) | Stmt::Return(code) => {
+                for (mut path, value) in self.eval(&path, code, &mut paths)? {
+                    let ConstValue::Int(status) = value else {
+                        unreachable!("exit statuses are checked to be ints")
+                    };
+                    // the process only sees the low byte, as when the program runs
+                    path.status = Some(status & 0xff);
+                    paths.push(path);
+                }
+            }
+
+            Stmt::Assert { condition, message, line } => {
+                for (mut path, value) in self.eval(&path, condition, &mut paths)? {
+                    if value == ConstValue::Bool(false) {
+                        let text = assert_message(&self.source_name, *line, message.as_deref());
+                        path.output.push(text);
+                        path.status = Some(ASSERT_FAILED_STATUS);
+                    }
+                    paths.push(path);
+                }
+            }
+        }
+        Ok(paths)
+    }
+
+    // how print shows a value: enums as their variant's index
+    fn display(&self, value: &ConstValue) -> String {
+        match value {
+            ConstValue::Int(n) => n.to_string(),
+            ConstValue::Bool(b) => b.to_string(),
+            ConstValue::String(s) => s.clone(),
+            ConstValue::Enum { enum_name, variant } => self.enums[enum_name]
+                .iter()
+                .position(|v| v == variant)
+                .unwrap_or(0)
+                .to_string(),
+        }
+    }
+
+    // -------- EXPRESSIONS --------
+
+    // arrays and structs are copied by value, everything else is a scalar
+    fn eval_value(
+        &self,
+        path: &Path,
+        expr: &Expr,
+        stopped: &mut Vec<Path>,
+    ) -> Result<Vec<(Path, Value)>, AnalysisError> {
+        match expr {
+            Expr::ArrayLiteral(elements) => Ok(self
+                .eval_all(path, elements, stopped)?
+                .into_iter()
+                .map(|(path, values)| (path, Value::Array(values)))
+                .collect()),
+            Expr::StructLiteral { fields, .. } => {
+                let exprs: Vec<Expr> = fields.iter().map(|(_, e)| e.clone()).collect();
+                Ok(self
+                    .eval_all(path, &exprs, stopped)?
+                    .into_iter()
+                    .map(|(path, values)| {
+                        let names = fields.iter().map(|(name, _)| name.clone());
+                        (path, Value::Struct(names.zip(values).collect()))
+                    })
+                    .collect())
+            }
+            Expr::Identifier(name) if !matches!(path.var(name), Value::Scalar(_)) => {
+                Ok(vec![(path.clone(), path.var(name).clone())])
+            }
+            _ => Ok(self
+                .eval(path, expr, stopped)?
+                .into_iter()
+                .map(|(path, value)| (path, Value::Scalar(value)))
+                .collect()),
+        }
+    }
+
+    // evaluates left to right; every combination of outcomes is a path
+    fn eval_all(
+        &self,
+        path: &Path,
+        exprs: &[Expr],
+        stopped: &mut Vec<Path>,
+    ) -> Result<Vec<(Path, Vec<ConstValue>)>, AnalysisError> {
+        let mut results = vec![(path.clone(), Vec::new())];
+        for expr in exprs {
+            let mut next = Vec::new();
+            for (path, values) in results {
+                for (path, value) in self.eval(&path, expr, stopped)? {
+                    let mut values = values.clone();
+                    values.push(value);
+                    next.push((path, values));
+                }
+            }
+            results = next;
+        }
+        Ok(results)
+    }
+
+    // the value of a scalar expression on every path it can take. a path that
+    // traps while evaluating it goes to `stopped` instead
+    fn eval(
+        &self,
+        path: &Path,
+        expr: &Expr,
+        stopped: &mut Vec<Path>,
+    ) -> Result<Vec<(Path, ConstValue)>, AnalysisError> {
+        let constants = path.constants();
+        let mut results = Vec::new();
+        let mut pending = vec![(path.flips, expr.clone())];
+
+        while let Some((flips, expr)) = pending.pop() {
+            let expr = Optimizer::with_constants(constants.clone()).simplify(expr);
+
+            // the first `maybe` in the source is the first one evaluated, and
+            // folding after each flip drops the ones short-circuiting skips
+            if contains_maybe(&expr) {
+                if flips >= MAX_FLIPS {
+                    return Err(AnalysisError::TooManyPaths);
+                }
+                for flip in [false, true] {
+                    let mut replaced = false;
+                    let expr = replace_first_maybe(expr.clone(), flip, &mut replaced);
+                    pending.push((flips + 1, expr));
+                }
+                continue;
+            }
+
+            let mut path = Path { flips, ..path.clone() };
+            match self.resolve(&path, expr)? {
+                Ok(expr) => {
+                    let value = Optimizer::with_constants(constants.clone())
+                        .evaluate(&expr)
+                        .ok_or(AnalysisError::NotStatic(expr))?;
+                    results.push((path, value));
+                }
+                Err(trap) => {
+                    path.trap(trap);
+                    stopped.push(path);
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    // replaces reads of array elements and struct fields with their values,
+    // which the optimizer does not track
+    fn resolve(&self, path: &Path, expr: Expr) -> Result<Result<Expr, Trap>, AnalysisError> {
+        let mut failure = None;
+        let mut trapped = None;
+        let resolved = self.resolve_expr(path, expr, &mut failure, &mut trapped);
+        match (failure, trapped) {
+            (Some(e), _) => Err(e),
+            (None, Some(trap)) => Ok(Err(trap)),
+            (None, None) => Ok(Ok(resolved)),
+        }
+    }
+
+    fn resolve_expr(
+        &self,
+        path: &Path,
+        expr: Expr,
+        failure: &mut Option<AnalysisError>,
+        trapped: &mut Option<Trap>,
+    ) -> Expr {
+        match expr {
+            Expr::Index { name, index } => {
+                let index = self.resolve_expr(path, *index, failure, trapped);
+                let Value::Array(elements) = path.var(&name) else {
+                    unreachable!("'{}' is checked to be an array", name)
+                };
+                match Optimizer::with_constants(path.constants()).evaluate(&index) {
+                    Some(ConstValue::Int(i)) => {
+                        match usize::try_from(i).ok().and_then(|i| elements.get(i)) {
+                            Some(value) => literal(value),
+                            None => {
+                                trapped.get_or_insert("runtime error: array index out of bounds");
+                                Expr::IntegerLiteral(0)
+                            }
+                        }
+                    }
+                    _ => {
+                        failure.get_or_insert(AnalysisError::NotStatic(index.clone()));
+                        Expr::Index { name, index: Box::new(index) }
+                    }
+                }
+            }
+            Expr::Field { name, field } => {
+                let Value::Struct(fields) = path.var(&name) else {
+                    unreachable!("'{}' is checked to be a struct", name)
+                };
+                let (_, value) = fields.iter().find(|(f, _)| *f == field).unwrap();
+                literal(value)
+            }
+            expr => map_children(expr, &mut |e| self.resolve_expr(path, e, failure, trapped)),
+        }
+    }
+}
+
+impl Path {
+    fn var(&self, name: &str) -> &Value {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .expect("variables are checked to be declared")
+    }
+
+    fn var_mut(&mut self, name: &str) -> &mut Value {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+            .expect("variables are checked to be declared")
+    }
+
+    // the scalar variables in scope, innermost declaration winning
+    fn constants(&self) -> HashMap<String, ConstValue> {
+        let mut constants = HashMap::new();
+        for scope in &self.scopes {
+            for (name, value) in scope {
+                match value {
+                    Value::Scalar(c) => {
+                        constants.insert(name.clone(), c.clone());
+                    }
+                    _ => {
+                        constants.remove(name);
+                    }
+                }
+            }
+        }
+        constants
+    }
+
+    fn trap(&mut self, message: Trap) {
+        self.output.push(message.to_string());
+        self.status = Some(1);
+    }
+}
+
+fn literal(value: &ConstValue) -> Expr {
+    match value {
+        ConstValue::Int(n) => Expr::IntegerLiteral(*n),
+        ConstValue::Bool(b) => Expr::BooleanLiteral(*b),
+        ConstValue::String(s) => Expr::StringLiteral(s.clone()),
+        ConstValue::Enum { enum_name, variant } => Expr::EnumVariant {
+            enum_name: enum_name.clone(),
+            variant: variant.clone(),
+        },
+    }
+}
+
+fn contains_maybe(expr: &Expr) -> bool {
+    let mut found = matches!(expr, Expr::Maybe);
+    map_children(expr.clone(), &mut |e| {
+        found |= contains_maybe(&e);
+        e
+    });
+    found
+}
+
+fn replace_first_maybe(expr: Expr, value: bool, replaced: &mut bool) -> Expr {
+    match expr {
+        _ if *replaced => expr,
+        Expr::Maybe => {
+            *replaced = true;
+            Expr::BooleanLiteral(value)
+        }
+        expr => map_children(expr, &mut |e| replace_first_maybe(e, value, replaced)),
+    }
+}
+
+// rebuilds an expression with `f` applied to each direct subexpression, in
+// evaluation order
+fn map_children(expr: Expr, f: &mut impl FnMut(Expr) -> Expr) -> Expr {
+    let mut boxed = |e: Box<Expr>| Box::new(f(*e));
+    match expr {
+        Expr::Unary { op, operand } => Expr::Unary { op, operand: boxed(operand) },
+        Expr::Binary { left, op, right } => {
+            let left = boxed(left);
+            Expr::Binary { left, op, right: boxed(right) }
+        }
+        Expr::Assign { name, value } => Expr::Assign { name, value: boxed(value) },
+        Expr::Index { name, index } => Expr::Index { name, index: boxed(index) },
+        Expr::IndexAssign { name, index, value } => {
+            let index = boxed(index);
+            Expr::IndexAssign { name, index, value: boxed(value) }
+        }
+        Expr::FieldAssign { name, field, value } => {
+            Expr::FieldAssign { name, field, value: boxed(value) }
+        }
+        Expr::Conditional { condition, then_expr, else_expr } => {
+            let condition = boxed(condition);
+            let then_expr = boxed(then_expr);
+            Expr::Conditional { condition, then_expr, else_expr: boxed(else_expr) }
+        }
+        Expr::Conversion { target, operand } => Expr::Conversion { target, operand: boxed(operand) },
+        Expr::ArrayLiteral(elements) => Expr::ArrayLiteral(elements.into_iter().map(f).collect()),
+        Expr::StructLiteral { name, fields } => Expr::StructLiteral {
+            name,
+            fields: fields.into_iter().map(|(n, e)| (n, f(e))).collect(),
+        },
+        Expr::Call { name, args } => Expr::Call { name, args: args.into_iter().map(f).collect() },
+        leaf => leaf,
+    }
+}
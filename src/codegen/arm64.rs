@@ -1,199 +1,1333 @@
-use std::collections::HashMap;
-use crate::parsing::ast::{Expr, Stmt, BinOp};
-
-pub struct Codegen {
-    out: String,
-    vars: HashMap<String, usize>, 
-    stack_offset: usize,
-    label_counter: usize,
-}
-
-impl Codegen {
-    pub fn new() -> Self {
-        Self {
-            out: String::new(),
-            vars: HashMap::new(),
-            stack_offset: 0,
-            label_counter: 0,
-        }
-    }
-
-    pub fn generate(mut self, stmts: &[Stmt]) -> String {
-        // 1. DATA SECTION 
-        let mut out = String::from("\t.data\n");
-        out.push_str("fmt_int: .asciz \"%d\\n\"\n");
-        out.push_str("fmt_str: .asciz \"%s\\n\"\n");
-        
-        // PAYWALL STRINGS
-        out.push_str("fmt_scan: .asciz \"%s\"\n");
-        out.push_str("msg_pay: .asciz \"free trial over pew pew, type 'haha' to continue: \"\n");
-        out.push_str("secret:  .asciz \"haha\"\n");
-
-        // 2. TEXT SECTION
-        out.push_str("\n\t.text\n");
-        out.push_str("\t.global main\n");
-        out.push_str("main:\n");
-
-        // Prologue
-        out.push_str("\tstp x29, x30, [sp, #-16]!\n");
-        out.push_str("\tmov x29, sp\n");
-        out.push_str("\tsub sp, sp, #512\n");
-
-        // Generate statements (populates self.out)
-        for stmt in stmts {
-            self.gen_stmt(stmt);
-        }
-
-        // Epilogue
-        self.emit("\tadd sp, sp, #512");
-        self.emit("\tldp x29, x30, [sp], #16");
-        self.emit("\tmov x0, #0"); 
-        self.emit("\tret");
-
-        out + &self.out 
-    }
-
-    // --- STATEMENT GENERATION ---
-
-    fn gen_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::VarDeclaration { name, value } => {
-                let r = self.gen_expr(value);
-                let offset = if let Some(&off) = self.vars.get(name) {
-                    off
-                } else {
-                    let off = self.stack_offset;
-                    self.vars.insert(name.clone(), off);
-                    self.stack_offset += 8; 
-                    off
-                };
-                self.emit(format!("\tstr {}, [sp, #{}]", r, offset));
-            }
-
-            Stmt::Print(expr) => {
-                let r = self.gen_expr(expr);
-                self.emit("\tadrp x0, fmt_int");
-                self.emit("\tadd  x0, x0, :lo12:fmt_int");
-                self.emit(format!("\tmov x1, {}", r));
-                self.emit("\tbl printf");
-            }
-
-            Stmt::Block(stmts) => {
-                for s in stmts { self.gen_stmt(s); }
-            }
-
-            Stmt::If { condition, then_block, else_block } => {
-                let cond_reg = self.gen_expr(condition);
-                let label_else = self.label("else");
-                let label_end = self.label("endif");
-
-                self.emit(format!("\tcmp {}, #0", cond_reg));
-                self.emit(format!("\tbeq {}", label_else));
-
-                for s in then_block { self.gen_stmt(s); }
-                self.emit(format!("\tb {}", label_end));
-
-                self.emit(format!("{}:", label_else));
-                if let Some(block) = else_block {
-                    for s in block { self.gen_stmt(s); }
-                }
-                self.emit(format!("{}:", label_end));
-            }
-
-            Stmt::ExprStmt(expr) => {
-                self.gen_expr(expr);
-            }
-
-            // --- PAYWALL ---
-            Stmt::Paywall(_) => {
-                self.emit("\tadrp x0, msg_pay");
-                self.emit("\tadd x0, x0, :lo12:msg_pay");
-                self.emit("\tbl printf");
-
-                self.emit("\tadrp x0, fmt_scan");
-                self.emit("\tadd x0, x0, :lo12:fmt_scan");
-                self.emit("\tadd x1, sp, #400"); // buffer at sp+400
-                self.emit("\tbl scanf");
-
-                self.emit("\tadd x0, sp, #400");
-                self.emit("\tadrp x1, secret");
-                self.emit("\tadd x1, x1, :lo12:secret");
-                self.emit("\tbl strcmp");
-
-                let label_paid = self.label("paid");
-                self.emit("\tcmp x0, #0");
-                self.emit(format!("\tbeq {}", label_paid));
-
-                // Exit if wrong
-                self.emit("\tmov x0, #1"); 
-                self.emit("\tmov x8, #93");
-                self.emit("\tsvc #0");
-
-                self.emit(format!("{}:", label_paid));
-            }
-        }
-    }
-
-    // --- EXPRESSION GENERATION ---
-
-    fn gen_expr(&mut self, expr: &Expr) -> String {
-        match expr {
-            Expr::IntegerLiteral(n) => {
-                let r = self.alloc_tmp();
-                self.emit(format!("\tldr {}, ={}", r, n));
-                r
-            }
-            Expr::Identifier(name) => {
-                let r = self.alloc_tmp();
-                let offset = self.vars.get(name).copied().unwrap_or(0);
-                self.emit(format!("\tldr {}, [sp, #{}]", r, offset));
-                r
-            }
-            Expr::Binary { left, op, right } => {
-                let r1 = self.gen_expr(left);
-                let r2 = self.gen_expr(right);
-                let dest = self.alloc_tmp();
-
-                match op {
-                    BinOp::Add => self.emit(format!("\tadd {}, {}, {}", dest, r1, r2)),
-                    BinOp::Sub => self.emit(format!("\tsub {}, {}, {}", dest, r1, r2)),
-                    BinOp::GreaterThan => {
-                        self.emit(format!("\tcmp {}, {}", r1, r2));
-                        self.emit(format!("\tcset {}, gt", dest));
-                    }
-                    BinOp::LessThan => {
-                        self.emit(format!("\tcmp {}, {}", r1, r2));
-                        self.emit(format!("\tcset {}, lt", dest));
-                    }
-                }
-                dest
-            }
-            Expr::Maybe => {
-                let r = self.alloc_tmp();
-                self.emit("\tbl rand");
-                self.emit(format!("\tand {}, x0, #1", r));
-                r
-            }
-            _ => { 
-                let r = self.alloc_tmp(); 
-                self.emit(format!("\tmov {}, #0", r)); 
-                r 
-            }
-        }
-    }
-
-    fn alloc_tmp(&self) -> String {
-        "x14".to_string() 
-    }
-
-    fn label(&mut self, prefix: &str) -> String {
-        let l = format!("{}_{}", prefix, self.label_counter);
-        self.label_counter += 1;
-        l
-    }
-
-    fn emit(&mut self, asm: impl Into<String>) {
-        self.out.push_str(&asm.into());
-        self.out.push('\n');
-    }
-}
\ No newline at end of file
+use std::collections::BTreeSet;
+use crate::parsing::ast::{Expr, Stmt, BinOp, UnOp, TypeName, MatchArm, MatchPattern};
+use crate::optimizer::optimizer::is_pure;
+use crate::semantic::semantic::{Type, TypeEnv};
+use crate::codegen::common::{
+    Layout, Slot, Var, assert_message, fnv1a, has_paywall, ASSERT_FAILED_STATUS,
+    DEFAULT_PAYWALL_KEY, DEFAULT_PAYWALL_RETRIES, JUMP_TABLE_MIN_ARMS, FNV_OFFSET_BASIS, FNV_PRIME,
+};
+
+// expression temporaries live in callee-saved registers so they survive
+// the calls (printf, __q_rand, ...) made while evaluating an expression.
+const TMP_REGS: [&str; 10] = [
+    "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27", "x28",
+];
+
+// holds an operand for the one instruction that uses it, when the
+// temporary it was computed in had to be handed back (see gen_operand)
+const SPILL_REG: &str = "x15";
+
+pub struct Codegen {
+    out: String,
+    // source file named in assertion failure messages
+    source_name: String,
+    // variables, structs, enums, string literals and globals
+    layout: Layout,
+    label_counter: usize,
+    tmp_depth: usize,
+    // FNV-1a hash of the paywall's unlock key; the key itself never reaches
+    // the output
+    paywall_key_hash: u64,
+    // attempts the paywall allows before exiting
+    paywall_retries: u32,
+    // seed for `maybe` when QUASAR_SEED is not set at startup; None seeds
+    // from the clock
+    seed: Option<u64>,
+    // the program has a paywall, so every print bumps `__q_print_count`
+    metered: bool,
+    // runtime support routines referenced by the generated code; each one is
+    // emitted once after main together with the data it needs
+    runtime: BTreeSet<&'static str>,
+}
+
+impl Default for Codegen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Codegen {
+    pub fn new() -> Self {
+        Self {
+            out: String::new(),
+            source_name: "<input>".to_string(),
+            layout: Layout::new(),
+            label_counter: 0,
+            tmp_depth: 0,
+            paywall_key_hash: fnv1a(DEFAULT_PAYWALL_KEY),
+            paywall_retries: DEFAULT_PAYWALL_RETRIES,
+            seed: None,
+            metered: false,
+            runtime: BTreeSet::new(),
+        }
+    }
+
+    pub fn with_source_name(mut self, name: &str) -> Self {
+        self.source_name = name.to_string();
+        self
+    }
+
+    pub fn with_paywall_key(mut self, key: &str) -> Self {
+        self.paywall_key_hash = fnv1a(key);
+        self
+    }
+
+    pub fn with_paywall_retries(mut self, retries: u32) -> Self {
+        self.paywall_retries = retries.max(1);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn generate(mut self, stmts: &[Stmt]) -> String {
+        // prints are only counted when some paywall reads the count
+        self.metered = stmts.iter().any(has_paywall);
+        if self.metered {
+            self.runtime.insert("print_count");
+        }
+
+        // Generate statements first (populates self.out and self.runtime)
+        for stmt in stmts {
+            self.gen_stmt(stmt);
+        }
+
+        // 1. DATA SECTION
+        let mut out = self.layout.data_sections(&self.runtime);
+
+        // 2. TEXT SECTION
+        out.push_str("\n\t.text\n");
+        out.push_str("\t.global main\n");
+        out.push_str("main:\n");
+
+        // Prologue
+        out.push_str("\tstp x29, x30, [sp, #-16]!\n");
+        out.push_str("\tmov x29, sp\n");
+
+        // argc / argv arrive in x0 / x1 and are kept for args() and arg_count()
+        if self.runtime.contains("args") {
+            out.push_str("\tadrp x9, __q_argc\n");
+            out.push_str("\tstr w0, [x9, :lo12:__q_argc]\n");
+            out.push_str("\tadrp x9, __q_argv\n");
+            out.push_str("\tstr x1, [x9, :lo12:__q_argv]\n");
+        }
+
+        out.push_str("\tstp x19, x20, [sp, #-16]!\n");
+        out.push_str("\tstp x21, x22, [sp, #-16]!\n");
+        out.push_str("\tstp x23, x24, [sp, #-16]!\n");
+        out.push_str("\tstp x25, x26, [sp, #-16]!\n");
+        out.push_str("\tstp x27, x28, [sp, #-16]!\n");
+
+        // the frame holds every variable slot, rounded up to keep sp 16-byte aligned
+        let frame = self.layout.frame_size.div_ceil(16) * 16;
+        out.push_str(&sp_adjust("sub", frame));
+
+        // runtime strings are released however the program ends
+        if self.runtime.contains("alloc") {
+            out.push_str("\tadrp x0, __q_arena_free\n");
+            out.push_str("\tadd x0, x0, :lo12:__q_arena_free\n");
+            out.push_str("\tbl atexit\n");
+        }
+
+        if self.runtime.contains("rand") {
+            out.push_str("\tbl __q_seed_rng\n");
+        }
+
+        // Epilogue; `return` jumps here with its status already in x0
+        self.emit("\tmov x0, #0");
+        self.emit("__q_main_exit:");
+        self.out.push_str(&sp_adjust("add", frame));
+        self.emit("\tldp x27, x28, [sp], #16");
+        self.emit("\tldp x25, x26, [sp], #16");
+        self.emit("\tldp x23, x24, [sp], #16");
+        self.emit("\tldp x21, x22, [sp], #16");
+        self.emit("\tldp x19, x20, [sp], #16");
+        self.emit("\tldp x29, x30, [sp], #16");
+        self.emit("\tret");
+
+        // 3. RUNTIME SUPPORT
+        self.gen_runtime();
+
+        out + &self.out 
+    }
+
+    fn gen_runtime(&mut self) {
+        // traps are reached by a branch from the failing check; they never return
+        if self.runtime.contains("div_zero") {
+            self.gen_trap("__q_div_zero", "msg_div_zero");
+        }
+        if self.runtime.contains("bounds") {
+            self.gen_trap("__q_bounds", "msg_bounds");
+        }
+        if self.runtime.contains("int_to_str") {
+            self.gen_int_to_str();
+        }
+        if self.runtime.contains("str_to_int") {
+            self.gen_str_to_int();
+            self.gen_trap("__q_bad_int", "msg_bad_int");
+        }
+        if self.runtime.contains("concat") {
+            self.gen_concat();
+        }
+        if self.runtime.contains("substr") {
+            self.gen_substr();
+        }
+        if self.runtime.contains("index_of") {
+            self.gen_index_of();
+        }
+        if self.runtime.contains("to_upper") {
+            self.gen_to_upper();
+        }
+        if self.runtime.contains("char_at") {
+            self.gen_char_at();
+            self.gen_trap("__q_str_index", "msg_str_index");
+        }
+        if self.runtime.contains("read_line") {
+            self.gen_read_line();
+        }
+        if self.runtime.contains("read_int") {
+            self.gen_read_int();
+        }
+        if self.runtime.contains("rand") {
+            self.gen_rand();
+        }
+        if self.runtime.contains("paywall") {
+            self.gen_paywall();
+            self.gen_trap("__q_pay_denied", "msg_pay_denied");
+        }
+        if self.runtime.contains("args") {
+            self.gen_args();
+            self.gen_trap("__q_arg_index", "msg_arg_index");
+        }
+        if self.runtime.contains("alloc") {
+            self.gen_alloc();
+            self.gen_trap("__q_oom", "msg_oom");
+        }
+    }
+
+    // Strings built at runtime (concatenations, conversions) live in an
+    // arena: every block comes from __q_alloc, is linked into the list headed
+    // by __q_arena, and is freed by __q_arena_free, which main registers with
+    // atexit. String values are immutable, so they are shared by pointer and
+    // nothing is freed while the program runs.
+
+    // x0 = size -> x0 = a fresh arena block of that many bytes
+    fn gen_alloc(&mut self) {
+        self.emit("__q_alloc:");
+        self.emit("\tstp x29, x30, [sp, #-16]!");
+        self.emit("\tmov x29, sp");
+        self.emit("\tadd x0, x0, #8"); // room for the link to the previous block
+        self.emit("\tbl malloc");
+        self.emit("\tcbz x0, __q_oom");
+        self.emit("\tadrp x9, __q_arena");
+        self.emit("\tldr x10, [x9, :lo12:__q_arena]");
+        self.emit("\tstr x10, [x0]");
+        self.emit("\tstr x0, [x9, :lo12:__q_arena]");
+        self.emit("\tadd x0, x0, #8");
+        self.emit("\tldp x29, x30, [sp], #16");
+        self.emit("\tret");
+
+        self.emit("__q_arena_free:");
+        self.emit("\tstp x29, x30, [sp, #-32]!");
+        self.emit("\tmov x29, sp");
+        self.emit("\tstr x19, [sp, #16]");
+        self.emit("\tadrp x9, __q_arena");
+        self.emit("\tldr x19, [x9, :lo12:__q_arena]");
+        self.emit("\tstr xzr, [x9, :lo12:__q_arena]");
+        self.emit("1:");
+        self.emit("\tcbz x19, 2f");
+        self.emit("\tmov x0, x19");
+        self.emit("\tldr x19, [x19]");
+        self.emit("\tbl free");
+        self.emit("\tb 1b");
+        self.emit("2:");
+        self.emit("\tldr x19, [sp, #16]");
+        self.emit("\tldp x29, x30, [sp], #32");
+        self.emit("\tret");
+    }
+
+    // x0 = a, x1 = b -> x0 = a new string holding a followed by b
+    fn gen_concat(&mut self) {
+        self.emit("__q_concat:");
+        self.emit("\tstp x29, x30, [sp, #-48]!");
+        self.emit("\tmov x29, sp");
+        self.emit("\tstp x19, x20, [sp, #16]");
+        self.emit("\tstp x21, x22, [sp, #32]");
+        self.emit("\tmov x19, x0");
+        self.emit("\tmov x20, x1");
+        self.emit("\tbl strlen");
+        self.emit("\tmov x21, x0");
+        self.emit("\tmov x0, x20");
+        self.emit("\tbl strlen");
+        self.emit("\tmov x22, x0");
+        self.emit("\tadd x0, x21, x22");
+        self.emit("\tadd x0, x0, #1");
+        self.emit("\tbl __q_alloc");
+        self.emit("\tmov x1, x19");
+        self.emit("\tmov x2, x21");
+        self.emit("\tbl memcpy");
+        self.emit("\tmov x19, x0");
+        self.emit("\tadd x0, x0, x21");
+        self.emit("\tmov x1, x20");
+        self.emit("\tadd x2, x22, #1"); // b's terminator ends the result
+        self.emit("\tbl memcpy");
+        self.emit("\tmov x0, x19");
+        self.emit("\tldp x21, x22, [sp, #32]");
+        self.emit("\tldp x19, x20, [sp, #16]");
+        self.emit("\tldp x29, x30, [sp], #48");
+        self.emit("\tret");
+    }
+
+    // x0 = s, x1 = start, x2 = len -> x0 = a copy of that part of s, with
+    // start clamped to [0, strlen(s)] and len to what is left after start
+    fn gen_substr(&mut self) {
+        self.emit("__q_substr:");
+        self.emit("\tstp x29, x30, [sp, #-48]!");
+        self.emit("\tmov x29, sp");
+        self.emit("\tstp x19, x20, [sp, #16]");
+        self.emit("\tstp x21, x22, [sp, #32]");
+        self.emit("\tmov x19, x0");
+        self.emit("\tmov x20, x1");
+        self.emit("\tmov x21, x2");
+        self.emit("\tbl strlen");
+        self.emit("\tcmp x20, #0");
+        self.emit("\tcsel x20, xzr, x20, lt");
+        self.emit("\tcmp x20, x0");
+        self.emit("\tcsel x20, x0, x20, gt");
+        self.emit("\tsub x9, x0, x20");
+        self.emit("\tcmp x21, #0");
+        self.emit("\tcsel x21, xzr, x21, lt");
+        self.emit("\tcmp x21, x9");
+        self.emit("\tcsel x21, x9, x21, gt");
+        self.emit("\tadd x0, x21, #1");
+        self.emit("\tbl __q_alloc");
+        self.emit("\tmov x22, x0");
+        self.emit("\tadd x1, x19, x20");
+        self.emit("\tmov x2, x21");
+        self.emit("\tbl memcpy");
+        self.emit("\tstrb wzr, [x22, x21]");
+        self.emit("\tmov x0, x22");
+        self.emit("\tldp x21, x22, [sp, #32]");
+        self.emit("\tldp x19, x20, [sp, #16]");
+        self.emit("\tldp x29, x30, [sp], #48");
+        self.emit("\tret");
+    }
+
+    // x0 = s, x1 = t -> x0 = offset of the first t in s, or -1
+    fn gen_index_of(&mut self) {
+        self.emit("__q_index_of:");
+        self.emit("\tstp x29, x30, [sp, #-32]!");
+        self.emit("\tmov x29, sp");
+        self.emit("\tstr x19, [sp, #16]");
+        self.emit("\tmov x19, x0");
+        self.emit("\tbl strstr");
+        self.emit("\tcbz x0, 1f");
+        self.emit("\tsub x0, x0, x19");
+        self.emit("\tb 2f");
+        self.emit("1:");
+        self.emit("\tmov x0, #-1");
+        self.emit("2:");
+        self.emit("\tldr x19, [sp, #16]");
+        self.emit("\tldp x29, x30, [sp], #32");
+        self.emit("\tret");
+    }
+
+    // x0 = s -> x0 = a copy of s with ASCII letters upper-cased
+    fn gen_to_upper(&mut self) {
+        self.emit("__q_to_upper:");
+        self.emit("\tstp x29, x30, [sp, #-32]!");
+        self.emit("\tmov x29, sp");
+        self.emit("\tstr x19, [sp, #16]");
+        self.emit("\tmov x19, x0");
+        self.emit("\tbl strlen");
+        self.emit("\tadd x0, x0, #1");
+        self.emit("\tbl __q_alloc");
+        self.emit("\tmov x11, #0");
+        self.emit("1:"); // copies up to and including the terminator
+        self.emit("\tldrb w9, [x19, x11]");
+        self.emit("\tsub w10, w9, #97"); // 'a'
+        self.emit("\tcmp w10, #25");
+        self.emit("\tb.hi 2f");
+        self.emit("\tsub w9, w9, #32");
+        self.emit("2:");
+        self.emit("\tstrb w9, [x0, x11]");
+        self.emit("\tadd x11, x11, #1");
+        self.emit("\tcbnz w9, 1b");
+        self.emit("\tldr x19, [sp, #16]");
+        self.emit("\tldp x29, x30, [sp], #32");
+        self.emit("\tret");
+    }
+
+    // x0 = s, x1 = i -> x0 = the one-character string at s[i]; traps when i
+    // is outside s
+    fn gen_char_at(&mut self) {
+        self.emit("__q_char_at:");
+        self.emit("\tstp x29, x30, [sp, #-32]!");
+        self.emit("\tmov x29, sp");
+        self.emit("\tstp x19, x20, [sp, #16]");
+        self.emit("\tmov x19, x0");
+        self.emit("\tmov x20, x1");
+        self.emit("\tbl strlen");
+        self.emit("\tcmp x20, x0"); // unsigned, so a negative index fails too
+        self.emit("\tb.hs __q_str_index");
+        self.emit("\tmov x0, #2");
+        self.emit("\tbl __q_alloc");
+        self.emit("\tldrb w9, [x19, x20]");
+        self.emit("\tstrb w9, [x0]");
+        self.emit("\tstrb wzr, [x0, #1]");
+        self.emit("\tldp x19, x20, [sp, #16]");
+        self.emit("\tldp x29, x30, [sp], #32");
+        self.emit("\tret");
+    }
+
+    // -> x0 = the next line of stdin without its newline, or "" at end of
+    // input. fgets never writes past the 1024-byte block; a longer line is
+    // returned in pieces by successive calls
+    fn gen_read_line(&mut self) {
+        self.emit("__q_read_line:");
+        self.emit("\tstp x29, x30, [sp, #-32]!");
+        self.emit("\tmov x29, sp");
+        self.emit("\tstr x19, [sp, #16]");
+        self.emit("\tmov x0, #1024");
+        self.emit("\tbl __q_alloc");
+        self.emit("\tmov x19, x0");
+        self.emit("\tmov x1, #1024");
+        self.emit("\tadrp x2, :got:stdin");
+        self.emit("\tldr x2, [x2, :got_lo12:stdin]");
+        self.emit("\tldr x2, [x2]");
+        self.emit("\tbl fgets");
+        self.emit("\tcbnz x0, 1f");
+        self.emit("\tstrb wzr, [x19]");
+        self.emit("1:");
+        self.emit("\tmov x0, x19");
+        self.emit("\tbl strlen");
+        self.emit("\tcbz x0, 2f");
+        self.emit("\tsub x0, x0, #1");
+        self.emit("\tldrb w9, [x19, x0]");
+        self.emit("\tcmp w9, #10"); // '\n'
+        self.emit("\tb.ne 2f");
+        self.emit("\tstrb wzr, [x19, x0]");
+        self.emit("2:");
+        self.emit("\tmov x0, x19");
+        self.emit("\tldr x19, [sp, #16]");
+        self.emit("\tldp x29, x30, [sp], #32");
+        self.emit("\tret");
+    }
+
+    // -> x0 = the next line of stdin as an int, with int(s)'s rules
+    fn gen_read_int(&mut self) {
+        self.emit("__q_read_int:");
+        self.emit("\tstp x29, x30, [sp, #-16]!");
+        self.emit("\tmov x29, sp");
+        self.emit("\tbl __q_read_line");
+        self.emit("\tbl __q_str_to_int");
+        self.emit("\tldp x29, x30, [sp], #16");
+        self.emit("\tret");
+    }
+
+    // `maybe` draws from a xorshift64* generator bundled with the program
+    // instead of libc rand, so a seed gives the same flips on every libc.
+    // __q_seed_rng runs at startup: QUASAR_SEED wins, then the seed given at
+    // compile time, then the clock. the seed goes through one splitmix64
+    // step so that small or zero seeds still give a non-zero state
+    fn gen_rand(&mut self) {
+        self.emit("__q_seed_rng:");
+        self.emit("\tstp x29, x30, [sp, #-16]!");
+        self.emit("\tmov x29, sp");
+        self.emit("\tadrp x0, env_seed");
+        self.emit("\tadd x0, x0, :lo12:env_seed");
+        self.emit("\tbl getenv");
+        self.emit("\tcbz x0, 1f");
+        self.emit("\tmov x1, #0");
+        self.emit("\tmov x2, #10");
+        self.emit("\tbl strtoull");
+        self.emit("\tb 2f");
+        self.emit("1:");
+        match self.seed {
+            Some(seed) => self.emit(format!("\tldr x0, ={}", seed)),
+            None => {
+                self.emit("\tmov x0, #0");
+                self.emit("\tbl time");
+            }
+        }
+        self.emit("2:");
+        self.emit("\tldr x9, =0x9e3779b97f4a7c15");
+        self.emit("\tadd x0, x0, x9");
+        self.emit("\teor x0, x0, x0, lsr #30");
+        self.emit("\tldr x9, =0xbf58476d1ce4e5b9");
+        self.emit("\tmul x0, x0, x9");
+        self.emit("\teor x0, x0, x0, lsr #27");
+        self.emit("\tldr x9, =0x94d049bb133111eb");
+        self.emit("\tmul x0, x0, x9");
+        self.emit("\teor x0, x0, x0, lsr #31");
+        self.emit("\tcbnz x0, 3f");
+        self.emit("\tldr x0, =0x9e3779b97f4a7c15");
+        self.emit("3:");
+        self.emit("\tadrp x9, __q_rng_state");
+        self.emit("\tstr x0, [x9, :lo12:__q_rng_state]");
+        self.emit("\tldp x29, x30, [sp], #16");
+        self.emit("\tret");
+
+        // -> x0 = 0 or 1, the top bit of the next output
+        self.emit("__q_rand:");
+        self.emit("\tadrp x9, __q_rng_state");
+        self.emit("\tldr x10, [x9, :lo12:__q_rng_state]");
+        self.emit("\teor x10, x10, x10, lsr #12");
+        self.emit("\teor x10, x10, x10, lsl #25");
+        self.emit("\teor x10, x10, x10, lsr #27");
+        self.emit("\tstr x10, [x9, :lo12:__q_rng_state]");
+        self.emit("\tldr x11, =0x2545f4914f6cdd1d");
+        self.emit("\tmul x0, x10, x11");
+        self.emit("\tlsr x0, x0, #63");
+        self.emit("\tret");
+    }
+
+    // prompts for the unlock key until its hash matches, exiting through
+    // __q_pay_denied once the attempts run out or stdin ends. each attempt
+    // reads one line of at most 63 bytes with fgets; the rest of a longer
+    // line is discarded so it cannot spill into the next attempt
+    fn gen_paywall(&mut self) {
+        self.emit("__q_paywall:");
+        self.emit("\tstp x29, x30, [sp, #-96]!");
+        self.emit("\tmov x29, sp");
+        self.emit("\tstp x19, x20, [sp, #16]");
+        self.emit(format!("\tldr x19, ={}", self.paywall_retries));
+        self.emit("1:");
+        self.emit("\tadrp x0, msg_pay");
+        self.emit("\tadd x0, x0, :lo12:msg_pay");
+        self.emit("\tbl printf");
+        self.emit("\tmov x0, #0");
+        self.emit("\tbl fflush");
+        self.emit("\tadd x0, sp, #32");
+        self.emit("\tmov x1, #64");
+        self.emit("\tadrp x2, :got:stdin");
+        self.emit("\tldr x2, [x2, :got_lo12:stdin]");
+        self.emit("\tldr x2, [x2]");
+        self.emit("\tbl fgets");
+        self.emit("\tcbz x0, __q_pay_denied");
+        // FNV-1a over the line, up to its newline
+        self.emit("\tadd x9, sp, #32");
+        self.emit(format!("\tldr x20, ={:#x}", FNV_OFFSET_BASIS));
+        self.emit(format!("\tldr x11, ={:#x}", FNV_PRIME));
+        self.emit("2:");
+        self.emit("\tldrb w10, [x9], #1");
+        self.emit("\tcmp w10, #10"); // '\n'
+        self.emit("\tb.eq 4f");
+        self.emit("\tcbz w10, 3f");
+        self.emit("\teor x20, x20, x10");
+        self.emit("\tmul x20, x20, x11");
+        self.emit("\tb 2b");
+        // no newline: the line did not fit, drop the rest of it
+        self.emit("3:");
+        self.emit("\tbl getchar");
+        self.emit("\tcmp w0, #10");
+        self.emit("\tb.eq 4f");
+        self.emit("\tcmn w0, #1"); // EOF
+        self.emit("\tb.ne 3b");
+        self.emit("4:");
+        self.emit(format!("\tldr x9, ={:#x}", self.paywall_key_hash));
+        self.emit("\tcmp x20, x9");
+        self.emit("\tb.eq 5f");
+        self.emit("\tsubs x19, x19, #1");
+        self.emit("\tb.ne 1b");
+        self.emit("\tb __q_pay_denied");
+        self.emit("5:");
+        self.emit("\tldp x19, x20, [sp, #16]");
+        self.emit("\tldp x29, x30, [sp], #96");
+        self.emit("\tret");
+    }
+
+    // x0 = i -> x0 = argv[i], trapping unless 0 <= i < argc;
+    // __q_arg_count returns argc
+    fn gen_args(&mut self) {
+        self.emit("__q_args:");
+        self.emit("\tadrp x9, __q_argc");
+        self.emit("\tldrsw x9, [x9, :lo12:__q_argc]");
+        self.emit("\tcmp x0, x9");
+        self.emit("\tb.hs __q_arg_index");
+        self.emit("\tadrp x9, __q_argv");
+        self.emit("\tldr x9, [x9, :lo12:__q_argv]");
+        self.emit("\tldr x0, [x9, x0, lsl #3]");
+        self.emit("\tret");
+
+        self.emit("__q_arg_count:");
+        self.emit("\tadrp x0, __q_argc");
+        self.emit("\tldrsw x0, [x0, :lo12:__q_argc]");
+        self.emit("\tret");
+    }
+
+    // x0 = int -> x0 = its decimal text, in a 16-byte arena block
+    // (room for "-2147483648")
+    fn gen_int_to_str(&mut self) {
+        self.emit("__q_int_to_str:");
+        self.emit("\tstp x29, x30, [sp, #-32]!");
+        self.emit("\tmov x29, sp");
+        self.emit("\tstp x19, x20, [sp, #16]");
+        self.emit("\tmov x19, x0");
+        self.emit("\tmov x0, #16");
+        self.emit("\tbl __q_alloc");
+        self.emit("\tmov x20, x0");
+        self.emit("\tmov x1, #16");
+        self.emit("\tadrp x2, fmt_dec");
+        self.emit("\tadd x2, x2, :lo12:fmt_dec");
+        self.emit("\tmov x3, x19");
+        self.emit("\tbl snprintf");
+        self.emit("\tmov x0, x20");
+        self.emit("\tldp x19, x20, [sp, #16]");
+        self.emit("\tldp x29, x30, [sp], #32");
+        self.emit("\tret");
+    }
+
+    // x0 = string -> x0 = int; accepts an optional '-' and at least one digit,
+    // wrapping to 32 bits like the optimizer's folding, and traps otherwise
+    fn gen_str_to_int(&mut self) {
+        self.emit("__q_str_to_int:");
+        self.emit("\tmov x10, #0"); // accumulated value
+        self.emit("\tmov x11, #0"); // negative?
+        self.emit("\tldrb w12, [x0]");
+        self.emit("\tcmp w12, #45"); // '-'
+        self.emit("\tb.ne 1f");
+        self.emit("\tmov x11, #1");
+        self.emit("\tadd x0, x0, #1");
+        self.emit("\tldrb w12, [x0]");
+        self.emit("1:");
+        self.emit("\tcbz w12, __q_bad_int");
+        self.emit("2:");
+        self.emit("\tsub w12, w12, #48"); // '0'
+        self.emit("\tcmp w12, #9");
+        self.emit("\tb.hi __q_bad_int");
+        self.emit("\tmov x13, #10");
+        self.emit("\tmadd x10, x10, x13, x12");
+        self.emit("\tadd x0, x0, #1");
+        self.emit("\tldrb w12, [x0]");
+        self.emit("\tcbnz w12, 2b");
+        self.emit("\tcbz x11, 3f");
+        self.emit("\tneg x10, x10");
+        self.emit("3:");
+        self.emit("\tsxtw x0, w10");
+        self.emit("\tret");
+    }
+
+    fn gen_trap(&mut self, label: &str, msg: &str) {
+        self.emit(format!("{}:", label));
+        self.emit(format!("\tadrp x0, {}", msg));
+        self.emit(format!("\tadd x0, x0, :lo12:{}", msg));
+        self.emit("\tbl printf");
+        self.emit("\tmov x0, #1");
+        self.emit("\tbl exit");
+    }
+
+    // --- STATEMENT GENERATION ---
+
+    fn gen_stmt(&mut self, stmt: &Stmt) {
+        // no temporary outlives the statement that produced it
+        self.tmp_depth = 0;
+
+        match stmt {
+            // a const the optimizer could not inline is kept like a variable
+            Stmt::VarDeclaration { name, ty, value }
+            | Stmt::ConstDeclaration { name, ty, value } => {
+                let ty = self.layout.resolve(ty);
+                let r = self.gen_expr(value);
+                let offset = self.layout.declare_local(name, ty.clone());
+                self.store_var(&r, offset, &ty);
+            }
+
+            Stmt::GlobalDeclaration { name, ty, value } => {
+                let ty = self.layout.resolve(ty);
+                self.layout.declare_global(name, ty, value);
+            }
+
+            // strings print as text, bools as "true" / "false", the rest as ints
+            Stmt::Print(expr) => {
+                let ty = self.layout.expr_type(expr);
+                let r = self.gen_expr(expr);
+                let fmt = match ty {
+                    Type::String => "fmt_str",
+                    Type::Bool => {
+                        self.emit_bool_to_str(&r);
+                        "fmt_str"
+                    }
+                    _ => "fmt_int",
+                };
+                self.emit(format!("\tadrp x0, {}", fmt));
+                self.emit(format!("\tadd  x0, x0, :lo12:{}", fmt));
+                self.emit(format!("\tmov x1, {}", r));
+                self.emit("\tbl printf");
+                self.emit_count_print();
+            }
+
+            Stmt::PrintFormatted { format, args } => {
+                self.gen_print_formatted(format, args);
+                self.emit_count_print();
+            }
+
+            Stmt::Block(stmts) => self.gen_block(stmts),
+
+            Stmt::If { condition, then_block, else_block } => {
+                let cond_reg = self.gen_expr(condition);
+                let label_else = self.label("else");
+                let label_end = self.label("endif");
+
+                self.emit(format!("\tcmp {}, #0", cond_reg));
+                self.emit(format!("\tbeq {}", label_else));
+
+                self.gen_block(then_block);
+                self.emit(format!("\tb {}", label_end));
+
+                self.emit(format!("{}:", label_else));
+                if let Some(block) = else_block {
+                    self.gen_block(block);
+                }
+                self.emit(format!("{}:", label_end));
+            }
+
+            Stmt::ExprStmt(expr) => {
+                self.gen_expr(expr);
+            }
+
+            Stmt::StructDeclaration { name, fields } => self.layout.declare_struct(name, fields),
+
+            Stmt::EnumDeclaration { name, variants } => self.layout.declare_enum(name, variants),
+
+            Stmt::Match { scrutinee, arms } => self.gen_match(scrutinee, arms),
+
+            // libc exit runs atexit handlers and flushes stdio on the way out
+            Stmt::Exit(code) => {
+                let r = self.gen_expr(code);
+                self.emit(format!("\tmov x0, {}", r));
+                self.emit("\tbl exit");
+            }
+
+            Stmt::Return(code) => {
+                let r = self.gen_expr(code);
+                self.emit(format!("\tmov x0, {}", r));
+                self.emit("\tb __q_main_exit");
+            }
+
+            // the whole failure message is known at compile time, so it is a
+            // single pooled string printed through fmt_str
+            Stmt::Assert { condition, message, line } => {
+                let r = self.gen_expr(condition);
+                let label_ok = self.label("assert_ok");
+                self.emit(format!("\tcbnz {}, {}", r, label_ok));
+
+                let text = assert_message(&self.source_name, *line, message.as_deref());
+                let text = self.layout.string_label(&text);
+                self.emit("\tadrp x0, fmt_str");
+                self.emit("\tadd x0, x0, :lo12:fmt_str");
+                self.emit(format!("\tadrp x1, {}", text));
+                self.emit(format!("\tadd x1, x1, :lo12:{}", text));
+                self.emit("\tbl printf");
+                self.emit(format!("\tmov x0, #{}", ASSERT_FAILED_STATUS));
+                self.emit("\tbl exit");
+                self.emit(format!("{}:", label_ok));
+            }
+
+            // --- PAYWALL ---
+            // paywall(n) lets the program through until n prints have run
+            Stmt::Paywall(n) => {
+                let label_paid = self.label("paid");
+                self.emit("\tadrp x9, __q_print_count");
+                self.emit("\tldr x9, [x9, :lo12:__q_print_count]");
+                self.emit(format!("\tldr x10, ={}", n));
+                self.emit("\tcmp x9, x10");
+                self.emit(format!("\tb.lt {}", label_paid));
+                self.runtime.insert("paywall");
+                self.emit("\tbl __q_paywall");
+                self.emit(format!("{}:", label_paid));
+            }
+        }
+    }
+
+    // one printf call: each `{}` becomes the argument's conversion and the
+    // format string is pooled like any other literal. the first seven
+    // arguments go in x1-x7 and the rest on the stack, 8 bytes each, which is
+    // how AAPCS64 passes variadic arguments
+    fn gen_print_formatted(&mut self, format: &str, args: &[Expr]) {
+        let mut printf_format = String::new();
+        let mut pieces = format.split("{}");
+        printf_format.push_str(&pieces.next().unwrap_or_default().replace('%', "%%"));
+        for (arg, piece) in args.iter().zip(pieces) {
+            let spec = match self.layout.expr_type(arg) {
+                Type::String | Type::Bool => "%s",
+                _ => "%d",
+            };
+            printf_format.push_str(spec);
+            printf_format.push_str(&piece.replace('%', "%%"));
+        }
+        printf_format.push('\n');
+        let label = self.layout.string_label(&printf_format);
+
+        // evaluate into frame slots first: the calls an argument makes would
+        // clobber argument registers that were already filled
+        let base = self.layout.alloc_slot(args.len() * 8);
+        for (i, arg) in args.iter().enumerate() {
+            let is_bool = self.layout.expr_type(arg) == Type::Bool;
+            let r = self.gen_expr(arg);
+            if is_bool {
+                self.emit_bool_to_str(&r);
+            }
+            self.emit_mem("str", &r, "sp", base + i * 8);
+            self.tmp_depth = 0;
+        }
+
+        let spilled = args.len().saturating_sub(7);
+        let area = (spilled * 8).div_ceil(16) * 16;
+        if area > 0 {
+            self.out.push_str(&sp_adjust("sub", area));
+        }
+        for i in 7..args.len() {
+            self.emit_mem("ldr", "x9", "sp", area + base + i * 8);
+            self.emit_mem("str", "x9", "sp", (i - 7) * 8);
+        }
+        for i in 0..args.len().min(7) {
+            self.emit_mem("ldr", &format!("x{}", i + 1), "sp", area + base + i * 8);
+        }
+
+        self.emit(format!("\tadrp x0, {}", label));
+        self.emit(format!("\tadd x0, x0, :lo12:{}", label));
+        self.emit("\tbl printf");
+        if area > 0 {
+            self.out.push_str(&sp_adjust("add", area));
+        }
+    }
+
+    fn gen_match(&mut self, scrutinee: &Expr, arms: &[MatchArm]) {
+        let enum_name = match self.layout.expr_type(scrutinee) {
+            Type::Enum(name) => name,
+            _ => unreachable!("semantic analysis guarantees an enum scrutinee"),
+        };
+        let variants = self.layout.variants(&enum_name).to_vec();
+        let r = self.gen_expr(scrutinee);
+
+        let label_end = self.label("endmatch");
+        let arm_labels: Vec<String> = arms.iter().map(|_| self.label("arm")).collect();
+
+        // the arm taken for each variant is the first one naming it, unless
+        // a `_` arm comes first and takes it
+        let explicit_arm = |variant: &str| {
+            arms.iter()
+                .position(|a| match &a.pattern {
+                    MatchPattern::Variant(v) => v == variant,
+                    MatchPattern::Wildcard => true,
+                })
+                .filter(|&i| arms[i].pattern != MatchPattern::Wildcard)
+        };
+        let wildcard = arms.iter().position(|a| a.pattern == MatchPattern::Wildcard);
+        let fallback = wildcard.map_or(label_end.clone(), |i| arm_labels[i].clone());
+
+        let explicit = arms.iter()
+            .filter(|a| matches!(a.pattern, MatchPattern::Variant(_)))
+            .count();
+
+        if explicit >= JUMP_TABLE_MIN_ARMS && explicit * 2 >= variants.len() {
+            // table of 32-bit offsets relative to the table itself
+            let table = self.label("jumptable");
+            self.emit(format!("\tadr x9, {}", table));
+            self.emit(format!("\tldrsw x10, [x9, {}, lsl #2]", r));
+            self.emit("\tadd x9, x9, x10");
+            self.emit("\tbr x9");
+            self.emit(format!("{}:", table));
+            for v in &variants {
+                let target = explicit_arm(v).map_or(&fallback, |i| &arm_labels[i]);
+                self.emit(format!("\t.word {} - {}", target, table));
+            }
+        } else {
+            for (i, v) in variants.iter().enumerate() {
+                if let Some(arm) = explicit_arm(v) {
+                    self.emit(format!("\tcmp {}, #{}", r, i));
+                    self.emit(format!("\tbeq {}", arm_labels[arm]));
+                }
+            }
+            self.emit(format!("\tb {}", fallback));
+        }
+
+        for (arm, label) in arms.iter().zip(&arm_labels) {
+            self.emit(format!("{}:", label));
+            self.gen_block(&arm.body);
+            self.emit(format!("\tb {}", label_end));
+        }
+        self.emit(format!("{}:", label_end));
+    }
+
+    // --- EXPRESSION GENERATION ---
+
+    fn gen_expr(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::IntegerLiteral(n) => {
+                let r = self.alloc_tmp();
+                self.emit(format!("\tldr {}, ={}", r, n));
+                r
+            }
+            Expr::StringLiteral(lit) => {
+                let r = self.alloc_tmp();
+                let label = self.layout.string_label(lit);
+                self.emit(format!("\tadrp {}, {}", r, label));
+                self.emit(format!("\tadd {}, {}, :lo12:{}", r, r, label));
+                r
+            }
+            Expr::BooleanLiteral(b) => {
+                let r = self.alloc_tmp();
+                self.emit(format!("\tmov {}, #{}", r, *b as i32));
+                r
+            }
+            Expr::Identifier(name) => {
+                let r = self.alloc_tmp();
+                match self.layout.var(name) {
+                    Some(Var { slot: Slot::Global(label), .. }) => {
+                        let label = label.clone();
+                        self.emit(format!("\tadrp {}, {}", r, label));
+                        self.emit(format!("\tldr {}, [{}, :lo12:{}]", r, r, label));
+                    }
+                    Some(Var { slot: Slot::Stack(offset), ty: Type::Array(..) | Type::Struct(_) }) => {
+                        // aggregates evaluate to the address of their first word
+                        let offset = *offset;
+                        self.emit_slot_addr(&r, offset);
+                    }
+                    Some(Var { slot: Slot::Stack(offset), .. }) => {
+                        let offset = *offset;
+                        self.emit_mem("ldr", &r, "sp", offset);
+                    }
+                    None => self.emit(format!("\tldr {}, [sp, #0]", r)),
+                }
+                r
+            }
+            Expr::Assign { name, value } => {
+                let r = self.gen_expr(value);
+                match self.layout.var(name) {
+                    Some(Var { slot: Slot::Global(label), .. }) => {
+                        let label = label.clone();
+                        self.emit(format!("\tadrp x9, {}", label));
+                        self.emit(format!("\tstr {}, [x9, :lo12:{}]", r, label));
+                    }
+                    Some(Var { slot: Slot::Stack(offset), ty }) => {
+                        let (offset, ty) = (*offset, ty.clone());
+                        self.store_var(&r, offset, &ty);
+                    }
+                    None => {}
+                }
+                r
+            }
+            Expr::ArrayLiteral(elements) => {
+                let mark = self.tmp_depth;
+                let base = self.layout.alloc_slot(elements.len() * 8);
+                for (i, e) in elements.iter().enumerate() {
+                    let r = self.gen_expr(e);
+                    self.emit_mem("str", &r, "sp", base + i * 8);
+                    self.tmp_depth = mark;
+                }
+                let r = self.alloc_tmp();
+                self.emit_slot_addr(&r, base);
+                r
+            }
+            Expr::EnumVariant { enum_name, variant } => {
+                let r = self.alloc_tmp();
+                let index = self.layout.variant_index(enum_name, variant);
+                self.emit(format!("\tmov {}, #{}", r, index));
+                r
+            }
+            Expr::StructLiteral { name, fields } => {
+                let mark = self.tmp_depth;
+                let size = self.layout.slot_size(&Type::Struct(name.clone()));
+                let base = self.layout.alloc_slot(size);
+                for (field, e) in fields {
+                    let r = self.gen_expr(e);
+                    let (field_offset, _) = self.layout.field(name, field);
+                    self.emit_mem("str", &r, "sp", base + field_offset);
+                    self.tmp_depth = mark;
+                }
+                let r = self.alloc_tmp();
+                self.emit_slot_addr(&r, base);
+                r
+            }
+            Expr::Field { name, field } => {
+                let r = self.alloc_tmp();
+                let offset = self.layout.field_slot(name, field);
+                self.emit_mem("ldr", &r, "sp", offset);
+                r
+            }
+            Expr::FieldAssign { name, field, value } => {
+                let r = self.gen_expr(value);
+                let offset = self.layout.field_slot(name, field);
+                self.emit_mem("str", &r, "sp", offset);
+                r
+            }
+            Expr::Index { name, index } => {
+                let mark = self.tmp_depth;
+                let (offset, len) = self.layout.array_var(name);
+                let r = self.gen_expr(index);
+                self.emit_bounds_check(&r, len);
+                self.emit_slot_addr("x9", offset);
+                self.emit(format!("\tldr {}, [x9, {}, lsl #3]", r, r));
+                self.tmp_depth = mark + 1;
+                r
+            }
+            Expr::IndexAssign { name, index, value } => {
+                let mark = self.tmp_depth;
+                let (offset, len) = self.layout.array_var(name);
+                let r = self.gen_expr(index);
+                let v = self.gen_operand(&r, value);
+                self.emit_bounds_check(&r, len);
+                self.emit_slot_addr("x9", offset);
+                self.emit(format!("\tstr {}, [x9, {}, lsl #3]", v, r));
+                self.emit(format!("\tmov {}, {}", r, v));
+                self.tmp_depth = mark + 1;
+                r
+            }
+            Expr::Unary { op, operand } => {
+                let r = self.gen_expr(operand);
+                match op {
+                    UnOp::Neg => {
+                        self.emit(format!("\tneg {}, {}", r, r));
+                        self.emit_wrap(&r);
+                    }
+                    UnOp::Not => self.emit(format!("\teor {}, {}, #1", r, r)),
+                }
+                r
+            }
+            Expr::Binary { left, op: op @ (BinOp::And | BinOp::Or), right } => {
+                // the right operand is only evaluated (and a `maybe` there
+                // only sampled) when the left one does not decide the result
+                let mark = self.tmp_depth;
+                let dest = self.gen_expr(left);
+                let label_end = self.label("logic_end");
+
+                let branch = if *op == BinOp::And { "cbz" } else { "cbnz" };
+                self.emit(format!("\t{} {}, {}", branch, dest, label_end));
+
+                self.release_if_full(mark);
+                let r = self.gen_expr(right);
+                if r != dest {
+                    self.emit(format!("\tmov {}, {}", dest, r));
+                }
+                self.emit(format!("{}:", label_end));
+
+                self.tmp_depth = mark + 1;
+                dest
+            }
+            Expr::Binary { left, op, right } => {
+                let mark = self.tmp_depth;
+                let operand_ty = self.layout.expr_type(left);
+                let r1 = self.gen_expr(left);
+                let r2 = self.gen_operand(&r1, right);
+                let dest = r1.clone();
+
+                match op {
+                    BinOp::Add if operand_ty == Type::String => {
+                        self.runtime.insert("concat");
+                        self.runtime.insert("alloc");
+                        self.emit(format!("\tmov x0, {}", r1));
+                        self.emit(format!("\tmov x1, {}", r2));
+                        self.emit("\tbl __q_concat");
+                        self.emit(format!("\tmov {}, x0", dest));
+                    }
+                    BinOp::Add => {
+                        self.emit(format!("\tadd {}, {}, {}", dest, r1, r2));
+                        self.emit_wrap(&dest);
+                    }
+                    BinOp::Sub => {
+                        self.emit(format!("\tsub {}, {}, {}", dest, r1, r2));
+                        self.emit_wrap(&dest);
+                    }
+                    BinOp::Mul => {
+                        self.emit(format!("\tmul {}, {}, {}", dest, r1, r2));
+                        self.emit_wrap(&dest);
+                    }
+                    BinOp::Div => {
+                        self.runtime.insert("div_zero");
+                        self.emit(format!("\tcbz {}, __q_div_zero", r2));
+                        self.emit(format!("\tsdiv {}, {}, {}", dest, r1, r2));
+                        self.emit_wrap(&dest);
+                    }
+                    BinOp::Mod => {
+                        // a % b == a - (a / b) * b, quotient kept in x9
+                        self.runtime.insert("div_zero");
+                        self.emit(format!("\tcbz {}, __q_div_zero", r2));
+                        self.emit(format!("\tsdiv x9, {}, {}", r1, r2));
+                        self.emit(format!("\tmsub {}, x9, {}, {}", dest, r2, r1));
+                    }
+                    BinOp::GreaterThan
+                    | BinOp::LessThan
+                    | BinOp::GreaterEqual
+                    | BinOp::LessEqual
+                    | BinOp::Equal
+                    | BinOp::NotEqual => {
+                        let cond = match op {
+                            BinOp::GreaterThan => "gt",
+                            BinOp::LessThan => "lt",
+                            BinOp::GreaterEqual => "ge",
+                            BinOp::LessEqual => "le",
+                            BinOp::Equal => "eq",
+                            _ => "ne",
+                        };
+                        if operand_ty == Type::String {
+                            // strings compare by content: strcmp(a, b) <=> 0
+                            self.emit(format!("\tmov x0, {}", r1));
+                            self.emit(format!("\tmov x1, {}", r2));
+                            self.emit("\tbl strcmp");
+                            self.emit("\tcmp w0, #0");
+                        } else {
+                            self.emit(format!("\tcmp {}, {}", r1, r2));
+                        }
+                        self.emit(format!("\tcset {}, {}", dest, cond));
+                    }
+                    BinOp::And | BinOp::Or => unreachable!("lowered with branches above"),
+                }
+                self.tmp_depth = mark + 1;
+                dest
+            }
+            Expr::Conditional { condition, then_expr, else_expr } => {
+                let mark = self.tmp_depth;
+                let dest = self.gen_expr(condition);
+
+                // the arms are evaluated while dest and a still hold values,
+                // so there must be a register left for each to start in
+                let room = self.tmp_depth + 2 <= TMP_REGS.len();
+                if room && is_pure(then_expr) && is_pure(else_expr) {
+                    // both arms are cheap to evaluate and cannot be observed,
+                    // so compute them both and pick one without branching
+                    let a = self.gen_expr(then_expr);
+                    let b = self.gen_expr(else_expr);
+                    self.emit(format!("\tcmp {}, #0", dest));
+                    self.emit(format!("\tcsel {}, {}, {}, ne", dest, a, b));
+                } else {
+                    let label_else = self.label("cond_else");
+                    let label_end = self.label("cond_end");
+                    self.emit(format!("\tcbz {}, {}", dest, label_else));
+
+                    self.release_if_full(mark);
+                    let a = self.gen_expr(then_expr);
+                    if a != dest {
+                        self.emit(format!("\tmov {}, {}", dest, a));
+                    }
+                    self.emit(format!("\tb {}", label_end));
+
+                    self.tmp_depth = mark + 1;
+                    self.emit(format!("{}:", label_else));
+                    self.release_if_full(mark);
+                    let b = self.gen_expr(else_expr);
+                    if b != dest {
+                        self.emit(format!("\tmov {}, {}", dest, b));
+                    }
+                    self.emit(format!("{}:", label_end));
+                }
+
+                self.tmp_depth = mark + 1;
+                dest
+            }
+            Expr::Conversion { target, operand } => {
+                let from = self.layout.expr_type(operand);
+                let r = self.gen_expr(operand);
+                match (target, from) {
+                    (TypeName::Int, Type::String) => {
+                        self.runtime.insert("str_to_int");
+                        self.emit(format!("\tmov x0, {}", r));
+                        self.emit("\tbl __q_str_to_int");
+                        self.emit(format!("\tmov {}, x0", r));
+                    }
+                    (TypeName::Bool, Type::Int) => {
+                        self.emit(format!("\tcmp {}, #0", r));
+                        self.emit(format!("\tcset {}, ne", r));
+                    }
+                    (TypeName::String, Type::Int) => {
+                        self.runtime.insert("int_to_str");
+                        self.runtime.insert("alloc");
+                        self.emit(format!("\tmov x0, {}", r));
+                        self.emit("\tbl __q_int_to_str");
+                        self.emit(format!("\tmov {}, x0", r));
+                    }
+                    (TypeName::String, Type::Bool) => self.emit_bool_to_str(&r),
+                    // same type, or bool -> int where 0 / 1 is already the value
+                    _ => {}
+                }
+                r
+            }
+            Expr::Call { name, args } => {
+                // arguments are evaluated into temporaries first, since
+                // evaluating one may call out and clobber x0-x7
+                let mark = self.tmp_depth;
+                if mark + args.len() <= TMP_REGS.len() {
+                    let regs: Vec<String> = args.iter().map(|a| self.gen_expr(a)).collect();
+                    for (i, r) in regs.iter().enumerate() {
+                        self.emit(format!("\tmov x{}, {}", i, r));
+                    }
+                } else {
+                    // too few temporaries left to hold them all: they wait in
+                    // the frame instead
+                    let base = self.layout.alloc_slot(args.len() * 8);
+                    for (i, a) in args.iter().enumerate() {
+                        let r = self.gen_expr(a);
+                        self.emit_mem("str", &r, "sp", base + i * 8);
+                        self.tmp_depth = mark;
+                    }
+                    for i in 0..args.len() {
+                        self.emit_mem("ldr", &format!("x{}", i), "sp", base + i * 8);
+                    }
+                }
+
+                // the routine each builtin calls and the runtime support it needs
+                let (target, needs): (&str, &[&'static str]) = match name.as_str() {
+                    "len" => ("strlen", &[]),
+                    "substr" => ("__q_substr", &["substr", "alloc"]),
+                    "index_of" => ("__q_index_of", &["index_of"]),
+                    "to_upper" => ("__q_to_upper", &["to_upper", "alloc"]),
+                    "char_at" => ("__q_char_at", &["char_at", "alloc"]),
+                    "read_line" => ("__q_read_line", &["read_line", "alloc"]),
+                    "read_int" => ("__q_read_int", &["read_int", "read_line", "str_to_int", "alloc"]),
+                    "args" => ("__q_args", &["args"]),
+                    "arg_count" => ("__q_arg_count", &["args"]),
+                    builtin => unreachable!("semantic analysis guarantees '{}' is a builtin", builtin),
+                };
+                self.runtime.extend(needs);
+                self.emit(format!("\tbl {}", target));
+
+                self.tmp_depth = mark;
+                let r = self.alloc_tmp();
+                self.emit(format!("\tmov {}, x0", r));
+                r
+            }
+            Expr::Maybe => {
+                self.runtime.insert("rand");
+                let r = self.alloc_tmp();
+                self.emit("\tbl __q_rand");
+                self.emit(format!("\tmov {}, x0", r));
+                r
+            }
+        }
+    }
+
+    // --- VARIABLES ---
+
+    fn gen_block(&mut self, stmts: &[Stmt]) {
+        self.layout.enter_scope();
+        for s in stmts {
+            self.gen_stmt(s);
+        }
+        self.layout.exit_scope();
+    }
+
+    // scalars are stored directly; for arrays and structs `r` holds the
+    // address of the source words, which are copied into the slot one by one
+    fn store_var(&mut self, r: &str, offset: usize, ty: &Type) {
+        if let Type::Array(..) | Type::Struct(_) = ty {
+            for i in 0..self.layout.slot_size(ty) / 8 {
+                self.emit_mem("ldr", "x9", r, i * 8);
+                self.emit_mem("str", "x9", "sp", offset + i * 8);
+            }
+        } else {
+            self.emit_mem("str", r, "sp", offset);
+        }
+    }
+
+    // a 64-bit load or store at base + offset. the instruction only encodes
+    // a scaled offset up to 4095 * 8, so a larger one goes through x16
+    fn emit_mem(&mut self, op: &str, r: &str, base: &str, offset: usize) {
+        if offset <= 4095 * 8 {
+            self.emit(format!("\t{} {}, [{}, #{}]", op, r, base, offset));
+        } else {
+            self.emit(format!("\tldr x16, ={}", offset));
+            self.emit(format!("\t{} {}, [{}, x16]", op, r, base));
+        }
+    }
+
+    fn emit_slot_addr(&mut self, r: &str, offset: usize) {
+        if offset < 4096 {
+            self.emit(format!("\tadd {}, sp, #{}", r, offset));
+        } else {
+            self.emit(format!("\tldr {}, ={}", r, offset));
+            self.emit(format!("\tadd {}, sp, {}", r, r));
+        }
+    }
+
+    // unsigned compare, so a negative index fails the check as well
+    fn emit_bounds_check(&mut self, index: &str, len: usize) {
+        self.runtime.insert("bounds");
+        self.emit(format!("\tldr x9, ={}", len));
+        self.emit(format!("\tcmp {}, x9", index));
+        self.emit("\tb.hs __q_bounds");
+    }
+
+    // replaces the bool in `r` with the address of "true" or "false"
+    fn emit_bool_to_str(&mut self, r: &str) {
+        let t = self.layout.string_label("true");
+        let f = self.layout.string_label("false");
+        self.emit(format!("\tadrp x9, {}", t));
+        self.emit(format!("\tadd x9, x9, :lo12:{}", t));
+        self.emit(format!("\tadrp x10, {}", f));
+        self.emit(format!("\tadd x10, x10, :lo12:{}", f));
+        self.emit(format!("\tcmp {}, #0", r));
+        self.emit(format!("\tcsel {}, x9, x10, ne", r));
+    }
+
+    fn emit_count_print(&mut self) {
+        if !self.metered {
+            return;
+        }
+        self.emit("\tadrp x9, __q_print_count");
+        self.emit("\tldr x10, [x9, :lo12:__q_print_count]");
+        self.emit("\tadd x10, x10, #1");
+        self.emit("\tstr x10, [x9, :lo12:__q_print_count]");
+    }
+
+    fn alloc_tmp(&mut self) -> String {
+        let r = TMP_REGS
+            .get(self.tmp_depth)
+            .expect("operands are spilled before the temporary pool runs out");
+        self.tmp_depth += 1;
+        r.to_string()
+    }
+
+    // evaluates `expr` while `live`, the newest temporary, still holds a
+    // value. when `live` took the last register, it waits in the frame so
+    // `expr` can start in its register, and the result is handed back in
+    // SPILL_REG for the caller's next instruction
+    fn gen_operand(&mut self, live: &str, expr: &Expr) -> String {
+        if self.tmp_depth < TMP_REGS.len() {
+            return self.gen_expr(expr);
+        }
+        let slot = self.layout.alloc_slot(8);
+        self.emit_mem("str", live, "sp", slot);
+        self.tmp_depth -= 1;
+        let r = self.gen_expr(expr);
+        self.emit(format!("\tmov {}, {}", SPILL_REG, r));
+        self.emit_mem("ldr", live, "sp", slot);
+        SPILL_REG.to_string()
+    }
+
+    // after a branch on the temporary at `mark`, the taken path overwrites
+    // it anyway; with the pool full it is handed back so that path has a
+    // register to start in
+    fn release_if_full(&mut self, mark: usize) {
+        if self.tmp_depth == TMP_REGS.len() {
+            self.tmp_depth = mark;
+        }
+    }
+
+    // ints are 32-bit: sign-extend the low word so overflow wraps the same
+    // way the optimizer's constant folding does
+    fn emit_wrap(&mut self, r: &str) {
+        self.emit(format!("\tsxtw {}, w{}", r, &r[1..]));
+    }
+
+    fn label(&mut self, prefix: &str) -> String {
+        let l = format!("{}_{}", prefix, self.label_counter);
+        self.label_counter += 1;
+        l
+    }
+
+    fn emit(&mut self, asm: impl Into<String>) {
+        self.out.push_str(&asm.into());
+        self.out.push('\n');
+    }
+}
+
+fn sp_adjust(op: &str, size: usize) -> String {
+    if size < 4096 {
+        format!("\t{} sp, sp, #{}\n", op, size)
+    } else {
+        format!("\tldr x9, ={}\n\t{} sp, sp, x9\n", size, op)
+    }
+}
@@ -0,0 +1,1149 @@
+use std::collections::HashMap;
+use crate::parsing::ast::{Expr, Stmt, BinOp, UnOp, TypeName, MatchPattern};
+use crate::semantic::semantic::{Type, TypeEnv, builtin_signature, expr_type, resolve_type_name};
+
+// "QBC" and the format version, at the start of every bytecode file
+const MAGIC: &[u8; 3] = b"QBC";
+const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Const {
+    Int(i32),
+    Bool(bool),
+    String(String),
+    Enum {
+        enum_name: String,
+        index: u32,
+    },
+}
+
+// Instructions for a stack machine. Operands are read from the stack and
+// the result pushed back; u32 operands are slot numbers, constant indices
+// or jump targets (instruction indices).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    Push(u32),
+    Load(u32),
+    Store(u32),
+    // the index is on the stack, below the value for StoreIndex
+    LoadIndex(u32),
+    StoreIndex(u32),
+    // slot and the constant naming the field
+    LoadField(u32, u32),
+    StoreField(u32, u32),
+    MakeArray(u32),
+    // constants naming the fields, in the order their values were pushed
+    MakeStruct(Vec<u32>),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Neg,
+    Not,
+    Jump(u32),
+    // pop a bool and jump when it is false / true
+    Jz(u32),
+    Jnz(u32),
+    Dup,
+    Pop,
+    Maybe,
+    ToInt,
+    ToBool,
+    ToString,
+    // constant naming the builtin, and its argument count
+    Call(u32, u32),
+    Print,
+    // format string constant and argument count
+    PrintFormatted(u32, u32),
+    Paywall(i64),
+    // pops the condition; the message constant is optional
+    Assert {
+        line: u32,
+        message: Option<u32>,
+    },
+    // pops the status
+    Exit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    // source file named in assertion failure messages
+    pub source_name: String,
+    pub slots: u32,
+    pub constants: Vec<Const>,
+    pub code: Vec<Instr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BytecodeError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    BadConstantTag(u8),
+    BadOpcode(u8),
+    InvalidString,
+    // the header asks for more slots than the code could use: every slot
+    // the compiler allocates is named by at least one instruction
+    BadSlotCount(u32),
+    // the instruction at this index names a slot, constant or jump target
+    // that is out of range
+    BadOperand(usize),
+    // the instruction at this index would underflow the stack, get a value
+    // of the wrong kind, call something that is not a builtin, or jump
+    // backwards or to a point the stack reaches in different shapes
+    IllTyped(usize),
+}
+
+// Compiles a checked, optimized program to bytecode. Every variable gets its
+// own slot, so blocks need no frames; a slot is never reused.
+pub struct BytecodeCompiler {
+    source_name: String,
+    // each variable's slot and declared type
+    scopes: Vec<HashMap<String, (u32, TypeName)>>,
+    structs: HashMap<String, Vec<(String, TypeName)>>,
+    enums: HashMap<String, Vec<String>>,
+    slots: u32,
+    constants: Vec<Const>,
+    code: Vec<Instr>,
+}
+
+impl Default for BytecodeCompiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BytecodeCompiler {
+    pub fn new() -> Self {
+        Self {
+            source_name: "<input>".to_string(),
+            scopes: vec![HashMap::new()],
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            slots: 0,
+            constants: Vec::new(),
+            code: Vec::new(),
+        }
+    }
+
+    pub fn with_source_name(mut self, name: &str) -> Self {
+        self.source_name = name.to_string();
+        self
+    }
+
+    pub fn compile(mut self, stmts: &[Stmt]) -> Program {
+        for stmt in stmts {
+            self.compile_stmt(stmt);
+        }
+        Program {
+            source_name: self.source_name,
+            slots: self.slots,
+            constants: self.constants,
+            code: self.code,
+        }
+    }
+
+    // -------- STATEMENTS --------
+
+    fn compile_block(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.compile_stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn compile_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Print(expr) => {
+                self.compile_expr(expr);
+                self.code.push(Instr::Print);
+            }
+
+            Stmt::PrintFormatted { format, args } => {
+                for arg in args {
+                    self.compile_expr(arg);
+                }
+                let format = self.constant(Const::String(format.clone()));
+                self.code.push(Instr::PrintFormatted(format, args.len() as u32));
+            }
+
+            // the value is computed before the name is in scope, so it can
+            // still read an outer variable of the same name
+            Stmt::VarDeclaration { name, ty, value }
+            | Stmt::ConstDeclaration { name, ty, value }
+            | Stmt::GlobalDeclaration { name, ty, value } => {
+                self.compile_expr(value);
+                let slot = self.alloc_slot();
+                self.scopes.last_mut().unwrap().insert(name.clone(), (slot, ty.clone()));
+                self.code.push(Instr::Store(slot));
+            }
+
+            Stmt::If { condition, then_block, else_block } => {
+                self.compile_expr(condition);
+                let to_else = self.emit_jump(Instr::Jz(0));
+                self.compile_block(then_block);
+                match else_block {
+                    Some(else_block) => {
+                        let to_end = self.emit_jump(Instr::Jump(0));
+                        self.patch(to_else);
+                        self.compile_block(else_block);
+                        self.patch(to_end);
+                    }
+                    None => self.patch(to_else),
+                }
+            }
+
+            Stmt::Block(stmts) => self.compile_block(stmts),
+
+            Stmt::ExprStmt(Expr::Assign { name, value }) => {
+                self.compile_expr(value);
+                let slot = self.slot(name);
+                self.code.push(Instr::Store(slot));
+            }
+
+            Stmt::ExprStmt(Expr::IndexAssign { name, index, value }) => {
+                self.compile_expr(index);
+                self.compile_expr(value);
+                let slot = self.slot(name);
+                self.code.push(Instr::StoreIndex(slot));
+            }
+
+            Stmt::ExprStmt(Expr::FieldAssign { name, field, value }) => {
+                self.compile_expr(value);
+                let slot = self.slot(name);
+                let field = self.constant(Const::String(field.clone()));
+                self.code.push(Instr::StoreField(slot, field));
+            }
+
+            Stmt::ExprStmt(expr) => {
+                self.compile_expr(expr);
+                self.code.push(Instr::Pop);
+            }
+
+            Stmt::StructDeclaration { name, fields } => {
+                self.structs.insert(name.clone(), fields.clone());
+            }
+
+            Stmt::EnumDeclaration { name, variants } => {
+                self.enums.insert(name.clone(), variants.clone());
+            }
+
+            // the scrutinee goes in a hidden slot and each arm compares
+            // against it in turn; a `_` arm ends the chain
+            Stmt::Match { scrutinee, arms } => {
+                self.compile_expr(scrutinee);
+                let scrutinee_slot = self.alloc_slot();
+                self.code.push(Instr::Store(scrutinee_slot));
+
+                let Some(Type::Enum(enum_name)) = expr_type(scrutinee, self) else {
+                    unreachable!("semantic analysis guarantees an enum scrutinee")
+                };
+                let mut to_end = Vec::new();
+                for arm in arms {
+                    match &arm.pattern {
+                        MatchPattern::Wildcard => {
+                            self.compile_block(&arm.body);
+                            break;
+                        }
+                        MatchPattern::Variant(variant) => {
+                            self.code.push(Instr::Load(scrutinee_slot));
+                            let variant = self.enum_const(&enum_name, variant);
+                            self.code.push(Instr::Push(variant));
+                            self.code.push(Instr::Eq);
+                            let to_next = self.emit_jump(Instr::Jz(0));
+                            self.compile_block(&arm.body);
+                            to_end.push(self.emit_jump(Instr::Jump(0)));
+                            self.patch(to_next);
+                        }
+                    }
+                }
+                for jump in to_end {
+                    self.patch(jump);
+                }
+            }
+
+            Stmt::Paywall(n) => self.code.push(Instr::Paywall(*n)),
+
+            Stmt::Exit(code) | Stmt::Return(code) => {
+                self.compile_expr(code);
+                self.code.push(Instr::Exit);
+            }
+
+            Stmt::Assert { condition, message, line } => {
+                self.compile_expr(condition);
+                let message = message.as_ref().map(|m| self.constant(Const::String(m.clone())));
+                self.code.push(Instr::Assert { line: *line as u32, message });
+            }
+        }
+    }
+
+    // -------- EXPRESSIONS --------
+
+    fn compile_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::IntegerLiteral(n) => self.push_const(Const::Int(*n)),
+            Expr::StringLiteral(s) => self.push_const(Const::String(s.clone())),
+            Expr::BooleanLiteral(b) => self.push_const(Const::Bool(*b)),
+            Expr::Identifier(name) => {
+                let slot = self.slot(name);
+                self.code.push(Instr::Load(slot));
+            }
+            Expr::Maybe => self.code.push(Instr::Maybe),
+
+            Expr::Unary { op, operand } => {
+                self.compile_expr(operand);
+                self.code.push(match op {
+                    UnOp::Neg => Instr::Neg,
+                    UnOp::Not => Instr::Not,
+                });
+            }
+
+            // the left value stays on the stack when it decides the result
+            Expr::Binary { left, op: op @ (BinOp::And | BinOp::Or), right } => {
+                self.compile_expr(left);
+                self.code.push(Instr::Dup);
+                let to_end = match op {
+                    BinOp::And => self.emit_jump(Instr::Jz(0)),
+                    _ => self.emit_jump(Instr::Jnz(0)),
+                };
+                self.code.push(Instr::Pop);
+                self.compile_expr(right);
+                self.patch(to_end);
+            }
+
+            Expr::Binary { left, op, right } => {
+                self.compile_expr(left);
+                self.compile_expr(right);
+                self.code.push(match op {
+                    BinOp::Add => Instr::Add,
+                    BinOp::Sub => Instr::Sub,
+                    BinOp::Mul => Instr::Mul,
+                    BinOp::Div => Instr::Div,
+                    BinOp::Mod => Instr::Mod,
+                    BinOp::Equal => Instr::Eq,
+                    BinOp::NotEqual => Instr::Ne,
+                    BinOp::LessThan => Instr::Lt,
+                    BinOp::GreaterThan => Instr::Gt,
+                    BinOp::LessEqual => Instr::Le,
+                    BinOp::GreaterEqual => Instr::Ge,
+                    BinOp::And | BinOp::Or => unreachable!("logical operators short-circuit above"),
+                });
+            }
+
+            Expr::ArrayLiteral(elements) => {
+                for element in elements {
+                    self.compile_expr(element);
+                }
+                self.code.push(Instr::MakeArray(elements.len() as u32));
+            }
+
+            Expr::Index { name, index } => {
+                self.compile_expr(index);
+                let slot = self.slot(name);
+                self.code.push(Instr::LoadIndex(slot));
+            }
+
+            Expr::StructLiteral { fields, .. } => {
+                let mut names = Vec::new();
+                for (field, value) in fields {
+                    self.compile_expr(value);
+                    names.push(self.constant(Const::String(field.clone())));
+                }
+                self.code.push(Instr::MakeStruct(names));
+            }
+
+            Expr::Field { name, field } => {
+                let slot = self.slot(name);
+                let field = self.constant(Const::String(field.clone()));
+                self.code.push(Instr::LoadField(slot, field));
+            }
+
+            Expr::EnumVariant { enum_name, variant } => {
+                let variant = self.enum_const(enum_name, variant);
+                self.code.push(Instr::Push(variant));
+            }
+
+            Expr::Conditional { condition, then_expr, else_expr } => {
+                self.compile_expr(condition);
+                let to_else = self.emit_jump(Instr::Jz(0));
+                self.compile_expr(then_expr);
+                let to_end = self.emit_jump(Instr::Jump(0));
+                self.patch(to_else);
+                self.compile_expr(else_expr);
+                self.patch(to_end);
+            }
+
+            Expr::Conversion { target, operand } => {
+                self.compile_expr(operand);
+                self.code.push(match target {
+                    TypeName::Int => Instr::ToInt,
+                    TypeName::Bool => Instr::ToBool,
+                    TypeName::String => Instr::ToString,
+                    _ => unreachable!("semantic analysis guarantees a scalar conversion"),
+                });
+            }
+
+            Expr::Call { name, args } => {
+                for arg in args {
+                    self.compile_expr(arg);
+                }
+                let name = self.constant(Const::String(name.clone()));
+                self.code.push(Instr::Call(name, args.len() as u32));
+            }
+
+            Expr::Assign { .. } | Expr::IndexAssign { .. } | Expr::FieldAssign { .. } => {
+                unreachable!("assignments are statements")
+            }
+        }
+    }
+
+    // -------- HELPERS --------
+
+    fn alloc_slot(&mut self) -> u32 {
+        self.slots += 1;
+        self.slots - 1
+    }
+
+    fn var(&self, name: &str) -> Option<&(u32, TypeName)> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    // like the arm64 backend, a name semantic analysis rejected still
+    // compiles: it gets a global slot of its own, which starts out as 0
+    fn slot(&mut self, name: &str) -> u32 {
+        if let Some((slot, _)) = self.var(name) {
+            return *slot;
+        }
+        let slot = self.alloc_slot();
+        self.scopes[0].insert(name.to_string(), (slot, TypeName::Int));
+        slot
+    }
+
+    // constants are deduplicated
+    fn constant(&mut self, c: Const) -> u32 {
+        match self.constants.iter().position(|k| *k == c) {
+            Some(i) => i as u32,
+            None => {
+                self.constants.push(c);
+                self.constants.len() as u32 - 1
+            }
+        }
+    }
+
+    fn push_const(&mut self, c: Const) {
+        let c = self.constant(c);
+        self.code.push(Instr::Push(c));
+    }
+
+    fn enum_const(&mut self, enum_name: &str, variant: &str) -> u32 {
+        let index = self.enums[enum_name]
+            .iter()
+            .position(|v| v == variant)
+            .expect("semantic analysis guarantees the variant exists");
+        self.constant(Const::Enum { enum_name: enum_name.to_string(), index: index as u32 })
+    }
+
+    fn emit_jump(&mut self, jump: Instr) -> usize {
+        self.code.push(jump);
+        self.code.len() - 1
+    }
+
+    // points a jump emitted earlier at the next instruction
+    fn patch(&mut self, at: usize) {
+        let target = self.code.len() as u32;
+        match &mut self.code[at] {
+            Instr::Jump(t) | Instr::Jz(t) | Instr::Jnz(t) => *t = target,
+            _ => unreachable!("only jumps are patched"),
+        }
+    }
+}
+
+impl TypeEnv for BytecodeCompiler {
+    // a name that was never declared has an int slot of its own
+    fn var_type(&self, name: &str) -> Option<Type> {
+        Some(self.var(name).map_or(Type::Int, |(_, ty)| self.resolve(ty)))
+    }
+
+    fn field_type(&self, struct_name: &str, field: &str) -> Option<Type> {
+        let fields = self.structs.get(struct_name)?;
+        fields.iter().find(|(f, _)| f == field).map(|(_, ty)| self.resolve(ty))
+    }
+
+    fn resolve(&self, ty: &TypeName) -> Type {
+        resolve_type_name(ty, &self.enums)
+    }
+}
+
+// -------- FILE FORMAT --------
+
+// A program file is the magic and version, then the source name, the slot
+// count, the constant pool and the code. Integers are little-endian and
+// counts and strings are prefixed with their u32 length; each constant and
+// instruction starts with a one-byte tag.
+impl Program {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        write_str(&mut out, &self.source_name);
+        write_u32(&mut out, self.slots);
+
+        write_u32(&mut out, self.constants.len() as u32);
+        for c in &self.constants {
+            match c {
+                Const::Int(n) => {
+                    out.push(0);
+                    out.extend_from_slice(&n.to_le_bytes());
+                }
+                Const::Bool(b) => {
+                    out.push(1);
+                    out.push(*b as u8);
+                }
+                Const::String(s) => {
+                    out.push(2);
+                    write_str(&mut out, s);
+                }
+                Const::Enum { enum_name, index } => {
+                    out.push(3);
+                    write_str(&mut out, enum_name);
+                    write_u32(&mut out, *index);
+                }
+            }
+        }
+
+        write_u32(&mut out, self.code.len() as u32);
+        for instr in &self.code {
+            out.push(opcode(instr));
+            match instr {
+                Instr::Push(a) | Instr::Load(a) | Instr::Store(a)
+                | Instr::LoadIndex(a) | Instr::StoreIndex(a) | Instr::MakeArray(a)
+                | Instr::Jump(a) | Instr::Jz(a) | Instr::Jnz(a) => write_u32(&mut out, *a),
+                Instr::LoadField(a, b) | Instr::StoreField(a, b)
+                | Instr::Call(a, b) | Instr::PrintFormatted(a, b) => {
+                    write_u32(&mut out, *a);
+                    write_u32(&mut out, *b);
+                }
+                Instr::MakeStruct(names) => {
+                    write_u32(&mut out, names.len() as u32);
+                    for name in names {
+                        write_u32(&mut out, *name);
+                    }
+                }
+                Instr::Paywall(n) => out.extend_from_slice(&n.to_le_bytes()),
+                Instr::Assert { line, message } => {
+                    write_u32(&mut out, *line);
+                    match message {
+                        Some(m) => {
+                            out.push(1);
+                            write_u32(&mut out, *m);
+                        }
+                        None => out.push(0),
+                    }
+                }
+                _ => {}
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Program, BytecodeError> {
+        let mut r = Reader { bytes, pos: 0 };
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err(BytecodeError::BadMagic);
+        }
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(BytecodeError::UnsupportedVersion(version));
+        }
+        let source_name = r.string()?;
+        let slots = r.u32()?;
+
+        let mut constants = Vec::new();
+        for _ in 0..r.u32()? {
+            constants.push(match r.u8()? {
+                0 => Const::Int(r.u32()? as i32),
+                1 => Const::Bool(r.u8()? != 0),
+                2 => Const::String(r.string()?),
+                3 => Const::Enum { enum_name: r.string()?, index: r.u32()? },
+                tag => return Err(BytecodeError::BadConstantTag(tag)),
+            });
+        }
+
+        let mut code = Vec::new();
+        for _ in 0..r.u32()? {
+            code.push(match r.u8()? {
+                0 => Instr::Push(r.u32()?),
+                1 => Instr::Load(r.u32()?),
+                2 => Instr::Store(r.u32()?),
+                3 => Instr::LoadIndex(r.u32()?),
+                4 => Instr::StoreIndex(r.u32()?),
+                5 => Instr::LoadField(r.u32()?, r.u32()?),
+                6 => Instr::StoreField(r.u32()?, r.u32()?),
+                7 => Instr::MakeArray(r.u32()?),
+                8 => {
+                    let mut names = Vec::new();
+                    for _ in 0..r.u32()? {
+                        names.push(r.u32()?);
+                    }
+                    Instr::MakeStruct(names)
+                }
+                9 => Instr::Add,
+                10 => Instr::Sub,
+                11 => Instr::Mul,
+                12 => Instr::Div,
+                13 => Instr::Mod,
+                14 => Instr::Eq,
+                15 => Instr::Ne,
+                16 => Instr::Lt,
+                17 => Instr::Gt,
+                18 => Instr::Le,
+                19 => Instr::Ge,
+                20 => Instr::Neg,
+                21 => Instr::Not,
+                22 => Instr::Jump(r.u32()?),
+                23 => Instr::Jz(r.u32()?),
+                24 => Instr::Jnz(r.u32()?),
+                25 => Instr::Dup,
+                26 => Instr::Pop,
+                27 => Instr::Maybe,
+                28 => Instr::ToInt,
+                29 => Instr::ToBool,
+                30 => Instr::ToString,
+                31 => Instr::Call(r.u32()?, r.u32()?),
+                32 => Instr::Print,
+                33 => Instr::PrintFormatted(r.u32()?, r.u32()?),
+                34 => Instr::Paywall(i64::from_le_bytes(r.take(8)?.try_into().unwrap())),
+                35 => {
+                    let line = r.u32()?;
+                    let message = match r.u8()? {
+                        0 => None,
+                        _ => Some(r.u32()?),
+                    };
+                    Instr::Assert { line, message }
+                }
+                36 => Instr::Exit,
+                op => return Err(BytecodeError::BadOpcode(op)),
+            });
+        }
+
+        if slots as usize > code.len() {
+            return Err(BytecodeError::BadSlotCount(slots));
+        }
+        let program = Program { source_name, slots, constants, code };
+        if let Some(at) = program.code.iter().position(|instr| !program.operands_in_range(instr)) {
+            return Err(BytecodeError::BadOperand(at));
+        }
+        program.verify()?;
+        Ok(program)
+    }
+
+    fn operands_in_range(&self, instr: &Instr) -> bool {
+        let slot = |s: &u32| *s < self.slots;
+        let constant = |c: &u32| (*c as usize) < self.constants.len();
+        let string = |c: &u32| matches!(self.constants.get(*c as usize), Some(Const::String(_)));
+        let target = |t: &u32| *t as usize <= self.code.len();
+        match instr {
+            Instr::Push(c) => constant(c),
+            Instr::Load(s) | Instr::Store(s) | Instr::LoadIndex(s) | Instr::StoreIndex(s) => slot(s),
+            Instr::LoadField(s, f) | Instr::StoreField(s, f) => slot(s) && string(f),
+            Instr::MakeStruct(names) => names.iter().all(string),
+            Instr::Jump(t) | Instr::Jz(t) | Instr::Jnz(t) => target(t),
+            Instr::Call(name, _) | Instr::PrintFormatted(name, _) => string(name),
+            Instr::Assert { message, .. } => message.as_ref().is_none_or(string),
+            _ => true,
+        }
+    }
+
+    // follows the code the way the VM runs it, tracking the kind of every
+    // stack value and slot, so a file the VM could not run is rejected
+    // before it starts. jumps only go forward, as the compiler's do, which
+    // keeps this to a single pass
+    fn verify(&self) -> Result<(), BytecodeError> {
+        // the VM starts every slot as Int(0)
+        let mut current = Some(Frame {
+            stack: Vec::new(),
+            slots: vec![Some(Kind::Int); self.slots as usize],
+        });
+        // frames waiting at jump targets not reached yet
+        let mut pending: HashMap<usize, Frame> = HashMap::new();
+
+        for (at, instr) in self.code.iter().enumerate() {
+            if let Some(jumped) = pending.remove(&at) {
+                current = match current {
+                    Some(frame) => Some(frame.merge(jumped).ok_or(BytecodeError::IllTyped(at))?),
+                    None => Some(jumped),
+                };
+            }
+            // code nothing reaches never runs
+            let Some(frame) = current.as_mut() else {
+                continue;
+            };
+            match self.step(frame, instr, at, &mut pending) {
+                Some(true) => {}
+                Some(false) => current = None,
+                None => return Err(BytecodeError::IllTyped(at)),
+            }
+        }
+        Ok(())
+    }
+
+    // applies one instruction to `frame`; Some(false) when it never falls
+    // through to the next one, None when it cannot run
+    fn step(
+        &self,
+        frame: &mut Frame,
+        instr: &Instr,
+        at: usize,
+        pending: &mut HashMap<usize, Frame>,
+    ) -> Option<bool> {
+        match instr {
+            Instr::Push(c) => {
+                let kind = match &self.constants[*c as usize] {
+                    Const::Int(_) => Kind::Int,
+                    Const::Bool(_) => Kind::Bool,
+                    Const::String(_) => Kind::String,
+                    Const::Enum { enum_name, .. } => Kind::Enum(enum_name.clone()),
+                };
+                frame.stack.push(kind);
+            }
+            Instr::Load(slot) => {
+                let kind = frame.slots[*slot as usize].clone()?;
+                frame.stack.push(kind);
+            }
+            Instr::Store(slot) => {
+                let kind = frame.stack.pop()?;
+                frame.slots[*slot as usize] = Some(kind);
+            }
+
+            Instr::LoadIndex(slot) => {
+                frame.pop_kind(&Kind::Int)?;
+                let Some(Kind::Array(element)) = &frame.slots[*slot as usize] else {
+                    return None;
+                };
+                let element = (**element).clone();
+                frame.stack.push(element);
+            }
+            Instr::StoreIndex(slot) => {
+                let value = frame.stack.pop()?;
+                frame.pop_kind(&Kind::Int)?;
+                let Some(Kind::Array(element)) = &frame.slots[*slot as usize] else {
+                    return None;
+                };
+                if **element != value {
+                    return None;
+                }
+            }
+
+            Instr::LoadField(slot, field) => {
+                let kind = self.field_kind(frame, *slot, *field)?;
+                frame.stack.push(kind);
+            }
+            Instr::StoreField(slot, field) => {
+                let value = frame.stack.pop()?;
+                if self.field_kind(frame, *slot, *field)? != value {
+                    return None;
+                }
+            }
+
+            Instr::MakeArray(n) => {
+                let elements = frame.pop_n(*n as usize)?;
+                let first = elements.first()?.clone();
+                if elements.iter().any(|e| *e != first) {
+                    return None;
+                }
+                frame.stack.push(Kind::Array(Box::new(first)));
+            }
+            Instr::MakeStruct(names) => {
+                let values = frame.pop_n(names.len())?;
+                // fields are found by name, so their order does not matter
+                let mut fields: Vec<(String, Kind)> = names
+                    .iter()
+                    .map(|name| self.name(*name).to_string())
+                    .zip(values)
+                    .collect();
+                fields.sort_by(|a, b| a.0.cmp(&b.0));
+                frame.stack.push(Kind::Struct(fields));
+            }
+
+            Instr::Add => {
+                let r = frame.stack.pop()?;
+                let l = frame.stack.pop()?;
+                match (l, r) {
+                    (Kind::Int, Kind::Int) => frame.stack.push(Kind::Int),
+                    (Kind::String, Kind::String) => frame.stack.push(Kind::String),
+                    _ => return None,
+                }
+            }
+            Instr::Sub | Instr::Mul | Instr::Div | Instr::Mod => {
+                frame.pop_kind(&Kind::Int)?;
+                frame.pop_kind(&Kind::Int)?;
+                frame.stack.push(Kind::Int);
+            }
+            Instr::Eq | Instr::Ne | Instr::Lt | Instr::Gt | Instr::Le | Instr::Ge => {
+                let r = frame.stack.pop()?;
+                let l = frame.stack.pop()?;
+                if l != r || !l.is_scalar() {
+                    return None;
+                }
+                frame.stack.push(Kind::Bool);
+            }
+            Instr::Neg => {
+                frame.pop_kind(&Kind::Int)?;
+                frame.stack.push(Kind::Int);
+            }
+            Instr::Not => {
+                frame.pop_kind(&Kind::Bool)?;
+                frame.stack.push(Kind::Bool);
+            }
+
+            Instr::Jump(target) => {
+                frame.jump(at, *target, pending)?;
+                return Some(false);
+            }
+            Instr::Jz(target) | Instr::Jnz(target) => {
+                frame.pop_kind(&Kind::Bool)?;
+                frame.jump(at, *target, pending)?;
+            }
+            Instr::Dup => {
+                let top = frame.stack.last()?.clone();
+                frame.stack.push(top);
+            }
+            Instr::Pop => {
+                frame.stack.pop()?;
+            }
+
+            Instr::Maybe => frame.stack.push(Kind::Bool),
+
+            Instr::ToInt => {
+                let from = frame.stack.pop()?;
+                if !matches!(from, Kind::Int | Kind::Bool | Kind::String) {
+                    return None;
+                }
+                frame.stack.push(Kind::Int);
+            }
+            Instr::ToBool => {
+                let from = frame.stack.pop()?;
+                if !matches!(from, Kind::Bool | Kind::Int) {
+                    return None;
+                }
+                frame.stack.push(Kind::Bool);
+            }
+            Instr::ToString => {
+                let from = frame.stack.pop()?;
+                if !matches!(from, Kind::Int | Kind::Bool | Kind::String) {
+                    return None;
+                }
+                frame.stack.push(Kind::String);
+            }
+
+            Instr::Call(name, argc) => {
+                let (params, ret) = builtin_signature(self.name(*name))?;
+                if params.len() != *argc as usize {
+                    return None;
+                }
+                let args = frame.pop_n(params.len())?;
+                for (arg, param) in args.iter().zip(&params) {
+                    if Kind::of(param)? != *arg {
+                        return None;
+                    }
+                }
+                frame.stack.push(Kind::of(&ret)?);
+            }
+
+            Instr::Print => {
+                if !frame.stack.pop()?.is_scalar() {
+                    return None;
+                }
+            }
+            Instr::PrintFormatted(_, argc) => {
+                let args = frame.pop_n(*argc as usize)?;
+                if !args.iter().all(Kind::is_scalar) {
+                    return None;
+                }
+            }
+
+            Instr::Paywall(_) => {}
+
+            Instr::Assert { .. } => frame.pop_kind(&Kind::Bool)?,
+
+            Instr::Exit => {
+                frame.pop_kind(&Kind::Int)?;
+                return Some(false);
+            }
+        }
+        Some(true)
+    }
+
+    fn field_kind(&self, frame: &Frame, slot: u32, field: u32) -> Option<Kind> {
+        let Some(Kind::Struct(fields)) = &frame.slots[slot as usize] else {
+            return None;
+        };
+        let field = self.name(field);
+        fields.iter().find(|(f, _)| f == field).map(|(_, kind)| kind.clone())
+    }
+
+    // a constant operands_in_range has checked is a string
+    fn name(&self, index: u32) -> &str {
+        match &self.constants[index as usize] {
+            Const::String(s) => s,
+            _ => unreachable!("operands naming things are checked to be strings"),
+        }
+    }
+}
+
+// what the verifier knows about a value
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Int,
+    Bool,
+    String,
+    Enum(String),
+    Array(Box<Kind>),
+    // fields sorted by name
+    Struct(Vec<(String, Kind)>),
+}
+
+impl Kind {
+    // builtins only take and return ints, bools and strings
+    fn of(ty: &Type) -> Option<Kind> {
+        match ty {
+            Type::Int => Some(Kind::Int),
+            Type::Bool => Some(Kind::Bool),
+            Type::String => Some(Kind::String),
+            _ => None,
+        }
+    }
+
+    // values that compare and print
+    fn is_scalar(&self) -> bool {
+        matches!(self, Kind::Int | Kind::Bool | Kind::String | Kind::Enum(_))
+    }
+}
+
+// the stack and slots on entry to an instruction. a slot that holds
+// different kinds on the paths meeting there is None and cannot be loaded
+#[derive(Debug, Clone)]
+struct Frame {
+    stack: Vec<Kind>,
+    slots: Vec<Option<Kind>>,
+}
+
+impl Frame {
+    fn pop_kind(&mut self, kind: &Kind) -> Option<()> {
+        (self.stack.pop()? == *kind).then_some(())
+    }
+
+    // the top n kinds, deepest first
+    fn pop_n(&mut self, n: usize) -> Option<Vec<Kind>> {
+        let at = self.stack.len().checked_sub(n)?;
+        Some(self.stack.split_off(at))
+    }
+
+    fn jump(&self, at: usize, target: u32, pending: &mut HashMap<usize, Frame>) -> Option<()> {
+        let target = target as usize;
+        if target <= at {
+            return None;
+        }
+        let frame = match pending.remove(&target) {
+            Some(waiting) => waiting.merge(self.clone())?,
+            None => self.clone(),
+        };
+        pending.insert(target, frame);
+        Some(())
+    }
+
+    // the paths must agree on the stack; slots they disagree on are lost
+    fn merge(mut self, other: Frame) -> Option<Frame> {
+        if self.stack != other.stack {
+            return None;
+        }
+        for (slot, theirs) in self.slots.iter_mut().zip(other.slots) {
+            if *slot != theirs {
+                *slot = None;
+            }
+        }
+        Some(self)
+    }
+}
+
+fn opcode(instr: &Instr) -> u8 {
+    match instr {
+        Instr::Push(_) => 0,
+        Instr::Load(_) => 1,
+        Instr::Store(_) => 2,
+        Instr::LoadIndex(_) => 3,
+        Instr::StoreIndex(_) => 4,
+        Instr::LoadField(..) => 5,
+        Instr::StoreField(..) => 6,
+        Instr::MakeArray(_) => 7,
+        Instr::MakeStruct(_) => 8,
+        Instr::Add => 9,
+        Instr::Sub => 10,
+        Instr::Mul => 11,
+        Instr::Div => 12,
+        Instr::Mod => 13,
+        Instr::Eq => 14,
+        Instr::Ne => 15,
+        Instr::Lt => 16,
+        Instr::Gt => 17,
+        Instr::Le => 18,
+        Instr::Ge => 19,
+        Instr::Neg => 20,
+        Instr::Not => 21,
+        Instr::Jump(_) => 22,
+        Instr::Jz(_) => 23,
+        Instr::Jnz(_) => 24,
+        Instr::Dup => 25,
+        Instr::Pop => 26,
+        Instr::Maybe => 27,
+        Instr::ToInt => 28,
+        Instr::ToBool => 29,
+        Instr::ToString => 30,
+        Instr::Call(..) => 31,
+        Instr::Print => 32,
+        Instr::PrintFormatted(..) => 33,
+        Instr::Paywall(_) => 34,
+        Instr::Assert { .. } => 35,
+        Instr::Exit => 36,
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_u32(out, s.len() as u32);
+    out.extend_from_slice(s.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], BytecodeError> {
+        let end = self.pos.checked_add(n).ok_or(BytecodeError::Truncated)?;
+        let bytes = self.bytes.get(self.pos..end).ok_or(BytecodeError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, BytecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, BytecodeError> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| BytecodeError::InvalidString)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexing::lexer::lex_program;
+    use crate::optimizer::optimizer::Optimizer;
+    use crate::parsing::parser::Parser;
+    use crate::semantic::semantic::SemanticAnalyzer;
+
+    const SOURCE: &str = r#"
+        enum Color { Red, Green, Blue }
+        struct P { int x; int y; }
+        global int g = 5;
+        int[3] a = [1, 2, 3];
+        P p = P { y: 2, x: 1 };
+        p.x = 40;
+        a[2] = a[0] + p.x;
+        Color c = maybe ? Color::Blue : Color::Red;
+        match (c) { Red => { print("red"); } Blue => { print("blue {}", 7); } _ => {} }
+        string s = "hello " + read_line();
+        print(to_upper(substr(s, 1, 3)));
+        print("{} {} {}", a[2], g > 3 && maybe, int("12") * -1 / 5);
+        paywall(2);
+        assert(a[2] == 41, "sum");
+        exit(len(s));
+    "#;
+
+    fn compile(source: &str) -> Program {
+        let ast = Parser::new(lex_program(source)).parse().expect("parses");
+        SemanticAnalyzer::new().analyze(&ast).expect("checks");
+        let ast = Optimizer::new().optimize(ast).expect("optimizes");
+        BytecodeCompiler::new().with_source_name("test.q").compile(&ast)
+    }
+
+    // a hand-written program with the given code and constants
+    fn program(constants: Vec<Const>, code: Vec<Instr>) -> Program {
+        Program { source_name: "bad.q".to_string(), slots: 1, constants, code }
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let program = compile(SOURCE);
+        assert_eq!(Program::from_bytes(&program.to_bytes()), Ok(program));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = compile(SOURCE).to_bytes();
+        for len in 0..bytes.len() {
+            assert!(Program::from_bytes(&bytes[..len]).is_err(), "prefix of {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let mut bytes = compile(SOURCE).to_bytes();
+        bytes[3] = VERSION + 1;
+        assert_eq!(Program::from_bytes(&bytes), Err(BytecodeError::UnsupportedVersion(VERSION + 1)));
+        bytes[0] = b'X';
+        assert_eq!(Program::from_bytes(&bytes), Err(BytecodeError::BadMagic));
+    }
+
+    #[test]
+    fn rejects_out_of_range_operands() {
+        let bad = program(vec![Const::Int(1)], vec![Instr::Push(0), Instr::Jump(9)]);
+        assert_eq!(Program::from_bytes(&bad.to_bytes()), Err(BytecodeError::BadOperand(1)));
+    }
+
+    #[test]
+    fn rejects_code_the_vm_cannot_run() {
+        use BytecodeError::*;
+        let mut huge = program(vec![], vec![Instr::Exit]);
+        huge.slots = u32::MAX;
+        let cases = [
+            // a slot count no code could use, which would be allocated up front
+            (huge, BadSlotCount(u32::MAX)),
+            // Add on an empty stack
+            (program(vec![], vec![Instr::Add]), IllTyped(0)),
+            // a call to something that is not a builtin
+            (program(vec![Const::String("nope".into())], vec![Instr::Call(0, 0), Instr::Print]), IllTyped(0)),
+            // a builtin with an argument of the wrong kind
+            (program(vec![Const::Int(1), Const::String("len".into())], vec![Instr::Push(0), Instr::Call(1, 1)]), IllTyped(1)),
+            // Jz on an int
+            (program(vec![Const::Int(1)], vec![Instr::Push(0), Instr::Jz(2), Instr::Exit]), IllTyped(1)),
+            // a backward jump
+            (program(vec![], vec![Instr::Maybe, Instr::Jnz(0)]), IllTyped(1)),
+            // paths that meet with different stacks
+            (program(vec![Const::Int(1)], vec![Instr::Maybe, Instr::Jz(3), Instr::Push(0), Instr::Print]), IllTyped(3)),
+            // an index into a slot that holds an int
+            (program(vec![Const::Int(0)], vec![Instr::Push(0), Instr::LoadIndex(0)]), IllTyped(1)),
+        ];
+        for (bad, error) in cases {
+            assert_eq!(Program::from_bytes(&bad.to_bytes()), Err(error), "{:?}", bad.code);
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::{BTreeSet, HashMap};
+use crate::parsing::ast::{Expr, Stmt, TypeName};
+use crate::semantic::semantic::{self, Type, TypeEnv, resolve_type_name};
+
+// Constants and helpers shared by the native backends, and by the
+// interpreter's runtime where it has to behave the same way.
+
+// a match becomes a jump table once it has this many explicit arms and they
+// cover at least half of the enum's variants; sparser matches compare in turn
+pub(crate) const JUMP_TABLE_MIN_ARMS: usize = 4;
+
+// status of a program stopped by a failed assert; runtime errors exit with 1
+pub(crate) const ASSERT_FAILED_STATUS: i32 = 3;
+
+// the line a failed assert prints before the program exits
+pub(crate) fn assert_message(source_name: &str, line: usize, message: Option<&str>) -> String {
+    let mut text = format!("assertion failed at {}:{}", source_name, line);
+    if let Some(message) = message {
+        text.push_str(": ");
+        text.push_str(message);
+    }
+    text
+}
+
+// key a paywall accepts unless the driver configures another one
+pub const DEFAULT_PAYWALL_KEY: &str = "haha";
+
+// attempts a paywall allows before the program exits, unless configured
+pub const DEFAULT_PAYWALL_RETRIES: u32 = 3;
+pub(crate) const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+pub(crate) const FNV_PRIME: u64 = 0x100000001b3;
+
+// strings the runtime routines use, emitted into .data only when the
+// program uses the routine: (routine, label, text)
+const RUNTIME_STRINGS: [(&str, &str, &str); 10] = [
+    ("div_zero", "msg_div_zero", "runtime error: division by zero\n"),
+    ("bounds", "msg_bounds", "runtime error: array index out of bounds\n"),
+    ("int_to_str", "fmt_dec", "%d"),
+    ("str_to_int", "msg_bad_int", "runtime error: invalid int conversion\n"),
+    ("alloc", "msg_oom", "runtime error: out of memory\n"),
+    ("char_at", "msg_str_index", "runtime error: string index out of bounds\n"),
+    ("paywall", "msg_pay", "free trial over pew pew, enter the key to continue: "),
+    ("paywall", "msg_pay_denied", "wrong key, access denied\n"),
+    ("rand", "env_seed", "QUASAR_SEED"),
+    ("args", "msg_arg_index", "runtime error: argument index out of range\n"),
+];
+
+// state the runtime routines keep in .bss: (routine, label)
+const RUNTIME_STATE: [(&str, &str); 5] = [
+    ("alloc", "__q_arena"),
+    ("args", "__q_argc"),
+    ("args", "__q_argv"),
+    ("print_count", "__q_print_count"),
+    ("rand", "__q_rng_state"),
+];
+
+// the .data and .bss sections of a generated program: the print formats,
+// the pooled string literals, the globals (a label with its initializer,
+// or none for a zeroed one) and whatever the used runtime routines need
+fn data_sections(
+    strings: &[String],
+    globals: &[(String, Option<String>)],
+    runtime: &BTreeSet<&str>,
+) -> String {
+    let mut out = String::from("\t.data\n");
+    out.push_str("fmt_int: .asciz \"%d\\n\"\n");
+    out.push_str("fmt_str: .asciz \"%s\\n\"\n");
+
+    // STRING LITERALS
+    for (i, lit) in strings.iter().enumerate() {
+        out.push_str(&format!("str_{}: .asciz \"{}\"\n", i, escape_asciz(lit)));
+    }
+
+    // GLOBALS
+    let (data, bss): (Vec<_>, Vec<_>) = globals.iter().partition(|(_, init)| init.is_some());
+    if !data.is_empty() {
+        out.push_str("\t.balign 8\n");
+        for (label, init) in data {
+            out.push_str(&format!("{}: .quad {}\n", label, init.as_ref().unwrap()));
+        }
+    }
+
+    // RUNTIME STRINGS
+    for (routine, label, text) in RUNTIME_STRINGS {
+        if runtime.contains(routine) {
+            out.push_str(&format!("{}: .asciz \"{}\"\n", label, escape_asciz(text)));
+        }
+    }
+
+    let mut bss: Vec<&str> = bss.iter().map(|(label, _)| label.as_str()).collect();
+    for (routine, label) in RUNTIME_STATE {
+        if runtime.contains(routine) {
+            bss.push(label);
+        }
+    }
+    if !bss.is_empty() {
+        out.push_str("\n\t.bss\n");
+        out.push_str("\t.balign 8\n");
+        for label in bss {
+            out.push_str(&format!("{}: .zero 8\n", label));
+        }
+    }
+    out
+}
+
+pub(crate) struct Var {
+    pub(crate) slot: Slot,
+    pub(crate) ty: Type,
+}
+
+pub(crate) enum Slot {
+    // offset from the stack pointer within main's frame
+    Stack(usize),
+    // label of a word in .data or .bss
+    Global(String),
+}
+
+// The target-independent bookkeeping of a native backend: where each
+// variable lives, the declared structs and enums, the string pool and the
+// globals. Every value takes 8-byte words; arrays and structs are laid out
+// word by word in main's frame.
+pub(crate) struct Layout {
+    // one map per lexical block, innermost last
+    scopes: Vec<HashMap<String, Var>>,
+    // struct name -> fields in declaration order; field i lives at offset 8 * i
+    structs: HashMap<String, Vec<(String, Type)>>,
+    // enum name -> variants; a value is its variant's index
+    enums: HashMap<String, Vec<String>>,
+    // string literals, deduplicated; literal i is emitted as `str_i`
+    strings: Vec<String>,
+    // globals in declaration order: label and initial `.quad` value, or
+    // None for a zero-initialized global placed in .bss
+    globals: Vec<(String, Option<String>)>,
+    // bytes of main's frame handed out so far
+    pub(crate) frame_size: usize,
+}
+
+impl Layout {
+    pub(crate) fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            strings: Vec::new(),
+            globals: Vec::new(),
+            frame_size: 0,
+        }
+    }
+
+    pub(crate) fn data_sections(&self, runtime: &BTreeSet<&str>) -> String {
+        data_sections(&self.strings, &self.globals, runtime)
+    }
+
+    pub(crate) fn enter_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub(crate) fn exit_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    pub(crate) fn declare_struct(&mut self, name: &str, fields: &[(String, TypeName)]) {
+        let fields = fields.iter().map(|(f, ty)| (f.clone(), self.resolve(ty))).collect();
+        self.structs.insert(name.to_string(), fields);
+    }
+
+    pub(crate) fn declare_enum(&mut self, name: &str, variants: &[String]) {
+        self.enums.insert(name.to_string(), variants.to_vec());
+    }
+
+    pub(crate) fn variants(&self, enum_name: &str) -> &[String] {
+        &self.enums[enum_name]
+    }
+
+    pub(crate) fn variant_index(&self, enum_name: &str, variant: &str) -> usize {
+        self.enums[enum_name]
+            .iter()
+            .position(|v| v == variant)
+            .expect("semantic analysis guarantees the variant exists")
+    }
+
+    // gives a variable its words in main's frame; the backend stores its
+    // value at the returned offset
+    pub(crate) fn declare_local(&mut self, name: &str, ty: Type) -> usize {
+        let offset = self.alloc_slot(self.slot_size(&ty));
+        let var = Var { slot: Slot::Stack(offset), ty };
+        self.scopes.last_mut().unwrap().insert(name.to_string(), var);
+        offset
+    }
+
+    // the initializer is a literal by now, so it becomes the global's
+    // initial value and no code runs for the declaration
+    pub(crate) fn declare_global(&mut self, name: &str, ty: Type, value: &Expr) {
+        let init = match value {
+            Expr::IntegerLiteral(0) | Expr::BooleanLiteral(false) => None,
+            Expr::IntegerLiteral(n) => Some(n.to_string()),
+            Expr::BooleanLiteral(true) => Some("1".to_string()),
+            Expr::StringLiteral(lit) => Some(self.string_label(lit)),
+            Expr::EnumVariant { enum_name, variant } => {
+                match self.variant_index(enum_name, variant) {
+                    0 => None,
+                    index => Some(index.to_string()),
+                }
+            }
+            _ => unreachable!("semantic analysis guarantees a constant initializer"),
+        };
+        let label = format!("g_{}", name);
+        self.globals.push((label.clone(), init));
+        let var = Var { slot: Slot::Global(label), ty };
+        self.scopes.last_mut().unwrap().insert(name.to_string(), var);
+    }
+
+    pub(crate) fn var(&self, name: &str) -> Option<&Var> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    // stack offset and length of the array `name`
+    pub(crate) fn array_var(&self, name: &str) -> (usize, usize) {
+        match self.var(name) {
+            Some(Var { slot: Slot::Stack(offset), ty: Type::Array(_, len) }) => (*offset, *len),
+            _ => unreachable!("semantic analysis guarantees '{}' is an array", name),
+        }
+    }
+
+    // offset of `field` within a `struct_name`, and its type
+    pub(crate) fn field(&self, struct_name: &str, field: &str) -> (usize, Type) {
+        let fields = &self.structs[struct_name];
+        let i = fields.iter()
+            .position(|(f, _)| f == field)
+            .expect("semantic analysis guarantees the field exists");
+        (i * 8, fields[i].1.clone())
+    }
+
+    // stack offset of `name.field`
+    pub(crate) fn field_slot(&self, name: &str, field: &str) -> usize {
+        match self.var(name) {
+            Some(Var { slot: Slot::Stack(offset), ty: Type::Struct(struct_name) }) => {
+                offset + self.field(struct_name, field).0
+            }
+            _ => unreachable!("semantic analysis guarantees '{}' is a struct", name),
+        }
+    }
+
+    pub(crate) fn slot_size(&self, ty: &Type) -> usize {
+        match ty {
+            Type::Array(_, len) => len * 8,
+            Type::Struct(name) => self.structs[name].len() * 8,
+            _ => 8,
+        }
+    }
+
+    pub(crate) fn alloc_slot(&mut self, size: usize) -> usize {
+        let offset = self.frame_size;
+        self.frame_size += size;
+        offset
+    }
+
+    pub(crate) fn string_label(&mut self, lit: &str) -> String {
+        let index = match self.strings.iter().position(|s| s == lit) {
+            Some(i) => i,
+            None => {
+                self.strings.push(lit.to_string());
+                self.strings.len() - 1
+            }
+        };
+        format!("str_{}", index)
+    }
+
+    // the type semantic analysis gave an expression, which decides how it
+    // prints
+    pub(crate) fn expr_type(&self, expr: &Expr) -> Type {
+        semantic::expr_type(expr, self).expect("semantic analysis guarantees a well-typed expression")
+    }
+}
+
+impl TypeEnv for Layout {
+    // a name analysis was told to treat as an int is never declared, and
+    // reads as an int
+    fn var_type(&self, name: &str) -> Option<Type> {
+        Some(self.var(name).map_or(Type::Int, |v| v.ty.clone()))
+    }
+
+    fn field_type(&self, struct_name: &str, field: &str) -> Option<Type> {
+        let fields = self.structs.get(struct_name)?;
+        fields.iter().find(|(f, _)| f == field).map(|(_, ty)| ty.clone())
+    }
+
+    fn resolve(&self, ty: &TypeName) -> Type {
+        resolve_type_name(ty, &self.enums)
+    }
+}
+
+pub fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(FNV_OFFSET_BASIS, |hash, b| (hash ^ b as u64).wrapping_mul(FNV_PRIME))
+}
+
+pub fn has_paywall(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Paywall(_) => true,
+        Stmt::Block(stmts) => stmts.iter().any(has_paywall),
+        Stmt::If { then_block, else_block, .. } => {
+            then_block.iter().any(has_paywall)
+                || else_block.iter().flatten().any(has_paywall)
+        }
+        Stmt::Match { arms, .. } => arms.iter().any(|arm| arm.body.iter().any(has_paywall)),
+        _ => false,
+    }
+}
+
+pub(crate) fn escape_asciz(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -1 +1,4 @@
-pub mod arm64;
\ No newline at end of file
+pub mod arm64;
+pub mod bytecode;
+pub mod common;
+pub mod x86_64;
\ No newline at end of file
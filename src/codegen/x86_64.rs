@@ -0,0 +1,1335 @@
+use std::collections::BTreeSet;
+use crate::parsing::ast::{Expr, Stmt, BinOp, UnOp, TypeName, MatchArm, MatchPattern};
+use crate::optimizer::optimizer::is_pure;
+use crate::semantic::semantic::{Type, TypeEnv};
+use crate::codegen::common::{
+    Layout, Slot, Var, assert_message, fnv1a, has_paywall, ASSERT_FAILED_STATUS,
+    DEFAULT_PAYWALL_KEY, DEFAULT_PAYWALL_RETRIES, JUMP_TABLE_MIN_ARMS, FNV_OFFSET_BASIS, FNV_PRIME,
+};
+
+// expression temporaries live in the callee-saved registers so they survive
+// the calls made while evaluating an expression. there is no frame pointer,
+// so %rbp is one of them; variables are addressed from %rsp
+const TMP_REGS: [&str; 6] = ["%rbx", "%r12", "%r13", "%r14", "%r15", "%rbp"];
+
+// holds an operand for the one instruction that uses it, when the
+// temporary it was computed in had to be handed back (see gen_operand)
+const SPILL_REG: &str = "%r11";
+
+// integer argument registers in System V order
+const ARG_REGS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+// Generates x86-64 GAS (AT&T syntax) assembly for Linux, following the
+// System V ABI, so the output assembles and links with the host `cc`. The
+// language's semantics and runtime messages are those of the arm64 backend.
+pub struct Codegen {
+    out: String,
+    // source file named in assertion failure messages
+    source_name: String,
+    // variables, structs, enums, string literals and globals
+    layout: Layout,
+    label_counter: usize,
+    tmp_depth: usize,
+    // FNV-1a hash of the paywall's unlock key
+    paywall_key_hash: u64,
+    // attempts the paywall allows before exiting
+    paywall_retries: u32,
+    // seed for `maybe` when QUASAR_SEED is not set at startup; None seeds
+    // from the clock
+    seed: Option<u64>,
+    // the program has a paywall, so every print bumps `__q_print_count`
+    metered: bool,
+    // runtime support routines referenced by the generated code; each one is
+    // emitted once after main together with the data it needs
+    runtime: BTreeSet<&'static str>,
+}
+
+impl Default for Codegen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Codegen {
+    pub fn new() -> Self {
+        Self {
+            out: String::new(),
+            source_name: "<input>".to_string(),
+            layout: Layout::new(),
+            label_counter: 0,
+            tmp_depth: 0,
+            paywall_key_hash: fnv1a(DEFAULT_PAYWALL_KEY),
+            paywall_retries: DEFAULT_PAYWALL_RETRIES,
+            seed: None,
+            metered: false,
+            runtime: BTreeSet::new(),
+        }
+    }
+
+    pub fn with_source_name(mut self, name: &str) -> Self {
+        self.source_name = name.to_string();
+        self
+    }
+
+    pub fn with_paywall_key(mut self, key: &str) -> Self {
+        self.paywall_key_hash = fnv1a(key);
+        self
+    }
+
+    pub fn with_paywall_retries(mut self, retries: u32) -> Self {
+        self.paywall_retries = retries.max(1);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn generate(mut self, stmts: &[Stmt]) -> String {
+        // prints are only counted when some paywall reads the count
+        self.metered = stmts.iter().any(has_paywall);
+        if self.metered {
+            self.runtime.insert("print_count");
+        }
+
+        for stmt in stmts {
+            self.gen_stmt(stmt);
+        }
+
+        // 1. DATA SECTION
+        let mut out = self.layout.data_sections(&self.runtime);
+
+        // 2. TEXT SECTION
+        out.push_str("\n\t.text\n");
+        out.push_str("\t.globl main\n");
+        out.push_str("main:\n");
+
+        // Prologue; the six pushes leave %rsp 8 bytes off a 16-byte boundary,
+        // like it was on entry
+        out.push_str("\tpush %rbx\n");
+        out.push_str("\tpush %rbp\n");
+        out.push_str("\tpush %r12\n");
+        out.push_str("\tpush %r13\n");
+        out.push_str("\tpush %r14\n");
+        out.push_str("\tpush %r15\n");
+
+        // argc / argv arrive in %edi / %rsi and are kept for args() and arg_count()
+        if self.runtime.contains("args") {
+            out.push_str("\tmovslq %edi, %rax\n");
+            out.push_str("\tmov %rax, __q_argc(%rip)\n");
+            out.push_str("\tmov %rsi, __q_argv(%rip)\n");
+        }
+
+        // the frame holds every variable slot; the extra 8 bytes realign %rsp
+        // to 16 for the calls main makes
+        let frame = self.layout.frame_size.div_ceil(16) * 16 + 8;
+        out.push_str(&format!("\tsub ${}, %rsp\n", frame));
+
+        // runtime strings are released however the program ends
+        if self.runtime.contains("alloc") {
+            out.push_str("\tlea __q_arena_free(%rip), %rdi\n");
+            out.push_str("\tcall atexit@PLT\n");
+        }
+
+        if self.runtime.contains("rand") {
+            out.push_str("\tcall __q_seed_rng\n");
+        }
+
+        // Epilogue; `return` jumps here with its status already in %rax
+        self.emit("\txor %eax, %eax");
+        self.emit("__q_main_exit:");
+        self.emit(format!("\tadd ${}, %rsp", frame));
+        self.emit("\tpop %r15");
+        self.emit("\tpop %r14");
+        self.emit("\tpop %r13");
+        self.emit("\tpop %r12");
+        self.emit("\tpop %rbp");
+        self.emit("\tpop %rbx");
+        self.emit("\tret");
+
+        // 3. RUNTIME SUPPORT
+        self.gen_runtime();
+
+        // the program needs no executable stack
+        self.emit("\t.section .note.GNU-stack,\"\",@progbits");
+
+        out + &self.out
+    }
+
+    fn gen_runtime(&mut self) {
+        // traps are reached by a jump from the failing check; they never return
+        if self.runtime.contains("div_zero") {
+            self.gen_trap("__q_div_zero", "msg_div_zero");
+        }
+        if self.runtime.contains("bounds") {
+            self.gen_trap("__q_bounds", "msg_bounds");
+        }
+        if self.runtime.contains("int_to_str") {
+            self.gen_int_to_str();
+        }
+        if self.runtime.contains("str_to_int") {
+            self.gen_str_to_int();
+            self.gen_trap("__q_bad_int", "msg_bad_int");
+        }
+        if self.runtime.contains("concat") {
+            self.gen_concat();
+        }
+        if self.runtime.contains("substr") {
+            self.gen_substr();
+        }
+        if self.runtime.contains("index_of") {
+            self.gen_index_of();
+        }
+        if self.runtime.contains("to_upper") {
+            self.gen_to_upper();
+        }
+        if self.runtime.contains("char_at") {
+            self.gen_char_at();
+            self.gen_trap("__q_str_index", "msg_str_index");
+        }
+        if self.runtime.contains("read_line") {
+            self.gen_read_line();
+        }
+        if self.runtime.contains("read_int") {
+            self.gen_read_int();
+        }
+        if self.runtime.contains("rand") {
+            self.gen_rand();
+        }
+        if self.runtime.contains("paywall") {
+            self.gen_paywall();
+            self.gen_trap("__q_pay_denied", "msg_pay_denied");
+        }
+        if self.runtime.contains("args") {
+            self.gen_args();
+            self.gen_trap("__q_arg_index", "msg_arg_index");
+        }
+        if self.runtime.contains("alloc") {
+            self.gen_alloc();
+            self.gen_trap("__q_oom", "msg_oom");
+        }
+    }
+
+    // The runtime routines take their arguments in %rdi, %rsi, %rdx and
+    // return in %rax like any System V function. Each one keeps %rsp 16-byte
+    // aligned at its own calls: it is entered 8 bytes off, so it pushes or
+    // reserves an odd number of quads before calling out.
+
+    // Strings built at runtime live in an arena, as in the arm64 backend:
+    // every block comes from __q_alloc, is linked into the list headed by
+    // __q_arena, and is freed by __q_arena_free, which main registers with
+    // atexit.
+
+    // %rdi = size -> %rax = a fresh arena block of that many bytes
+    fn gen_alloc(&mut self) {
+        self.emit("__q_alloc:");
+        self.emit("\tsub $8, %rsp");
+        self.emit("\tadd $8, %rdi"); // room for the link to the previous block
+        self.emit("\tcall malloc@PLT");
+        self.emit("\ttest %rax, %rax");
+        self.emit("\tjz __q_oom");
+        self.emit("\tmov __q_arena(%rip), %rcx");
+        self.emit("\tmov %rcx, (%rax)");
+        self.emit("\tmov %rax, __q_arena(%rip)");
+        self.emit("\tadd $8, %rax");
+        self.emit("\tadd $8, %rsp");
+        self.emit("\tret");
+
+        self.emit("__q_arena_free:");
+        self.emit("\tpush %rbx");
+        self.emit("\tmov __q_arena(%rip), %rbx");
+        self.emit("\tmovq $0, __q_arena(%rip)");
+        self.emit("1:");
+        self.emit("\ttest %rbx, %rbx");
+        self.emit("\tjz 2f");
+        self.emit("\tmov %rbx, %rdi");
+        self.emit("\tmov (%rbx), %rbx");
+        self.emit("\tcall free@PLT");
+        self.emit("\tjmp 1b");
+        self.emit("2:");
+        self.emit("\tpop %rbx");
+        self.emit("\tret");
+    }
+
+    // %rdi = a, %rsi = b -> %rax = a new string holding a followed by b
+    fn gen_concat(&mut self) {
+        self.emit("__q_concat:");
+        self.emit("\tpush %rbx");
+        self.emit("\tpush %r12");
+        self.emit("\tpush %r13");
+        self.emit("\tpush %r14");
+        self.emit("\tsub $8, %rsp");
+        self.emit("\tmov %rdi, %rbx");
+        self.emit("\tmov %rsi, %r12");
+        self.emit("\tcall strlen@PLT");
+        self.emit("\tmov %rax, %r13");
+        self.emit("\tmov %r12, %rdi");
+        self.emit("\tcall strlen@PLT");
+        self.emit("\tmov %rax, %r14");
+        self.emit("\tlea 1(%r13,%r14), %rdi");
+        self.emit("\tcall __q_alloc");
+        self.emit("\tmov %rax, %rdi");
+        self.emit("\tmov %rbx, %rsi");
+        self.emit("\tmov %r13, %rdx");
+        self.emit("\tmov %rax, %rbx");
+        self.emit("\tcall memcpy@PLT");
+        self.emit("\tlea (%rbx,%r13), %rdi");
+        self.emit("\tmov %r12, %rsi");
+        self.emit("\tlea 1(%r14), %rdx"); // b's terminator ends the result
+        self.emit("\tcall memcpy@PLT");
+        self.emit("\tmov %rbx, %rax");
+        self.emit("\tadd $8, %rsp");
+        self.emit("\tpop %r14");
+        self.emit("\tpop %r13");
+        self.emit("\tpop %r12");
+        self.emit("\tpop %rbx");
+        self.emit("\tret");
+    }
+
+    // %rdi = s, %rsi = start, %rdx = len -> %rax = a copy of that part of s,
+    // with start clamped to [0, strlen(s)] and len to what is left after start
+    fn gen_substr(&mut self) {
+        self.emit("__q_substr:");
+        self.emit("\tpush %rbx");
+        self.emit("\tpush %r12");
+        self.emit("\tpush %r13");
+        self.emit("\tpush %r14");
+        self.emit("\tsub $8, %rsp");
+        self.emit("\tmov %rdi, %rbx");
+        self.emit("\tmov %rsi, %r12");
+        self.emit("\tmov %rdx, %r13");
+        self.emit("\tcall strlen@PLT");
+        self.emit("\txor %ecx, %ecx");
+        self.emit("\tcmp $0, %r12");
+        self.emit("\tcmovl %rcx, %r12");
+        self.emit("\tcmp %rax, %r12");
+        self.emit("\tcmovg %rax, %r12");
+        self.emit("\tmov %rax, %rdx");
+        self.emit("\tsub %r12, %rdx");
+        self.emit("\tcmp $0, %r13");
+        self.emit("\tcmovl %rcx, %r13");
+        self.emit("\tcmp %rdx, %r13");
+        self.emit("\tcmovg %rdx, %r13");
+        self.emit("\tlea 1(%r13), %rdi");
+        self.emit("\tcall __q_alloc");
+        self.emit("\tmov %rax, %r14");
+        self.emit("\tmov %rax, %rdi");
+        self.emit("\tlea (%rbx,%r12), %rsi");
+        self.emit("\tmov %r13, %rdx");
+        self.emit("\tcall memcpy@PLT");
+        self.emit("\tmovb $0, (%r14,%r13)");
+        self.emit("\tmov %r14, %rax");
+        self.emit("\tadd $8, %rsp");
+        self.emit("\tpop %r14");
+        self.emit("\tpop %r13");
+        self.emit("\tpop %r12");
+        self.emit("\tpop %rbx");
+        self.emit("\tret");
+    }
+
+    // %rdi = s, %rsi = t -> %rax = offset of the first t in s, or -1
+    fn gen_index_of(&mut self) {
+        self.emit("__q_index_of:");
+        self.emit("\tpush %rbx");
+        self.emit("\tmov %rdi, %rbx");
+        self.emit("\tcall strstr@PLT");
+        self.emit("\ttest %rax, %rax");
+        self.emit("\tjz 1f");
+        self.emit("\tsub %rbx, %rax");
+        self.emit("\tjmp 2f");
+        self.emit("1:");
+        self.emit("\tmov $-1, %rax");
+        self.emit("2:");
+        self.emit("\tpop %rbx");
+        self.emit("\tret");
+    }
+
+    // %rdi = s -> %rax = a copy of s with ASCII letters upper-cased
+    fn gen_to_upper(&mut self) {
+        self.emit("__q_to_upper:");
+        self.emit("\tpush %rbx");
+        self.emit("\tmov %rdi, %rbx");
+        self.emit("\tcall strlen@PLT");
+        self.emit("\tlea 1(%rax), %rdi");
+        self.emit("\tcall __q_alloc");
+        self.emit("\txor %ecx, %ecx");
+        self.emit("1:"); // copies up to and including the terminator
+        self.emit("\tmovzbl (%rbx,%rcx), %edx");
+        self.emit("\tlea -97(%rdx), %esi"); // 'a'
+        self.emit("\tcmp $25, %esi");
+        self.emit("\tja 2f");
+        self.emit("\tsub $32, %edx");
+        self.emit("2:");
+        self.emit("\tmov %dl, (%rax,%rcx)");
+        self.emit("\tinc %rcx");
+        self.emit("\ttest %edx, %edx");
+        self.emit("\tjnz 1b");
+        self.emit("\tpop %rbx");
+        self.emit("\tret");
+    }
+
+    // %rdi = s, %rsi = i -> %rax = the one-character string at s[i]; traps
+    // when i is outside s
+    fn gen_char_at(&mut self) {
+        self.emit("__q_char_at:");
+        self.emit("\tpush %rbx");
+        self.emit("\tpush %r12");
+        self.emit("\tsub $8, %rsp");
+        self.emit("\tmov %rdi, %rbx");
+        self.emit("\tmov %rsi, %r12");
+        self.emit("\tcall strlen@PLT");
+        self.emit("\tcmp %rax, %r12"); // unsigned, so a negative index fails too
+        self.emit("\tjae __q_str_index");
+        self.emit("\tmov $2, %edi");
+        self.emit("\tcall __q_alloc");
+        self.emit("\tmovzbl (%rbx,%r12), %ecx");
+        self.emit("\tmov %cl, (%rax)");
+        self.emit("\tmovb $0, 1(%rax)");
+        self.emit("\tadd $8, %rsp");
+        self.emit("\tpop %r12");
+        self.emit("\tpop %rbx");
+        self.emit("\tret");
+    }
+
+    // -> %rax = the next line of stdin without its newline, or "" at end of
+    // input. fgets never writes past the 1024-byte block; a longer line is
+    // returned in pieces by successive calls
+    fn gen_read_line(&mut self) {
+        self.emit("__q_read_line:");
+        self.emit("\tpush %rbx");
+        self.emit("\tmov $1024, %edi");
+        self.emit("\tcall __q_alloc");
+        self.emit("\tmov %rax, %rbx");
+        self.emit("\tmov %rax, %rdi");
+        self.emit("\tmov $1024, %esi");
+        self.emit("\tmov stdin@GOTPCREL(%rip), %rdx");
+        self.emit("\tmov (%rdx), %rdx");
+        self.emit("\tcall fgets@PLT");
+        self.emit("\ttest %rax, %rax");
+        self.emit("\tjnz 1f");
+        self.emit("\tmovb $0, (%rbx)");
+        self.emit("1:");
+        self.emit("\tmov %rbx, %rdi");
+        self.emit("\tcall strlen@PLT");
+        self.emit("\ttest %rax, %rax");
+        self.emit("\tjz 2f");
+        self.emit("\tcmpb $10, -1(%rbx,%rax)"); // '\n'
+        self.emit("\tjne 2f");
+        self.emit("\tmovb $0, -1(%rbx,%rax)");
+        self.emit("2:");
+        self.emit("\tmov %rbx, %rax");
+        self.emit("\tpop %rbx");
+        self.emit("\tret");
+    }
+
+    // -> %rax = the next line of stdin as an int, with int(s)'s rules
+    fn gen_read_int(&mut self) {
+        self.emit("__q_read_int:");
+        self.emit("\tsub $8, %rsp");
+        self.emit("\tcall __q_read_line");
+        self.emit("\tmov %rax, %rdi");
+        self.emit("\tcall __q_str_to_int");
+        self.emit("\tadd $8, %rsp");
+        self.emit("\tret");
+    }
+
+    // the same xorshift64* generator and splitmix64 seeding as the arm64
+    // runtime, so a seed flips every `maybe` alike on both targets
+    fn gen_rand(&mut self) {
+        self.emit("__q_seed_rng:");
+        self.emit("\tsub $8, %rsp");
+        self.emit("\tlea env_seed(%rip), %rdi");
+        self.emit("\tcall getenv@PLT");
+        self.emit("\ttest %rax, %rax");
+        self.emit("\tjz 1f");
+        self.emit("\tmov %rax, %rdi");
+        self.emit("\txor %esi, %esi");
+        self.emit("\tmov $10, %edx");
+        self.emit("\tcall strtoull@PLT");
+        self.emit("\tjmp 2f");
+        self.emit("1:");
+        match self.seed {
+            Some(seed) => self.emit(format!("\tmovabs ${:#x}, %rax", seed)),
+            None => {
+                self.emit("\txor %edi, %edi");
+                self.emit("\tcall time@PLT");
+            }
+        }
+        self.emit("2:");
+        self.emit("\tmovabs $0x9e3779b97f4a7c15, %rcx");
+        self.emit("\tadd %rcx, %rax");
+        self.emit("\tmov %rax, %rcx");
+        self.emit("\tshr $30, %rcx");
+        self.emit("\txor %rcx, %rax");
+        self.emit("\tmovabs $0xbf58476d1ce4e5b9, %rcx");
+        self.emit("\timul %rcx, %rax");
+        self.emit("\tmov %rax, %rcx");
+        self.emit("\tshr $27, %rcx");
+        self.emit("\txor %rcx, %rax");
+        self.emit("\tmovabs $0x94d049bb133111eb, %rcx");
+        self.emit("\timul %rcx, %rax");
+        self.emit("\tmov %rax, %rcx");
+        self.emit("\tshr $31, %rcx");
+        self.emit("\txor %rcx, %rax");
+        self.emit("\ttest %rax, %rax");
+        self.emit("\tjnz 3f");
+        self.emit("\tmovabs $0x9e3779b97f4a7c15, %rax");
+        self.emit("3:");
+        self.emit("\tmov %rax, __q_rng_state(%rip)");
+        self.emit("\tadd $8, %rsp");
+        self.emit("\tret");
+
+        // -> %rax = 0 or 1, the top bit of the next output
+        self.emit("__q_rand:");
+        self.emit("\tmov __q_rng_state(%rip), %rax");
+        self.emit("\tmov %rax, %rcx");
+        self.emit("\tshr $12, %rcx");
+        self.emit("\txor %rcx, %rax");
+        self.emit("\tmov %rax, %rcx");
+        self.emit("\tshl $25, %rcx");
+        self.emit("\txor %rcx, %rax");
+        self.emit("\tmov %rax, %rcx");
+        self.emit("\tshr $27, %rcx");
+        self.emit("\txor %rcx, %rax");
+        self.emit("\tmov %rax, __q_rng_state(%rip)");
+        self.emit("\tmovabs $0x2545f4914f6cdd1d, %rcx");
+        self.emit("\timul %rcx, %rax");
+        self.emit("\tshr $63, %rax");
+        self.emit("\tret");
+    }
+
+    // prompts for the unlock key until its hash matches, exiting through
+    // __q_pay_denied once the attempts run out or stdin ends. each attempt
+    // reads one line of at most 63 bytes with fgets into a buffer at the
+    // bottom of the frame; the rest of a longer line is discarded
+    fn gen_paywall(&mut self) {
+        self.emit("__q_paywall:");
+        self.emit("\tpush %rbx");
+        self.emit("\tpush %r12");
+        self.emit("\tsub $72, %rsp");
+        self.emit(format!("\tmov ${}, %ebx", self.paywall_retries));
+        self.emit("1:");
+        self.emit("\tlea msg_pay(%rip), %rdi");
+        self.emit("\txor %eax, %eax");
+        self.emit("\tcall printf@PLT");
+        self.emit("\txor %edi, %edi");
+        self.emit("\tcall fflush@PLT");
+        self.emit("\tmov %rsp, %rdi");
+        self.emit("\tmov $64, %esi");
+        self.emit("\tmov stdin@GOTPCREL(%rip), %rdx");
+        self.emit("\tmov (%rdx), %rdx");
+        self.emit("\tcall fgets@PLT");
+        self.emit("\ttest %rax, %rax");
+        self.emit("\tjz __q_pay_denied");
+        // FNV-1a over the line, up to its newline
+        self.emit("\tmov %rsp, %rcx");
+        self.emit(format!("\tmovabs ${:#x}, %r12", FNV_OFFSET_BASIS));
+        self.emit(format!("\tmovabs ${:#x}, %rsi", FNV_PRIME));
+        self.emit("2:");
+        self.emit("\tmovzbl (%rcx), %edx");
+        self.emit("\tinc %rcx");
+        self.emit("\tcmp $10, %edx"); // '\n'
+        self.emit("\tje 4f");
+        self.emit("\ttest %edx, %edx");
+        self.emit("\tjz 3f");
+        self.emit("\txor %rdx, %r12");
+        self.emit("\timul %rsi, %r12");
+        self.emit("\tjmp 2b");
+        // no newline: the line did not fit, drop the rest of it
+        self.emit("3:");
+        self.emit("\tcall getchar@PLT");
+        self.emit("\tcmp $10, %eax");
+        self.emit("\tje 4f");
+        self.emit("\tcmp $-1, %eax"); // EOF
+        self.emit("\tjne 3b");
+        self.emit("4:");
+        self.emit(format!("\tmovabs ${:#x}, %rcx", self.paywall_key_hash));
+        self.emit("\tcmp %rcx, %r12");
+        self.emit("\tje 5f");
+        self.emit("\tdec %ebx");
+        self.emit("\tjnz 1b");
+        self.emit("\tjmp __q_pay_denied");
+        self.emit("5:");
+        self.emit("\tadd $72, %rsp");
+        self.emit("\tpop %r12");
+        self.emit("\tpop %rbx");
+        self.emit("\tret");
+    }
+
+    // %rdi = i -> %rax = argv[i], trapping unless 0 <= i < argc;
+    // __q_arg_count returns argc
+    fn gen_args(&mut self) {
+        self.emit("__q_args:");
+        self.emit("\tcmp __q_argc(%rip), %rdi");
+        self.emit("\tjae __q_arg_index");
+        self.emit("\tmov __q_argv(%rip), %rax");
+        self.emit("\tmov (%rax,%rdi,8), %rax");
+        self.emit("\tret");
+
+        self.emit("__q_arg_count:");
+        self.emit("\tmov __q_argc(%rip), %rax");
+        self.emit("\tret");
+    }
+
+    // %rdi = int -> %rax = its decimal text, in a 16-byte arena block
+    // (room for "-2147483648")
+    fn gen_int_to_str(&mut self) {
+        self.emit("__q_int_to_str:");
+        self.emit("\tpush %rbx");
+        self.emit("\tpush %r12");
+        self.emit("\tsub $8, %rsp");
+        self.emit("\tmov %rdi, %rbx");
+        self.emit("\tmov $16, %edi");
+        self.emit("\tcall __q_alloc");
+        self.emit("\tmov %rax, %r12");
+        self.emit("\tmov %rax, %rdi");
+        self.emit("\tmov $16, %esi");
+        self.emit("\tlea fmt_dec(%rip), %rdx");
+        self.emit("\tmov %ebx, %ecx");
+        self.emit("\txor %eax, %eax");
+        self.emit("\tcall snprintf@PLT");
+        self.emit("\tmov %r12, %rax");
+        self.emit("\tadd $8, %rsp");
+        self.emit("\tpop %r12");
+        self.emit("\tpop %rbx");
+        self.emit("\tret");
+    }
+
+    // %rdi = string -> %rax = int; accepts an optional '-' and at least one
+    // digit, wrapping to 32 bits like the optimizer's folding, and traps
+    // otherwise
+    fn gen_str_to_int(&mut self) {
+        self.emit("__q_str_to_int:");
+        self.emit("\txor %eax, %eax"); // accumulated value
+        self.emit("\txor %ecx, %ecx"); // negative?
+        self.emit("\tmovzbl (%rdi), %edx");
+        self.emit("\tcmp $45, %edx"); // '-'
+        self.emit("\tjne 1f");
+        self.emit("\tmov $1, %ecx");
+        self.emit("\tinc %rdi");
+        self.emit("\tmovzbl (%rdi), %edx");
+        self.emit("1:");
+        self.emit("\ttest %edx, %edx");
+        self.emit("\tjz __q_bad_int");
+        self.emit("2:");
+        self.emit("\tsub $48, %edx"); // '0'
+        self.emit("\tcmp $9, %edx");
+        self.emit("\tja __q_bad_int");
+        self.emit("\timul $10, %rax, %rax");
+        self.emit("\tadd %rdx, %rax");
+        self.emit("\tinc %rdi");
+        self.emit("\tmovzbl (%rdi), %edx");
+        self.emit("\ttest %edx, %edx");
+        self.emit("\tjnz 2b");
+        self.emit("\ttest %ecx, %ecx");
+        self.emit("\tjz 3f");
+        self.emit("\tneg %rax");
+        self.emit("3:");
+        self.emit("\tmovslq %eax, %rax");
+        self.emit("\tret");
+    }
+
+    // a trap can be jumped to from any depth, so it realigns %rsp itself
+    fn gen_trap(&mut self, label: &str, msg: &str) {
+        self.emit(format!("{}:", label));
+        self.emit("\tand $-16, %rsp");
+        self.emit(format!("\tlea {}(%rip), %rdi", msg));
+        self.emit("\txor %eax, %eax");
+        self.emit("\tcall printf@PLT");
+        self.emit("\tmov $1, %edi");
+        self.emit("\tcall exit@PLT");
+    }
+
+    // --- STATEMENT GENERATION ---
+
+    fn gen_stmt(&mut self, stmt: &Stmt) {
+        // no temporary outlives the statement that produced it
+        self.tmp_depth = 0;
+
+        match stmt {
+            // a const the optimizer could not inline is kept like a variable
+            Stmt::VarDeclaration { name, ty, value }
+            | Stmt::ConstDeclaration { name, ty, value } => {
+                let ty = self.layout.resolve(ty);
+                let r = self.gen_expr(value);
+                let offset = self.layout.declare_local(name, ty.clone());
+                self.store_var(&r, offset, &ty);
+            }
+
+            Stmt::GlobalDeclaration { name, ty, value } => {
+                let ty = self.layout.resolve(ty);
+                self.layout.declare_global(name, ty, value);
+            }
+
+            // strings print as text, bools as "true" / "false", the rest as ints
+            Stmt::Print(expr) => {
+                let ty = self.layout.expr_type(expr);
+                let r = self.gen_expr(expr);
+                let fmt = match ty {
+                    Type::String => "fmt_str",
+                    Type::Bool => {
+                        self.emit_bool_to_str(&r);
+                        "fmt_str"
+                    }
+                    _ => "fmt_int",
+                };
+                self.emit(format!("\tlea {}(%rip), %rdi", fmt));
+                self.emit(format!("\tmov {}, %rsi", r));
+                self.emit("\txor %eax, %eax");
+                self.emit("\tcall printf@PLT");
+                self.emit_count_print();
+            }
+
+            Stmt::PrintFormatted { format, args } => {
+                self.gen_print_formatted(format, args);
+                self.emit_count_print();
+            }
+
+            Stmt::Block(stmts) => self.gen_block(stmts),
+
+            Stmt::If { condition, then_block, else_block } => {
+                let cond_reg = self.gen_expr(condition);
+                let label_else = self.label("else");
+                let label_end = self.label("endif");
+
+                self.emit(format!("\ttest {}, {}", cond_reg, cond_reg));
+                self.emit(format!("\tjz {}", label_else));
+
+                self.gen_block(then_block);
+                self.emit(format!("\tjmp {}", label_end));
+
+                self.emit(format!("{}:", label_else));
+                if let Some(block) = else_block {
+                    self.gen_block(block);
+                }
+                self.emit(format!("{}:", label_end));
+            }
+
+            Stmt::ExprStmt(expr) => {
+                self.gen_expr(expr);
+            }
+
+            Stmt::StructDeclaration { name, fields } => self.layout.declare_struct(name, fields),
+
+            Stmt::EnumDeclaration { name, variants } => self.layout.declare_enum(name, variants),
+
+            Stmt::Match { scrutinee, arms } => self.gen_match(scrutinee, arms),
+
+            // libc exit runs atexit handlers and flushes stdio on the way out
+            Stmt::Exit(code) => {
+                let r = self.gen_expr(code);
+                self.emit(format!("\tmov {}, %rdi", r));
+                self.emit("\tcall exit@PLT");
+            }
+
+            Stmt::Return(code) => {
+                let r = self.gen_expr(code);
+                self.emit(format!("\tmov {}, %rax", r));
+                self.emit("\tjmp __q_main_exit");
+            }
+
+            // the whole failure message is known at compile time, so it is a
+            // single pooled string printed through fmt_str
+            Stmt::Assert { condition, message, line } => {
+                let r = self.gen_expr(condition);
+                let label_ok = self.label("assert_ok");
+                self.emit(format!("\ttest {}, {}", r, r));
+                self.emit(format!("\tjnz {}", label_ok));
+
+                let text = assert_message(&self.source_name, *line, message.as_deref());
+                let text = self.layout.string_label(&text);
+                self.emit("\tlea fmt_str(%rip), %rdi");
+                self.emit(format!("\tlea {}(%rip), %rsi", text));
+                self.emit("\txor %eax, %eax");
+                self.emit("\tcall printf@PLT");
+                self.emit(format!("\tmov ${}, %edi", ASSERT_FAILED_STATUS));
+                self.emit("\tcall exit@PLT");
+                self.emit(format!("{}:", label_ok));
+            }
+
+            // --- PAYWALL ---
+            // paywall(n) lets the program through until n prints have run
+            Stmt::Paywall(n) => {
+                let label_paid = self.label("paid");
+                self.emit("\tmov __q_print_count(%rip), %rax");
+                self.emit(format!("\tmovabs ${}, %rcx", n));
+                self.emit("\tcmp %rcx, %rax");
+                self.emit(format!("\tjl {}", label_paid));
+                self.runtime.insert("paywall");
+                self.emit("\tcall __q_paywall");
+                self.emit(format!("{}:", label_paid));
+            }
+        }
+    }
+
+    // one printf call: each `{}` becomes the argument's conversion and the
+    // format string is pooled like any other literal. the first five
+    // arguments go in %rsi-%r9 and the rest on the stack, 8 bytes each, with
+    // %al = 0 since no vector registers are used
+    fn gen_print_formatted(&mut self, format: &str, args: &[Expr]) {
+        let mut printf_format = String::new();
+        let mut pieces = format.split("{}");
+        printf_format.push_str(&pieces.next().unwrap_or_default().replace('%', "%%"));
+        for (arg, piece) in args.iter().zip(pieces) {
+            let spec = match self.layout.expr_type(arg) {
+                Type::String | Type::Bool => "%s",
+                _ => "%d",
+            };
+            printf_format.push_str(spec);
+            printf_format.push_str(&piece.replace('%', "%%"));
+        }
+        printf_format.push('\n');
+        let label = self.layout.string_label(&printf_format);
+
+        // evaluate into frame slots first: the calls an argument makes would
+        // clobber argument registers that were already filled
+        let base = self.layout.alloc_slot(args.len() * 8);
+        for (i, arg) in args.iter().enumerate() {
+            let is_bool = self.layout.expr_type(arg) == Type::Bool;
+            let r = self.gen_expr(arg);
+            if is_bool {
+                self.emit_bool_to_str(&r);
+            }
+            self.emit(format!("\tmov {}, {}(%rsp)", r, base + i * 8));
+            self.tmp_depth = 0;
+        }
+
+        let in_regs = ARG_REGS.len() - 1;
+        let spilled = args.len().saturating_sub(in_regs);
+        let area = (spilled * 8).div_ceil(16) * 16;
+        if area > 0 {
+            self.emit(format!("\tsub ${}, %rsp", area));
+        }
+        for i in in_regs..args.len() {
+            self.emit(format!("\tmov {}(%rsp), %rax", area + base + i * 8));
+            self.emit(format!("\tmov %rax, {}(%rsp)", (i - in_regs) * 8));
+        }
+        for i in 0..args.len().min(in_regs) {
+            self.emit(format!("\tmov {}(%rsp), {}", area + base + i * 8, ARG_REGS[i + 1]));
+        }
+
+        self.emit(format!("\tlea {}(%rip), %rdi", label));
+        self.emit("\txor %eax, %eax");
+        self.emit("\tcall printf@PLT");
+        if area > 0 {
+            self.emit(format!("\tadd ${}, %rsp", area));
+        }
+    }
+
+    fn gen_match(&mut self, scrutinee: &Expr, arms: &[MatchArm]) {
+        let enum_name = match self.layout.expr_type(scrutinee) {
+            Type::Enum(name) => name,
+            _ => unreachable!("semantic analysis guarantees an enum scrutinee"),
+        };
+        let variants = self.layout.variants(&enum_name).to_vec();
+        let r = self.gen_expr(scrutinee);
+
+        let label_end = self.label("endmatch");
+        let arm_labels: Vec<String> = arms.iter().map(|_| self.label("arm")).collect();
+
+        // the arm taken for each variant is the first one naming it, unless
+        // a `_` arm comes first and takes it
+        let explicit_arm = |variant: &str| {
+            arms.iter()
+                .position(|a| match &a.pattern {
+                    MatchPattern::Variant(v) => v == variant,
+                    MatchPattern::Wildcard => true,
+                })
+                .filter(|&i| arms[i].pattern != MatchPattern::Wildcard)
+        };
+        let wildcard = arms.iter().position(|a| a.pattern == MatchPattern::Wildcard);
+        let fallback = wildcard.map_or(label_end.clone(), |i| arm_labels[i].clone());
+
+        let explicit = arms.iter()
+            .filter(|a| matches!(a.pattern, MatchPattern::Variant(_)))
+            .count();
+
+        if explicit >= JUMP_TABLE_MIN_ARMS && explicit * 2 >= variants.len() {
+            // table of 32-bit offsets relative to the table itself
+            let table = self.label("jumptable");
+            self.emit(format!("\tlea {}(%rip), %rax", table));
+            self.emit(format!("\tmovslq (%rax,{},4), %rcx", r));
+            self.emit("\tadd %rcx, %rax");
+            self.emit("\tjmp *%rax");
+            self.emit(format!("{}:", table));
+            for v in &variants {
+                let target = explicit_arm(v).map_or(&fallback, |i| &arm_labels[i]);
+                self.emit(format!("\t.long {} - {}", target, table));
+            }
+        } else {
+            for (i, v) in variants.iter().enumerate() {
+                if let Some(arm) = explicit_arm(v) {
+                    self.emit(format!("\tcmp ${}, {}", i, r));
+                    self.emit(format!("\tje {}", arm_labels[arm]));
+                }
+            }
+            self.emit(format!("\tjmp {}", fallback));
+        }
+
+        for (arm, label) in arms.iter().zip(&arm_labels) {
+            self.emit(format!("{}:", label));
+            self.gen_block(&arm.body);
+            self.emit(format!("\tjmp {}", label_end));
+        }
+        self.emit(format!("{}:", label_end));
+    }
+
+    // --- EXPRESSION GENERATION ---
+
+    fn gen_expr(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::IntegerLiteral(n) => {
+                let r = self.alloc_tmp();
+                self.emit(format!("\tmov ${}, {}", n, r));
+                r
+            }
+            Expr::StringLiteral(lit) => {
+                let r = self.alloc_tmp();
+                let label = self.layout.string_label(lit);
+                self.emit(format!("\tlea {}(%rip), {}", label, r));
+                r
+            }
+            Expr::BooleanLiteral(b) => {
+                let r = self.alloc_tmp();
+                self.emit(format!("\tmov ${}, {}", *b as i32, r));
+                r
+            }
+            Expr::Identifier(name) => {
+                let r = self.alloc_tmp();
+                match self.layout.var(name) {
+                    Some(Var { slot: Slot::Global(label), .. }) => {
+                        let label = label.clone();
+                        self.emit(format!("\tmov {}(%rip), {}", label, r));
+                    }
+                    Some(Var { slot: Slot::Stack(offset), ty: Type::Array(..) | Type::Struct(_) }) => {
+                        // aggregates evaluate to the address of their first word
+                        let offset = *offset;
+                        self.emit(format!("\tlea {}(%rsp), {}", offset, r));
+                    }
+                    Some(Var { slot: Slot::Stack(offset), .. }) => {
+                        let offset = *offset;
+                        self.emit(format!("\tmov {}(%rsp), {}", offset, r));
+                    }
+                    None => self.emit(format!("\tmov (%rsp), {}", r)),
+                }
+                r
+            }
+            Expr::Assign { name, value } => {
+                let r = self.gen_expr(value);
+                match self.layout.var(name) {
+                    Some(Var { slot: Slot::Global(label), .. }) => {
+                        let label = label.clone();
+                        self.emit(format!("\tmov {}, {}(%rip)", r, label));
+                    }
+                    Some(Var { slot: Slot::Stack(offset), ty }) => {
+                        let (offset, ty) = (*offset, ty.clone());
+                        self.store_var(&r, offset, &ty);
+                    }
+                    None => {}
+                }
+                r
+            }
+            Expr::ArrayLiteral(elements) => {
+                let mark = self.tmp_depth;
+                let base = self.layout.alloc_slot(elements.len() * 8);
+                for (i, e) in elements.iter().enumerate() {
+                    let r = self.gen_expr(e);
+                    self.emit(format!("\tmov {}, {}(%rsp)", r, base + i * 8));
+                    self.tmp_depth = mark;
+                }
+                let r = self.alloc_tmp();
+                self.emit(format!("\tlea {}(%rsp), {}", base, r));
+                r
+            }
+            Expr::EnumVariant { enum_name, variant } => {
+                let r = self.alloc_tmp();
+                let index = self.layout.variant_index(enum_name, variant);
+                self.emit(format!("\tmov ${}, {}", index, r));
+                r
+            }
+            Expr::StructLiteral { name, fields } => {
+                let mark = self.tmp_depth;
+                let size = self.layout.slot_size(&Type::Struct(name.clone()));
+                let base = self.layout.alloc_slot(size);
+                for (field, e) in fields {
+                    let r = self.gen_expr(e);
+                    let (field_offset, _) = self.layout.field(name, field);
+                    self.emit(format!("\tmov {}, {}(%rsp)", r, base + field_offset));
+                    self.tmp_depth = mark;
+                }
+                let r = self.alloc_tmp();
+                self.emit(format!("\tlea {}(%rsp), {}", base, r));
+                r
+            }
+            Expr::Field { name, field } => {
+                let r = self.alloc_tmp();
+                let offset = self.layout.field_slot(name, field);
+                self.emit(format!("\tmov {}(%rsp), {}", offset, r));
+                r
+            }
+            Expr::FieldAssign { name, field, value } => {
+                let r = self.gen_expr(value);
+                let offset = self.layout.field_slot(name, field);
+                self.emit(format!("\tmov {}, {}(%rsp)", r, offset));
+                r
+            }
+            Expr::Index { name, index } => {
+                let mark = self.tmp_depth;
+                let (offset, len) = self.layout.array_var(name);
+                let r = self.gen_expr(index);
+                self.emit_bounds_check(&r, len);
+                self.emit(format!("\tmov {}(%rsp,{},8), {}", offset, r, r));
+                self.tmp_depth = mark + 1;
+                r
+            }
+            Expr::IndexAssign { name, index, value } => {
+                let mark = self.tmp_depth;
+                let (offset, len) = self.layout.array_var(name);
+                let r = self.gen_expr(index);
+                let v = self.gen_operand(&r, value);
+                self.emit_bounds_check(&r, len);
+                self.emit(format!("\tmov {}, {}(%rsp,{},8)", v, offset, r));
+                self.emit(format!("\tmov {}, {}", v, r));
+                self.tmp_depth = mark + 1;
+                r
+            }
+            Expr::Unary { op, operand } => {
+                let r = self.gen_expr(operand);
+                match op {
+                    UnOp::Neg => {
+                        self.emit(format!("\tneg {}", r));
+                        self.emit_wrap(&r);
+                    }
+                    UnOp::Not => self.emit(format!("\txor $1, {}", r)),
+                }
+                r
+            }
+            Expr::Binary { left, op: op @ (BinOp::And | BinOp::Or), right } => {
+                // the right operand is only evaluated (and a `maybe` there
+                // only sampled) when the left one does not decide the result
+                let mark = self.tmp_depth;
+                let dest = self.gen_expr(left);
+                let label_end = self.label("logic_end");
+
+                let branch = if *op == BinOp::And { "jz" } else { "jnz" };
+                self.emit(format!("\ttest {}, {}", dest, dest));
+                self.emit(format!("\t{} {}", branch, label_end));
+
+                self.release_if_full(mark);
+                let r = self.gen_expr(right);
+                if r != dest {
+                    self.emit(format!("\tmov {}, {}", r, dest));
+                }
+                self.emit(format!("{}:", label_end));
+
+                self.tmp_depth = mark + 1;
+                dest
+            }
+            Expr::Binary { left, op, right } => {
+                let mark = self.tmp_depth;
+                let operand_ty = self.layout.expr_type(left);
+                let r1 = self.gen_expr(left);
+                let r2 = self.gen_operand(&r1, right);
+                let dest = r1.clone();
+
+                match op {
+                    BinOp::Add if operand_ty == Type::String => {
+                        self.runtime.insert("concat");
+                        self.runtime.insert("alloc");
+                        self.emit(format!("\tmov {}, %rdi", r1));
+                        self.emit(format!("\tmov {}, %rsi", r2));
+                        self.emit("\tcall __q_concat");
+                        self.emit(format!("\tmov %rax, {}", dest));
+                    }
+                    BinOp::Add => {
+                        self.emit(format!("\tadd {}, {}", r2, dest));
+                        self.emit_wrap(&dest);
+                    }
+                    BinOp::Sub => {
+                        self.emit(format!("\tsub {}, {}", r2, dest));
+                        self.emit_wrap(&dest);
+                    }
+                    BinOp::Mul => {
+                        self.emit(format!("\timul {}, {}", r2, dest));
+                        self.emit_wrap(&dest);
+                    }
+                    // idiv leaves the quotient in %rax and the remainder in %rdx;
+                    // the operands are sign-extended 32-bit values, so the
+                    // 64-bit division cannot overflow
+                    BinOp::Div | BinOp::Mod => {
+                        self.runtime.insert("div_zero");
+                        self.emit(format!("\ttest {}, {}", r2, r2));
+                        self.emit("\tjz __q_div_zero");
+                        self.emit(format!("\tmov {}, %rax", r1));
+                        self.emit("\tcqo");
+                        self.emit(format!("\tidiv {}", r2));
+                        if *op == BinOp::Div {
+                            self.emit(format!("\tmov %rax, {}", dest));
+                            self.emit_wrap(&dest);
+                        } else {
+                            self.emit(format!("\tmov %rdx, {}", dest));
+                        }
+                    }
+                    BinOp::GreaterThan
+                    | BinOp::LessThan
+                    | BinOp::GreaterEqual
+                    | BinOp::LessEqual
+                    | BinOp::Equal
+                    | BinOp::NotEqual => {
+                        let cond = match op {
+                            BinOp::GreaterThan => "g",
+                            BinOp::LessThan => "l",
+                            BinOp::GreaterEqual => "ge",
+                            BinOp::LessEqual => "le",
+                            BinOp::Equal => "e",
+                            _ => "ne",
+                        };
+                        if operand_ty == Type::String {
+                            // strings compare by content: strcmp(a, b) <=> 0
+                            self.emit(format!("\tmov {}, %rdi", r1));
+                            self.emit(format!("\tmov {}, %rsi", r2));
+                            self.emit("\tcall strcmp@PLT");
+                            self.emit("\tcmp $0, %eax");
+                        } else {
+                            self.emit(format!("\tcmp {}, {}", r2, r1));
+                        }
+                        self.emit(format!("\tset{} %al", cond));
+                        self.emit(format!("\tmovzbq %al, {}", dest));
+                    }
+                    BinOp::And | BinOp::Or => unreachable!("lowered with branches above"),
+                }
+                self.tmp_depth = mark + 1;
+                dest
+            }
+            Expr::Conditional { condition, then_expr, else_expr } => {
+                let mark = self.tmp_depth;
+                let dest = self.gen_expr(condition);
+
+                // the arms are evaluated while dest and a still hold values,
+                // so there must be a register left for each to start in
+                let room = self.tmp_depth + 2 <= TMP_REGS.len();
+                if room && is_pure(then_expr) && is_pure(else_expr) {
+                    // both arms are cheap to evaluate and cannot be observed,
+                    // so compute them both and pick one without branching
+                    let a = self.gen_expr(then_expr);
+                    let b = self.gen_expr(else_expr);
+                    self.emit(format!("\ttest {}, {}", dest, dest));
+                    self.emit(format!("\tcmovnz {}, {}", a, b));
+                    self.emit(format!("\tmov {}, {}", b, dest));
+                } else {
+                    let label_else = self.label("cond_else");
+                    let label_end = self.label("cond_end");
+                    self.emit(format!("\ttest {}, {}", dest, dest));
+                    self.emit(format!("\tjz {}", label_else));
+
+                    self.release_if_full(mark);
+                    let a = self.gen_expr(then_expr);
+                    if a != dest {
+                        self.emit(format!("\tmov {}, {}", a, dest));
+                    }
+                    self.emit(format!("\tjmp {}", label_end));
+
+                    self.tmp_depth = mark + 1;
+                    self.emit(format!("{}:", label_else));
+                    self.release_if_full(mark);
+                    let b = self.gen_expr(else_expr);
+                    if b != dest {
+                        self.emit(format!("\tmov {}, {}", b, dest));
+                    }
+                    self.emit(format!("{}:", label_end));
+                }
+
+                self.tmp_depth = mark + 1;
+                dest
+            }
+            Expr::Conversion { target, operand } => {
+                let from = self.layout.expr_type(operand);
+                let r = self.gen_expr(operand);
+                match (target, from) {
+                    (TypeName::Int, Type::String) => {
+                        self.runtime.insert("str_to_int");
+                        self.emit(format!("\tmov {}, %rdi", r));
+                        self.emit("\tcall __q_str_to_int");
+                        self.emit(format!("\tmov %rax, {}", r));
+                    }
+                    (TypeName::Bool, Type::Int) => {
+                        self.emit(format!("\ttest {}, {}", r, r));
+                        self.emit("\tsetne %al");
+                        self.emit(format!("\tmovzbq %al, {}", r));
+                    }
+                    (TypeName::String, Type::Int) => {
+                        self.runtime.insert("int_to_str");
+                        self.runtime.insert("alloc");
+                        self.emit(format!("\tmov {}, %rdi", r));
+                        self.emit("\tcall __q_int_to_str");
+                        self.emit(format!("\tmov %rax, {}", r));
+                    }
+                    (TypeName::String, Type::Bool) => self.emit_bool_to_str(&r),
+                    // same type, or bool -> int where 0 / 1 is already the value
+                    _ => {}
+                }
+                r
+            }
+            Expr::Call { name, args } => {
+                // arguments are evaluated into temporaries first, since
+                // evaluating one may call out and clobber the argument registers
+                let mark = self.tmp_depth;
+                if mark + args.len() <= TMP_REGS.len() {
+                    let regs: Vec<String> = args.iter().map(|a| self.gen_expr(a)).collect();
+                    for (r, arg_reg) in regs.iter().zip(ARG_REGS) {
+                        self.emit(format!("\tmov {}, {}", r, arg_reg));
+                    }
+                } else {
+                    // too few temporaries left to hold them all: they wait in
+                    // the frame instead
+                    let base = self.layout.alloc_slot(args.len() * 8);
+                    for (i, a) in args.iter().enumerate() {
+                        let r = self.gen_expr(a);
+                        self.emit(format!("\tmov {}, {}(%rsp)", r, base + i * 8));
+                        self.tmp_depth = mark;
+                    }
+                    for (i, arg_reg) in ARG_REGS.iter().enumerate().take(args.len()) {
+                        self.emit(format!("\tmov {}(%rsp), {}", base + i * 8, arg_reg));
+                    }
+                }
+
+                // the routine each builtin calls and the runtime support it needs
+                let (target, needs): (&str, &[&'static str]) = match name.as_str() {
+                    "len" => ("strlen@PLT", &[]),
+                    "substr" => ("__q_substr", &["substr", "alloc"]),
+                    "index_of" => ("__q_index_of", &["index_of"]),
+                    "to_upper" => ("__q_to_upper", &["to_upper", "alloc"]),
+                    "char_at" => ("__q_char_at", &["char_at", "alloc"]),
+                    "read_line" => ("__q_read_line", &["read_line", "alloc"]),
+                    "read_int" => ("__q_read_int", &["read_int", "read_line", "str_to_int", "alloc"]),
+                    "args" => ("__q_args", &["args"]),
+                    "arg_count" => ("__q_arg_count", &["args"]),
+                    builtin => unreachable!("semantic analysis guarantees '{}' is a builtin", builtin),
+                };
+                self.runtime.extend(needs);
+                self.emit(format!("\tcall {}", target));
+
+                self.tmp_depth = mark;
+                let r = self.alloc_tmp();
+                self.emit(format!("\tmov %rax, {}", r));
+                r
+            }
+            Expr::Maybe => {
+                self.runtime.insert("rand");
+                let r = self.alloc_tmp();
+                self.emit("\tcall __q_rand");
+                self.emit(format!("\tmov %rax, {}", r));
+                r
+            }
+        }
+    }
+
+    // --- VARIABLES ---
+
+    fn gen_block(&mut self, stmts: &[Stmt]) {
+        self.layout.enter_scope();
+        for s in stmts {
+            self.gen_stmt(s);
+        }
+        self.layout.exit_scope();
+    }
+
+    // scalars are stored directly; for arrays and structs `r` holds the
+    // address of the source words, which are copied into the slot one by one
+    fn store_var(&mut self, r: &str, offset: usize, ty: &Type) {
+        if let Type::Array(..) | Type::Struct(_) = ty {
+            for i in 0..self.layout.slot_size(ty) / 8 {
+                self.emit(format!("\tmov {}({}), %rax", i * 8, r));
+                self.emit(format!("\tmov %rax, {}(%rsp)", offset + i * 8));
+            }
+        } else {
+            self.emit(format!("\tmov {}, {}(%rsp)", r, offset));
+        }
+    }
+
+    // unsigned compare, so a negative index fails the check as well
+    fn emit_bounds_check(&mut self, index: &str, len: usize) {
+        self.runtime.insert("bounds");
+        self.emit(format!("\tcmp ${}, {}", len, index));
+        self.emit("\tjae __q_bounds");
+    }
+
+    // replaces the bool in `r` with the address of "true" or "false"
+    fn emit_bool_to_str(&mut self, r: &str) {
+        let t = self.layout.string_label("true");
+        let f = self.layout.string_label("false");
+        self.emit(format!("\tlea {}(%rip), %rax", t));
+        self.emit(format!("\tlea {}(%rip), %rcx", f));
+        self.emit(format!("\ttest {}, {}", r, r));
+        self.emit("\tcmovz %rcx, %rax");
+        self.emit(format!("\tmov %rax, {}", r));
+    }
+
+    fn emit_count_print(&mut self) {
+        if !self.metered {
+            return;
+        }
+        self.emit("\tincq __q_print_count(%rip)");
+    }
+
+    fn alloc_tmp(&mut self) -> String {
+        let r = TMP_REGS
+            .get(self.tmp_depth)
+            .expect("operands are spilled before the temporary pool runs out");
+        self.tmp_depth += 1;
+        r.to_string()
+    }
+
+    // evaluates `expr` while `live`, the newest temporary, still holds a
+    // value. when `live` took the last register, it waits in the frame so
+    // `expr` can start in its register, and the result is handed back in
+    // SPILL_REG for the caller's next instruction
+    fn gen_operand(&mut self, live: &str, expr: &Expr) -> String {
+        if self.tmp_depth < TMP_REGS.len() {
+            return self.gen_expr(expr);
+        }
+        let slot = self.layout.alloc_slot(8);
+        self.emit(format!("\tmov {}, {}(%rsp)", live, slot));
+        self.tmp_depth -= 1;
+        let r = self.gen_expr(expr);
+        self.emit(format!("\tmov {}, {}", r, SPILL_REG));
+        self.emit(format!("\tmov {}(%rsp), {}", slot, live));
+        SPILL_REG.to_string()
+    }
+
+    // after a branch on the temporary at `mark`, the taken path overwrites
+    // it anyway; with the pool full it is handed back so that path has a
+    // register to start in
+    fn release_if_full(&mut self, mark: usize) {
+        if self.tmp_depth == TMP_REGS.len() {
+            self.tmp_depth = mark;
+        }
+    }
+
+    // ints are 32-bit: sign-extend the low word so overflow wraps the same
+    // way the optimizer's constant folding does
+    fn emit_wrap(&mut self, r: &str) {
+        self.emit(format!("\tmovslq {}, {}", low_dword(r), r));
+    }
+
+    fn label(&mut self, prefix: &str) -> String {
+        let l = format!("{}_{}", prefix, self.label_counter);
+        self.label_counter += 1;
+        l
+    }
+
+    fn emit(&mut self, asm: impl Into<String>) {
+        self.out.push_str(&asm.into());
+        self.out.push('\n');
+    }
+}
+
+// the 32-bit name of a temporary register: %rbx -> %ebx, %r12 -> %r12d
+fn low_dword(r: &str) -> String {
+    match r.strip_prefix("%r") {
+        Some(n) if n.starts_with(|c: char| c.is_ascii_digit()) => format!("{}d", r),
+        Some(n) => format!("%e{}", n),
+        None => unreachable!("temporaries are 64-bit registers"),
+    }
+}
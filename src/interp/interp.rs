@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use crate::interp::runtime::{Halt, Runtime, Value};
+use crate::parsing::ast::{Expr, Stmt, BinOp, UnOp, MatchPattern};
+
+pub use crate::interp::runtime::{RunOptions, RunResult};
+
+// Executes a checked program directly, with the semantics of the arm64
+// backend: 32-bit wrapping ints, the runtime's checks and messages, and
+// the same exit statuses. Input comes from the given string instead of
+// stdin and output is captured instead of printed.
+pub struct Interpreter {
+    runtime: Runtime,
+    scopes: Vec<HashMap<String, Value>>,
+    enums: HashMap<String, Vec<String>>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self {
+            runtime: Runtime::new(),
+            scopes: vec![HashMap::new()],
+            enums: HashMap::new(),
+        }
+    }
+
+    pub fn with_source_name(mut self, name: &str) -> Self {
+        self.runtime.source_name = name.to_string();
+        self
+    }
+
+    pub fn with_options(mut self, options: RunOptions) -> Self {
+        self.runtime.options = options;
+        self
+    }
+
+    // expects a semantically valid program, optimized or not
+    pub fn run(mut self, stmts: &[Stmt]) -> RunResult {
+        self.runtime.start();
+        let result = self.exec_stmts(stmts);
+        self.runtime.finish(result)
+    }
+
+    // -------- STATEMENTS --------
+
+    fn exec_stmts(&mut self, stmts: &[Stmt]) -> Result<(), Halt> {
+        for stmt in stmts {
+            self.exec_stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn exec_block(&mut self, stmts: &[Stmt]) -> Result<(), Halt> {
+        self.scopes.push(HashMap::new());
+        let result = self.exec_stmts(stmts);
+        self.scopes.pop();
+        result
+    }
+
+    fn exec_stmt(&mut self, stmt: &Stmt) -> Result<(), Halt> {
+        match stmt {
+            Stmt::Print(expr) => {
+                let value = self.eval(expr)?;
+                let text = self.runtime.display(&value);
+                self.runtime.print(&text);
+            }
+
+            Stmt::PrintFormatted { format, args } => {
+                let mut pieces = format.split("{}");
+                let mut text = pieces.next().unwrap_or_default().to_string();
+                for (arg, piece) in args.iter().zip(pieces) {
+                    let value = self.eval(arg)?;
+                    text.push_str(&self.runtime.display(&value));
+                    text.push_str(piece);
+                }
+                self.runtime.print(&text);
+            }
+
+            Stmt::VarDeclaration { name, value, .. }
+            | Stmt::ConstDeclaration { name, value, .. }
+            | Stmt::GlobalDeclaration { name, value, .. } => {
+                let value = self.eval(value)?;
+                self.scopes.last_mut().unwrap().insert(name.clone(), value);
+            }
+
+            Stmt::If { condition, then_block, else_block } => {
+                if self.eval(condition)?.bool() {
+                    self.exec_block(then_block)?;
+                } else if let Some(else_block) = else_block {
+                    self.exec_block(else_block)?;
+                }
+            }
+
+            Stmt::Block(stmts) => self.exec_block(stmts)?,
+
+            Stmt::ExprStmt(expr) => {
+                self.eval(expr)?;
+            }
+
+            Stmt::StructDeclaration { .. } => {}
+
+            Stmt::EnumDeclaration { name, variants } => {
+                self.enums.insert(name.clone(), variants.clone());
+            }
+
+            Stmt::Match { scrutinee, arms } => {
+                let Value::Enum(enum_name, index) = self.eval(scrutinee)? else {
+                    unreachable!("semantic analysis guarantees an enum scrutinee")
+                };
+                let arm = arms.iter().find(|arm| match &arm.pattern {
+                    MatchPattern::Variant(v) => self.variant_index(&enum_name, v) == index,
+                    MatchPattern::Wildcard => true,
+                });
+                if let Some(arm) = arm {
+                    self.exec_block(&arm.body)?;
+                }
+            }
+
+            Stmt::Paywall(n) => self.runtime.paywall(*n)?,
+
+            Stmt::Exit(code) | Stmt::Return(code) => {
+                let status = self.eval(code)?.int();
+                return Err(Halt::Exit(status));
+            }
+
+            Stmt::Assert { condition, message, line } => {
+                if !self.eval(condition)?.bool() {
+                    self.runtime.assert_failed(*line, message.as_deref())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // -------- EXPRESSIONS --------
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, Halt> {
+        match expr {
+            Expr::IntegerLiteral(n) => Ok(Value::Int(*n)),
+            Expr::StringLiteral(s) => Ok(Value::String(s.clone())),
+            Expr::BooleanLiteral(b) => Ok(Value::Bool(*b)),
+            Expr::Identifier(name) => Ok(self.var(name).clone()),
+            Expr::Maybe => Ok(Value::Bool(self.runtime.flip())),
+
+            Expr::Unary { op, operand } => {
+                let value = self.eval(operand)?;
+                Ok(match op {
+                    UnOp::Neg => Value::Int(value.int().wrapping_neg()),
+                    UnOp::Not => Value::Bool(!value.bool()),
+                })
+            }
+
+            // the right operand is only evaluated when it decides the result
+            Expr::Binary { left, op: BinOp::And, right } => {
+                Ok(Value::Bool(self.eval(left)?.bool() && self.eval(right)?.bool()))
+            }
+            Expr::Binary { left, op: BinOp::Or, right } => {
+                Ok(Value::Bool(self.eval(left)?.bool() || self.eval(right)?.bool()))
+            }
+            Expr::Binary { left, op, right } => {
+                let l = self.eval(left)?;
+                let r = self.eval(right)?;
+                self.runtime.binary(l, op, r)
+            }
+
+            Expr::Assign { name, value } => {
+                let value = self.eval(value)?;
+                *self.var_mut(name) = value;
+                Ok(Value::Int(0))
+            }
+
+            Expr::ArrayLiteral(elements) => {
+                let mut values = Vec::new();
+                for element in elements {
+                    values.push(self.eval(element)?);
+                }
+                Ok(Value::Array(values))
+            }
+
+            Expr::Index { name, index } => {
+                let i = self.eval(index)?.int();
+                let Value::Array(elements) = self.var(name) else {
+                    unreachable!("semantic analysis guarantees '{}' is an array", name)
+                };
+                match usize::try_from(i).ok().and_then(|i| elements.get(i)) {
+                    Some(value) => Ok(value.clone()),
+                    None => self.runtime.trap("runtime error: array index out of bounds"),
+                }
+            }
+
+            Expr::IndexAssign { name, index, value } => {
+                let i = self.eval(index)?.int();
+                let value = self.eval(value)?;
+                let Value::Array(elements) = self.var_mut(name) else {
+                    unreachable!("semantic analysis guarantees '{}' is an array", name)
+                };
+                match usize::try_from(i).ok().and_then(|i| elements.get_mut(i)) {
+                    Some(slot) => *slot = value,
+                    None => return self.runtime.trap("runtime error: array index out of bounds"),
+                }
+                Ok(Value::Int(0))
+            }
+
+            Expr::StructLiteral { fields, .. } => {
+                let mut values = Vec::new();
+                for (field, value) in fields {
+                    values.push((field.clone(), self.eval(value)?));
+                }
+                Ok(Value::Struct(values))
+            }
+
+            Expr::Field { name, field } => {
+                let Value::Struct(fields) = self.var(name) else {
+                    unreachable!("semantic analysis guarantees '{}' is a struct", name)
+                };
+                let (_, value) = fields
+                    .iter()
+                    .find(|(f, _)| f == field)
+                    .expect("semantic analysis guarantees the field exists");
+                Ok(value.clone())
+            }
+
+            Expr::FieldAssign { name, field, value } => {
+                let value = self.eval(value)?;
+                let Value::Struct(fields) = self.var_mut(name) else {
+                    unreachable!("semantic analysis guarantees '{}' is a struct", name)
+                };
+                let slot = fields
+                    .iter_mut()
+                    .find(|(f, _)| f == field)
+                    .expect("semantic analysis guarantees the field exists");
+                slot.1 = value;
+                Ok(Value::Int(0))
+            }
+
+            Expr::EnumVariant { enum_name, variant } => {
+                Ok(Value::Enum(enum_name.clone(), self.variant_index(enum_name, variant)))
+            }
+
+            Expr::Conditional { condition, then_expr, else_expr } => {
+                if self.eval(condition)?.bool() {
+                    self.eval(then_expr)
+                } else {
+                    self.eval(else_expr)
+                }
+            }
+
+            Expr::Conversion { target, operand } => {
+                let value = self.eval(operand)?;
+                self.runtime.convert(target, value)
+            }
+
+            Expr::Call { name, args } => {
+                let mut values = Vec::new();
+                for arg in args {
+                    values.push(self.eval(arg)?);
+                }
+                self.runtime.call(name, &values)
+            }
+        }
+    }
+
+    fn variant_index(&self, enum_name: &str, variant: &str) -> usize {
+        self.enums[enum_name]
+            .iter()
+            .position(|v| v == variant)
+            .expect("semantic analysis guarantees the variant exists")
+    }
+
+    fn var(&self, name: &str) -> &Value {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .expect("semantic analysis guarantees the variable is declared")
+    }
+
+    fn var_mut(&mut self, name: &str) -> &mut Value {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+            .expect("semantic analysis guarantees the variable is declared")
+    }
+}
@@ -0,0 +1,3 @@
+#[allow(clippy::module_inception)]
+pub mod interp;
+pub mod runtime;
\ No newline at end of file
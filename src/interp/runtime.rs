@@ -0,0 +1,363 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::codegen::common::{
+    assert_message, fnv1a, ASSERT_FAILED_STATUS, DEFAULT_PAYWALL_KEY, DEFAULT_PAYWALL_RETRIES,
+};
+use crate::optimizer::optimizer::parse_int;
+use crate::parsing::ast::{BinOp, TypeName};
+
+// bytes the paywall's fgets keeps from a line, and read_line's
+const PAYWALL_LINE_MAX: usize = 63;
+const READ_LINE_MAX: usize = 1023;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Int(i32),
+    Bool(bool),
+    String(String),
+    // enum name and the variant's index, which is how the backend stores it
+    Enum(String, usize),
+    Array(Vec<Value>),
+    Struct(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub(crate) fn int(&self) -> i32 {
+        match self {
+            Value::Int(n) => *n,
+            _ => unreachable!("semantic analysis guarantees an int"),
+        }
+    }
+
+    pub(crate) fn bool(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            _ => unreachable!("semantic analysis guarantees a bool"),
+        }
+    }
+
+    pub(crate) fn string(&self) -> &str {
+        match self {
+            Value::String(s) => s,
+            _ => unreachable!("semantic analysis guarantees a string"),
+        }
+    }
+}
+
+// why the program stopped before running off its end
+pub(crate) enum Halt {
+    Exit(i32),
+}
+
+// what a program printed and the status it exited with, truncated to a byte
+// the way the process would report it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunResult {
+    pub stdout: String,
+    pub status: i32,
+}
+
+// The same xorshift64* generator, splitmix64 seeding and bit choice as the
+// arm64 runtime's __q_rand, so a seed flips every `maybe` the same way in
+// both backends.
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        Self { state: if z == 0 { 0x9e3779b97f4a7c15 } else { z } }
+    }
+
+    fn flip(&mut self) -> bool {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545f4914f6cdd1d) >> 63 == 1
+    }
+}
+
+// How the interpreter or the VM runs a program: the seed of `maybe`, the
+// program's input and arguments, and the paywall's settings.
+#[derive(Clone)]
+pub struct RunOptions {
+    seed: Option<u64>,
+    input: String,
+    // argv after the program name, which is the program's source name
+    args: Vec<String>,
+    paywall_key_hash: u64,
+    paywall_retries: u32,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RunOptions {
+    pub fn new() -> Self {
+        Self {
+            seed: None,
+            input: String::new(),
+            args: Vec::new(),
+            paywall_key_hash: fnv1a(DEFAULT_PAYWALL_KEY),
+            paywall_retries: DEFAULT_PAYWALL_RETRIES,
+        }
+    }
+
+    // without a seed `maybe` is seeded from the clock
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_input(mut self, input: &str) -> Self {
+        self.input = input.to_string();
+        self
+    }
+
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    pub fn with_paywall_key(mut self, key: &str) -> Self {
+        self.paywall_key_hash = fnv1a(key);
+        self
+    }
+
+    // at least one attempt is always allowed
+    pub fn with_paywall_retries(mut self, retries: u32) -> Self {
+        self.paywall_retries = retries.max(1);
+        self
+    }
+}
+
+// The state a running program shares between the interpreter and the VM:
+// its options, its output and the `maybe` generator. The operations on
+// values live here too, so both execute them alike.
+pub(crate) struct Runtime {
+    // source file named in assertion failure messages, and argv[0]
+    pub(crate) source_name: String,
+    pub(crate) options: RunOptions,
+
+    rng: Rng,
+    input_pos: usize,
+    prints: usize,
+    stdout: String,
+}
+
+impl Runtime {
+    pub(crate) fn new() -> Self {
+        Self {
+            source_name: "<input>".to_string(),
+            options: RunOptions::new(),
+            rng: Rng::new(0),
+            input_pos: 0,
+            prints: 0,
+            stdout: String::new(),
+        }
+    }
+
+    // seeds `maybe`, from the clock when no seed was given
+    pub(crate) fn start(&mut self) {
+        let seed = self.options.seed.unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+        });
+        self.rng = Rng::new(seed);
+    }
+
+    pub(crate) fn finish(self, result: Result<(), Halt>) -> RunResult {
+        let status = match result {
+            Ok(()) => 0,
+            Err(Halt::Exit(status)) => status,
+        };
+        RunResult { stdout: self.stdout, status: status & 0xff }
+    }
+
+    pub(crate) fn flip(&mut self) -> bool {
+        self.rng.flip()
+    }
+
+    pub(crate) fn print(&mut self, text: &str) {
+        self.stdout.push_str(text);
+        self.stdout.push('\n');
+        self.prints += 1;
+    }
+
+    // strings as text, bools as true / false, ints and enums as numbers
+    pub(crate) fn display(&self, value: &Value) -> String {
+        match value {
+            Value::Int(n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::String(s) => s.clone(),
+            Value::Enum(_, i) => i.to_string(),
+            Value::Array(_) | Value::Struct(_) => {
+                unreachable!("semantic analysis guarantees a printable value")
+            }
+        }
+    }
+
+    // paywall(n): lets the program through until n prints have run, then
+    // asks for the key until its hash matches, keeping at most 63 bytes of
+    // each line like the runtime's bounded fgets
+    pub(crate) fn paywall(&mut self, n: i64) -> Result<(), Halt> {
+        if (self.prints as i64) < n {
+            return Ok(());
+        }
+        for _ in 0..self.options.paywall_retries {
+            self.stdout.push_str("free trial over pew pew, enter the key to continue: ");
+            let Some(line) = self.read_input_line(usize::MAX) else {
+                break;
+            };
+            let key = &line.as_bytes()[..line.len().min(PAYWALL_LINE_MAX)];
+            if fnv1a(&String::from_utf8_lossy(key)) == self.options.paywall_key_hash {
+                return Ok(());
+            }
+        }
+        self.trap("wrong key, access denied")
+    }
+
+    // the next line of input without its newline, at most `max` bytes of it;
+    // the rest of a longer line is left for the next read. None at the end
+    fn read_input_line(&mut self, max: usize) -> Option<String> {
+        let rest = &self.options.input[self.input_pos..];
+        if rest.is_empty() {
+            return None;
+        }
+        let line_len = rest.find('\n').map_or(rest.len(), |i| i + 1);
+        let mut take = line_len.min(max);
+        while !rest.is_char_boundary(take) {
+            take -= 1;
+        }
+        self.input_pos += take;
+        Some(rest[..take].strip_suffix('\n').unwrap_or(&rest[..take]).to_string())
+    }
+
+    pub(crate) fn trap<T>(&mut self, message: &str) -> Result<T, Halt> {
+        self.stdout.push_str(message);
+        self.stdout.push('\n');
+        Err(Halt::Exit(1))
+    }
+
+    pub(crate) fn assert_failed(&mut self, line: usize, message: Option<&str>) -> Result<(), Halt> {
+        let text = assert_message(&self.source_name, line, message);
+        self.stdout.push_str(&text);
+        self.stdout.push('\n');
+        Err(Halt::Exit(ASSERT_FAILED_STATUS))
+    }
+
+    // int(x), bool(x) and string(x)
+    pub(crate) fn convert(&mut self, target: &TypeName, value: Value) -> Result<Value, Halt> {
+        match (target, value) {
+            (TypeName::Int, Value::String(s)) => match parse_int(&s) {
+                Some(n) => Ok(Value::Int(n)),
+                None => self.trap("runtime error: invalid int conversion"),
+            },
+            (TypeName::Int, Value::Bool(b)) => Ok(Value::Int(b as i32)),
+            (TypeName::Bool, Value::Int(n)) => Ok(Value::Bool(n != 0)),
+            (TypeName::String, Value::Int(n)) => Ok(Value::String(n.to_string())),
+            (TypeName::String, Value::Bool(b)) => Ok(Value::String(b.to_string())),
+            (_, value) => Ok(value),
+        }
+    }
+
+    // ints wrap at 32 bits; strings compare bytewise like strcmp
+    pub(crate) fn binary(&mut self, l: Value, op: &BinOp, r: Value) -> Result<Value, Halt> {
+        if let (Value::String(a), BinOp::Add, Value::String(b)) = (&l, op, &r) {
+            return Ok(Value::String(format!("{}{}", a, b)));
+        }
+
+        let ordering = match (&l, &r) {
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Enum(_, a), Value::Enum(_, b)) => a.cmp(b),
+            _ => l.int().cmp(&r.int()),
+        };
+
+        let (a, b) = match (&l, &r) {
+            (Value::Int(a), Value::Int(b)) => (*a, *b),
+            _ => (0, 0),
+        };
+
+        Ok(match op {
+            BinOp::Add => Value::Int(a.wrapping_add(b)),
+            BinOp::Sub => Value::Int(a.wrapping_sub(b)),
+            BinOp::Mul => Value::Int(a.wrapping_mul(b)),
+            BinOp::Div | BinOp::Mod if b == 0 => return self.trap("runtime error: division by zero"),
+            BinOp::Div => Value::Int(a.wrapping_div(b)),
+            BinOp::Mod => Value::Int(a.wrapping_rem(b)),
+            BinOp::GreaterThan => Value::Bool(ordering.is_gt()),
+            BinOp::LessThan => Value::Bool(ordering.is_lt()),
+            BinOp::GreaterEqual => Value::Bool(ordering.is_ge()),
+            BinOp::LessEqual => Value::Bool(ordering.is_le()),
+            BinOp::Equal => Value::Bool(ordering.is_eq()),
+            BinOp::NotEqual => Value::Bool(ordering.is_ne()),
+            BinOp::And | BinOp::Or => unreachable!("logical operators short-circuit above"),
+        })
+    }
+
+    // strings are treated as bytes, like the runtime helpers
+    pub(crate) fn call(&mut self, name: &str, args: &[Value]) -> Result<Value, Halt> {
+        match name {
+            "len" => Ok(Value::Int(args[0].string().len() as i32)),
+
+            // start and length are clamped to the string
+            "substr" => {
+                let s = args[0].string().as_bytes();
+                let start = args[1].int().clamp(0, s.len() as i32) as usize;
+                let len = args[2].int().clamp(0, (s.len() - start) as i32) as usize;
+                Ok(Value::String(String::from_utf8_lossy(&s[start..start + len]).into_owned()))
+            }
+
+            "index_of" => {
+                let found = args[0].string().find(args[1].string());
+                Ok(Value::Int(found.map_or(-1, |i| i as i32)))
+            }
+
+            "to_upper" => Ok(Value::String(args[0].string().to_ascii_uppercase())),
+
+            "char_at" => {
+                let s = args[0].string().as_bytes();
+                match usize::try_from(args[1].int()).ok().and_then(|i| s.get(i)) {
+                    Some(byte) => Ok(Value::String(String::from_utf8_lossy(&[*byte]).into_owned())),
+                    None => self.trap("runtime error: string index out of bounds"),
+                }
+            }
+
+            "read_line" => {
+                let line = self.read_input_line(READ_LINE_MAX).unwrap_or_default();
+                Ok(Value::String(line))
+            }
+
+            "read_int" => {
+                let line = self.read_input_line(READ_LINE_MAX).unwrap_or_default();
+                match parse_int(&line) {
+                    Some(n) => Ok(Value::Int(n)),
+                    None => self.trap("runtime error: invalid int conversion"),
+                }
+            }
+
+            // argv[0] is the program itself
+            "args" => {
+                let i = args[0].int();
+                let arg = match i {
+                    0 => Some(self.source_name.clone()),
+                    _ => usize::try_from(i - 1).ok().and_then(|i| self.options.args.get(i)).cloned(),
+                };
+                match arg {
+                    Some(arg) => Ok(Value::String(arg)),
+                    None => self.trap("runtime error: argument index out of range"),
+                }
+            }
+
+            "arg_count" => Ok(Value::Int(self.options.args.len() as i32 + 1)),
+
+            builtin => unreachable!("semantic analysis guarantees '{}' is a builtin", builtin),
+        }
+    }
+}
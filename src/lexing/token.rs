@@ -1,125 +1,223 @@
-#[derive(Debug, Clone, PartialEq)]
-pub enum Token {
-    // keywords
-    Print,
-    If,
-    Else,
-    Int,
-    Maybe,
-    Paywall,
-
-    // identifiers & literals
-    Identifier(String),
-    IntegerLiteral(i64),
-    StringLiteral(String),
-
-    // operators
-    Plus,
-    Minus,
-    Assign,
-    GreaterThan,
-    LessThan,
-
-    // punctuation
-    SemiColon,
-    LeftParen,
-    RightParen,
-    LeftBrace,
-    RightBrace,
-}
-
-impl Token {
-    pub fn get_token(token_type: &str, value: Option<&str>) -> Token {
-        match token_type {
-            // keywords
-            "Print" => Token::Print,
-            "If" => Token::If,
-            "Else" => Token::Else,
-            "Int" => Token::Int,
-            "Maybe" => Token::Maybe,
-            "Paywall" => Token::Paywall,
-
-            // literals
-            "IntegerLiteral" => {
-                Token::IntegerLiteral(
-                    value
-                        .expect("IntegerLiteral requires a value")
-                        .parse::<i64>()
-                        .expect("Invalid integer literal"),
-                )
-            }
-            "StringLiteral" => {
-                Token::StringLiteral(
-                    value
-                        .expect("StringLiteral requires a value")
-                        .to_string(),
-                )
-            }
-
-            // identifiers
-            "Identifier" => {
-                Token::Identifier(
-                    value
-                        .expect("Identifier requires a value")
-                        .to_string(),
-                )
-            }
-
-            // operators
-            "Plus" => Token::Plus,
-            "Minus" => Token::Minus,
-            "Assign" => Token::Assign,
-
-            // punctuation
-            "SemiColon" => Token::SemiColon,
-            "LeftParen" => Token::LeftParen,
-            "RightParen" => Token::RightParen,
-            "LeftBrace" => Token::LeftBrace,
-            "RightBrace" => Token::RightBrace,
-
-            // logical operators
-            "GreaterThan" => Token::GreaterThan,
-            "LessThan" => Token::LessThan,
-
-            _ => panic!("invalid token type {}", token_type),
-        }
-    }
-
-    pub fn get_token_regex(token_type: &str) -> String {
-        match token_type {
-            // keywords
-            "Print" => r"\bprint\b",
-            "If" => r"\bif\b",
-            "Else" => r"\belse\b",
-            "Int" => r"\bint\b",
-            "Maybe" => r"\bmaybe\b",
-            "Paywall" => r"\bpaywall\b",
-
-            // literals
-            "IntegerLiteral" => r"\d+",
-            "StringLiteral" => r#""[^"]*""#,
-
-            // identifiers
-            "Identifier" => r"[a-zA-Z_][a-zA-Z0-9_]*",
-
-            // operators
-            "Plus" => r"\+",
-            "Minus" => r"-",
-            "Assign" => r"=",
-
-            // punctuation
-            "SemiColon" => r";",
-            "LeftParen" => r"\(",
-            "RightParen" => r"\)",
-            "LeftBrace" => r"\{",
-            "RightBrace" => r"\}",
-
-            // logical operators
-            "GreaterThan" => r">",
-            "LessThan" => r"<",
-
-            _ => panic!("invalid token type: {}", token_type),
-        }
-        .to_string()
-    }
-}
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    // keywords
+    Print,
+    If,
+    Else,
+    Int,
+    Bool,
+    String,
+    Struct,
+    Enum,
+    Match,
+    Maybe,
+    Paywall,
+    True,
+    False,
+    Const,
+    Global,
+    Exit,
+    Return,
+    // carries its source line, for the failure message
+    Assert(usize),
+
+    // identifiers & literals
+    Identifier(String),
+    IntegerLiteral(i64),
+    StringLiteral(String),
+
+    // operators
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Assign,
+    GreaterThan,
+    LessThan,
+    GreaterEqual,
+    LessEqual,
+    Equal,
+    NotEqual,
+    Not,
+    And,
+    Or,
+    Question,
+
+    // punctuation
+    SemiColon,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    LeftBracket,
+    RightBracket,
+    Comma,
+    Colon,
+    DoubleColon,
+    FatArrow,
+    Dot,
+}
+
+impl Token {
+    pub fn get_token(token_type: &str, value: Option<&str>) -> Token {
+        match token_type {
+            // keywords
+            "Print" => Token::Print,
+            "If" => Token::If,
+            "Else" => Token::Else,
+            "Int" => Token::Int,
+            "Bool" => Token::Bool,
+            "String" => Token::String,
+            "Struct" => Token::Struct,
+            "Enum" => Token::Enum,
+            "Match" => Token::Match,
+            "Maybe" => Token::Maybe,
+            "Paywall" => Token::Paywall,
+            "True" => Token::True,
+            "False" => Token::False,
+            "Const" => Token::Const,
+            "Global" => Token::Global,
+            "Exit" => Token::Exit,
+            "Return" => Token::Return,
+            "Assert" => {
+                Token::Assert(
+                    value
+                        .expect("Assert requires a line number")
+                        .parse::<usize>()
+                        .expect("Invalid line number"),
+                )
+            }
+
+            // literals
+            "IntegerLiteral" => {
+                Token::IntegerLiteral(
+                    value
+                        .expect("IntegerLiteral requires a value")
+                        .parse::<i64>()
+                        .expect("Invalid integer literal"),
+                )
+            }
+            "StringLiteral" => {
+                Token::StringLiteral(
+                    value
+                        .expect("StringLiteral requires a value")
+                        .to_string(),
+                )
+            }
+
+            // identifiers
+            "Identifier" => {
+                Token::Identifier(
+                    value
+                        .expect("Identifier requires a value")
+                        .to_string(),
+                )
+            }
+
+            // operators
+            "Plus" => Token::Plus,
+            "Minus" => Token::Minus,
+            "Star" => Token::Star,
+            "Slash" => Token::Slash,
+            "Percent" => Token::Percent,
+            "Assign" => Token::Assign,
+
+            // punctuation
+            "SemiColon" => Token::SemiColon,
+            "LeftParen" => Token::LeftParen,
+            "RightParen" => Token::RightParen,
+            "LeftBrace" => Token::LeftBrace,
+            "RightBrace" => Token::RightBrace,
+            "LeftBracket" => Token::LeftBracket,
+            "RightBracket" => Token::RightBracket,
+            "Comma" => Token::Comma,
+            "Colon" => Token::Colon,
+            "DoubleColon" => Token::DoubleColon,
+            "FatArrow" => Token::FatArrow,
+            "Dot" => Token::Dot,
+
+            // logical operators
+            "GreaterThan" => Token::GreaterThan,
+            "LessThan" => Token::LessThan,
+            "GreaterEqual" => Token::GreaterEqual,
+            "LessEqual" => Token::LessEqual,
+            "Equal" => Token::Equal,
+            "NotEqual" => Token::NotEqual,
+            "Not" => Token::Not,
+            "And" => Token::And,
+            "Or" => Token::Or,
+            "Question" => Token::Question,
+
+            _ => panic!("invalid token type {}", token_type),
+        }
+    }
+
+    pub fn get_token_regex(token_type: &str) -> String {
+        match token_type {
+            // keywords
+            "Print" => r"\bprint\b",
+            "If" => r"\bif\b",
+            "Else" => r"\belse\b",
+            "Int" => r"\bint\b",
+            "Bool" => r"\bbool\b",
+            "String" => r"\bstring\b",
+            "Struct" => r"\bstruct\b",
+            "Enum" => r"\benum\b",
+            "Match" => r"\bmatch\b",
+            "Maybe" => r"\bmaybe\b",
+            "Paywall" => r"\bpaywall\b",
+            "True" => r"\btrue\b",
+            "False" => r"\bfalse\b",
+            "Const" => r"\bconst\b",
+            "Global" => r"\bglobal\b",
+            "Exit" => r"\bexit\b",
+            "Return" => r"\breturn\b",
+            "Assert" => r"\bassert\b",
+
+            // literals
+            "IntegerLiteral" => r"\d+",
+            "StringLiteral" => r#""[^"]*""#,
+
+            // identifiers
+            "Identifier" => r"[a-zA-Z_][a-zA-Z0-9_]*",
+
+            // operators
+            "Plus" => r"\+",
+            "Minus" => r"-",
+            "Star" => r"\*",
+            "Slash" => r"/",
+            "Percent" => r"%",
+            "Assign" => r"=",
+
+            // punctuation
+            "SemiColon" => r";",
+            "LeftParen" => r"\(",
+            "RightParen" => r"\)",
+            "LeftBrace" => r"\{",
+            "RightBrace" => r"\}",
+            "LeftBracket" => r"\[",
+            "RightBracket" => r"\]",
+            "Comma" => r",",
+            "Colon" => r":",
+            "DoubleColon" => r"::",
+            "FatArrow" => r"=>",
+            "Dot" => r"\.",
+
+            // logical operators
+            "GreaterThan" => r">",
+            "LessThan" => r"<",
+            "GreaterEqual" => r">=",
+            "LessEqual" => r"<=",
+            "Equal" => r"==",
+            "NotEqual" => r"!=",
+            "Not" => r"!",
+            "And" => r"&&",
+            "Or" => r"\|\|",
+            "Question" => r"\?",
+
+            _ => panic!("invalid token type: {}", token_type),
+        }
+        .to_string()
+    }
+}
@@ -3,3 +3,6 @@ pub mod parsing;
 pub mod semantic;
 pub mod optimizer;
 pub mod codegen;
+pub mod analysis;
+pub mod interp;
+pub mod vm;
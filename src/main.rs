@@ -1,14 +1,22 @@
 
 use std::time::Instant;
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 use lexical::{
     lexing::lexer::lex_program,
     parsing::parser::Parser,
     semantic::semantic::SemanticAnalyzer,
     optimizer::optimizer::Optimizer,
-    codegen::arm64::Codegen,
+    codegen::{arm64, x86_64},
+    codegen::common::{has_paywall, DEFAULT_PAYWALL_KEY, DEFAULT_PAYWALL_RETRIES},
+    codegen::bytecode::{BytecodeCompiler, Program},
+    parsing::ast::Stmt,
+    analysis::probability::{AnalysisError, Outcome, ProbabilityAnalyzer},
+    analysis::monte_carlo::Histogram,
+    interp::interp::{Interpreter, RunOptions},
+    vm::vm::Vm,
 };
+use std::io::Read;
 
 fn make_program(repetitions: usize, chaos_mode: bool) -> String {
     let standard_block = r#"
@@ -42,7 +50,460 @@ fn make_program(repetitions: usize, chaos_mode: bool) -> String {
     }
 }
 
+fn usage() -> ! {
+    eprintln!("usage: quasar [OPTIONS]");
+    eprintln!("       quasar analyze-prob [FILE]");
+    eprintln!("       quasar monte-carlo [OPTIONS] [--runs N] [FILE]");
+    eprintln!("       quasar run [--interp | --vm] [OPTIONS] [FILE] [-- ARGS...]");
+    eprintln!("options: --target T, --seed N, --paywall-key K, --paywall-retries N");
+    eprintln!("targets: aarch64-linux (default), x86_64-linux");
+    std::process::exit(2);
+}
+
+// reads, checks and optimizes a program for the analysis modes, exiting on
+// any error; without a file the benchmark's standard program is used
+fn front_end(path: Option<String>) -> (String, Vec<Stmt>) {
+    let (source_name, program) = match path {
+        Some(path) => match std::fs::read_to_string(&path) {
+            Ok(program) => (path, program),
+            Err(e) => {
+                eprintln!("cannot read {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => ("<generated>".to_string(), make_program(1, false)),
+    };
+
+    let tokens = lex_program(&program);
+    let ast = match Parser::new(tokens).parse() {
+        Ok(ast) => ast,
+        Err(e) => {
+            println!("parse error: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(errors) = SemanticAnalyzer::new().analyze(&ast) {
+        println!("semantic errors ({}):", errors.len());
+        for e in errors {
+            println!("  {:?}", e);
+        }
+        std::process::exit(1);
+    }
+    let ast = match Optimizer::new().optimize(ast) {
+        Ok(ast) => ast,
+        Err(errors) => {
+            println!("optimizer errors ({}):", errors.len());
+            for e in errors {
+                println!("  {:?}", e);
+            }
+            std::process::exit(1);
+        }
+    };
+    (source_name, ast)
+}
+
+fn show_trace(output: &[String], status: i32) -> String {
+    let trace = format!("{:?}", output.join("\n"));
+    if status == 0 {
+        trace
+    } else {
+        format!("{} exit={}", trace, status)
+    }
+}
+
+// lists every way a program can end with its exact probability, instead of
+// compiling it
+fn analyze_prob(path: Option<String>) {
+    let (source_name, ast) = front_end(path);
+    let outcomes = match ProbabilityAnalyzer::new().with_source_name(&source_name).analyze(&ast) {
+        Ok(outcomes) => outcomes,
+        Err(e) => {
+            println!("cannot analyze: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("outcomes ({}):", outcomes.len());
+    for outcome in outcomes {
+        println!("  {} p={}", show_trace(&outcome.output, outcome.status), outcome.probability);
+    }
+}
+
+enum Backend {
+    Native,
+    Interp,
+    Vm,
+}
+
+// reads a bytecode file written by run --vm or the benchmark, exiting if it
+// is not one
+fn load_bytecode(path: &str) -> Program {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("cannot read {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    match Program::from_bytes(&bytes) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("invalid bytecode in {}: {:?}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// the machine native code is generated for, which also decides how out.s is
+// assembled and how the executable is run: under qemu for aarch64, directly
+// with the host toolchain for x86_64
+#[derive(Clone, Copy)]
+enum Target {
+    Aarch64Linux,
+    X86_64Linux,
+}
+
+impl Target {
+    fn parse(name: &str) -> Option<Target> {
+        match name {
+            "aarch64-linux" => Some(Target::Aarch64Linux),
+            "x86_64-linux" => Some(Target::X86_64Linux),
+            _ => None,
+        }
+    }
+
+    // `key` and `retries` configure the paywall
+    fn generate(
+        self,
+        source_name: &str,
+        (key, retries): (&str, u32),
+        seed: Option<u64>,
+        ast: &[Stmt],
+    ) -> String {
+        match self {
+            Target::Aarch64Linux => {
+                let mut codegen = arm64::Codegen::new()
+                    .with_source_name(source_name)
+                    .with_paywall_key(key)
+                    .with_paywall_retries(retries);
+                if let Some(seed) = seed {
+                    codegen = codegen.with_seed(seed);
+                }
+                codegen.generate(ast)
+            }
+            Target::X86_64Linux => {
+                let mut codegen = x86_64::Codegen::new()
+                    .with_source_name(source_name)
+                    .with_paywall_key(key)
+                    .with_paywall_retries(retries);
+                if let Some(seed) = seed {
+                    codegen = codegen.with_seed(seed);
+                }
+                codegen.generate(ast)
+            }
+        }
+    }
+
+    // out.s -> out
+    fn assemble(self) -> bool {
+        let status = match self {
+            Target::Aarch64Linux => Command::new("aarch64-linux-gnu-gcc")
+                .args(["-static", "out.s", "-o", "out"])
+                .status(),
+            Target::X86_64Linux => Command::new("cc").args(["out.s", "-o", "out"]).status(),
+        };
+        status.is_ok_and(|status| status.success())
+    }
+
+    // a command that runs ./out, for the caller to add arguments to
+    fn runner(self) -> Command {
+        match self {
+            Target::Aarch64Linux => {
+                let mut command = Command::new("qemu-aarch64");
+                command.arg("./out");
+                command
+            }
+            Target::X86_64Linux => Command::new("./out"),
+        }
+    }
+}
+
+// the options shared by every mode that generates or runs a program
+struct Options {
+    target: Target,
+    seed: Option<u64>,
+    // QUASAR_SEED, which a compiled program reads at startup over its own seed
+    env_seed: Option<u64>,
+    // the paywall's unlock key; only its hash is written into out.s
+    paywall_key: String,
+    paywall_retries: u32,
+}
+
+impl Options {
+    // the key defaults to QUASAR_PAYWALL_KEY, then the backends' own default
+    fn new() -> Self {
+        Self {
+            target: Target::Aarch64Linux,
+            seed: None,
+            env_seed: std::env::var("QUASAR_SEED").ok().map(|s| s.parse::<u64>().unwrap_or(0)),
+            paywall_key: std::env::var("QUASAR_PAYWALL_KEY")
+                .unwrap_or_else(|_| DEFAULT_PAYWALL_KEY.to_string()),
+            paywall_retries: DEFAULT_PAYWALL_RETRIES,
+        }
+    }
+
+    fn paywall(&self) -> (&str, u32) {
+        (&self.paywall_key, self.paywall_retries)
+    }
+
+    // how the interpreter and the vm run the program; the seed is picked the
+    // way a compiled program would pick it
+    fn run_options(&self) -> RunOptions {
+        let mut run = RunOptions::new()
+            .with_paywall_key(&self.paywall_key)
+            .with_paywall_retries(self.paywall_retries);
+        if let Some(seed) = self.env_seed.or(self.seed) {
+            run = run.with_seed(seed);
+        }
+        run
+    }
+
+    // takes `arg`, and its value from `cli`, when it is a shared option;
+    // a bad value exits through usage
+    fn parse(&mut self, arg: &str, cli: &mut impl Iterator<Item = String>) -> bool {
+        match arg {
+            "--target" => match cli.next().as_deref().and_then(Target::parse) {
+                Some(t) => self.target = t,
+                None => usage(),
+            },
+            "--seed" => match cli.next().and_then(|n| n.parse::<u64>().ok()) {
+                Some(n) => self.seed = Some(n),
+                None => usage(),
+            },
+            "--paywall-key" => match cli.next() {
+                Some(key) => self.paywall_key = key,
+                None => usage(),
+            },
+            "--paywall-retries" => match cli.next().and_then(|n| n.parse::<u32>().ok()) {
+                Some(n) if n > 0 => self.paywall_retries = n,
+                _ => usage(),
+            },
+            _ => return false,
+        }
+        true
+    }
+}
+
+fn read_stdin() -> String {
+    let mut input = String::new();
+    if let Err(e) = std::io::stdin().read_to_string(&mut input) {
+        eprintln!("cannot read stdin: {}", e);
+        std::process::exit(1);
+    }
+    input
+}
+
+// runs one program and exits with its status: through the interpreter, the
+// bytecode VM, or compiled for the target and run. ARGS become the program's argv
+// after its name. the VM writes the bytecode to out.qbc, and runs a .qbc FILE
+// as it is
+fn run(
+    path: Option<String>,
+    backend: Backend,
+    options: &Options,
+    args: Vec<String>,
+) -> ! {
+    let (target, seed) = (options.target, options.seed);
+
+    if let Backend::Vm = backend {
+        let program = match path {
+            Some(path) if path.ends_with(".qbc") => load_bytecode(&path),
+            path => {
+                let (source_name, ast) = front_end(path);
+                let program = BytecodeCompiler::new().with_source_name(&source_name).compile(&ast);
+                std::fs::write("out.qbc", program.to_bytes()).expect("failed to write out.qbc");
+                program
+            }
+        };
+        let run = options.run_options().with_input(&read_stdin()).with_args(args);
+        let result = Vm::new().with_options(run).run(&program);
+        print!("{}", result.stdout);
+        std::process::exit(result.status);
+    }
+
+    let (source_name, ast) = front_end(path);
+    let status = if let Backend::Interp = backend {
+        let run = options.run_options().with_input(&read_stdin()).with_args(args);
+        let result = Interpreter::new()
+            .with_source_name(&source_name)
+            .with_options(run)
+            .run(&ast);
+        print!("{}", result.stdout);
+        Some(result.status)
+    } else {
+        let asm = target.generate(&source_name, options.paywall(), seed, &ast);
+        std::fs::write("out.s", asm).expect("failed to write out.s");
+        if !target.assemble() {
+            println!("assembly failed");
+            std::process::exit(1);
+        }
+        match target.runner().args(&args).status() {
+            Ok(status) => status.code(),
+            Err(e) => {
+                println!("runtime execution failed: {}", e);
+                None
+            }
+        }
+    };
+    std::process::exit(status.unwrap_or(1));
+}
+
+// compiles the program once and runs it `runs` times, seeding run
+// i with seed + i, then tests the outcome counts against the exact
+// probabilities. exits with 1 when the counts do not fit them, or when a
+// paywall might prompt: runs get no stdin, so every one would be denied
+fn monte_carlo(path: Option<String>, options: &Options, runs: usize) {
+    let (target, seed) = (options.target, options.seed.unwrap_or(1));
+    let (source_name, ast) = front_end(path);
+    let expected: Option<Vec<Outcome>> =
+        match ProbabilityAnalyzer::new().with_source_name(&source_name).analyze(&ast) {
+            Ok(outcomes) => Some(outcomes),
+            Err(AnalysisError::Paywall) => {
+                println!("cannot test: the paywall prompts for a key, which the runs cannot answer");
+                std::process::exit(1);
+            }
+            Err(e) if ast.iter().any(has_paywall) => {
+                println!("cannot test: cannot analyze ({:?}), so the paywall may prompt", e);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                println!("cannot analyze ({:?}); reporting counts only", e);
+                None
+            }
+        };
+
+    let asm = target.generate(&source_name, options.paywall(), None, &ast);
+    std::fs::write("out.s", &asm).expect("failed to write out.s");
+    if !target.assemble() {
+        println!("assembly failed");
+        std::process::exit(1);
+    }
+
+    let mut histogram = Histogram::new();
+    for i in 0..runs {
+        let run = target
+            .runner()
+            .env("QUASAR_SEED", seed.wrapping_add(i as u64).to_string())
+            .stdin(Stdio::null())
+            .output();
+        match run {
+            Ok(run) => {
+                let output = String::from_utf8_lossy(&run.stdout).lines().map(String::from).collect();
+                // killed by a signal: no status, counted apart from every real one
+                histogram.record(output, run.status.code().unwrap_or(-1));
+            }
+            Err(e) => {
+                println!("runtime execution failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    println!("observed over {} runs:", histogram.runs());
+    for (output, status, count) in histogram.entries() {
+        let share = count as f64 / runs as f64;
+        match expected.iter().flatten().find(|e| e.output == output && e.status == status) {
+            Some(e) => println!(
+                "  {} n={} ({:.4}, expected {:.4})",
+                show_trace(&output, status), count, share, e.probability.as_f64()
+            ),
+            None => println!("  {} n={} ({:.4})", show_trace(&output, status), count, share),
+        }
+    }
+
+    let Some(expected) = expected else {
+        return;
+    };
+    for e in expected.iter().filter(|e| histogram.count(&e.output, e.status) == 0) {
+        println!("  {} n=0 (expected {:.4})", show_trace(&e.output, e.status), e.probability.as_f64());
+    }
+
+    let test = histogram.chi_square(&expected);
+    for (output, status, _) in &test.unexpected {
+        println!("impossible outcome: {}", show_trace(output, *status));
+    }
+    println!(
+        "chi-square: {:.3} with {} degrees of freedom (critical {:.3} at p=0.001)",
+        test.statistic, test.degrees_of_freedom, test.critical
+    );
+    if test.passed() {
+        println!("result: consistent with the expected probabilities");
+    } else {
+        println!("result: NOT consistent with the expected probabilities");
+        std::process::exit(1);
+    }
+}
+
 fn main() {
+    // options:
+    //   --seed N   seed for `maybe`; QUASAR_SEED set when the program starts
+    //              overrides it, and without either the clock is used
+    //   --target T aarch64-linux (the default) or x86_64-linux
+    //   --paywall-key K      the key that unlocks the paywall
+    //   --paywall-retries N  attempts the paywall allows before exiting
+    let mut options = Options::new();
+    let mut cli = std::env::args().skip(1).peekable();
+    match cli.peek().map(String::as_str) {
+        Some("analyze-prob") => {
+            cli.next();
+            let path = cli.next();
+            if cli.next().is_some() {
+                usage();
+            }
+            analyze_prob(path);
+            return;
+        }
+        Some("run") => {
+            cli.next();
+            let (mut backend, mut path, mut args) = (Backend::Native, None, Vec::new());
+            while let Some(arg) = cli.next() {
+                match arg.as_str() {
+                    "--interp" => backend = Backend::Interp,
+                    "--vm" => backend = Backend::Vm,
+                    "--" => args.extend(cli.by_ref()),
+                    _ if options.parse(&arg, &mut cli) => {}
+                    _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+                    _ => usage(),
+                }
+            }
+            run(path, backend, &options, args);
+        }
+        Some("monte-carlo") => {
+            cli.next();
+            let (mut runs, mut path) = (1000, None);
+            while let Some(arg) = cli.next() {
+                match arg.as_str() {
+                    "--runs" => match cli.next().and_then(|n| n.parse::<usize>().ok()) {
+                        Some(n) if n > 0 => runs = n,
+                        _ => usage(),
+                    },
+                    _ if options.parse(&arg, &mut cli) => {}
+                    _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+                    _ => usage(),
+                }
+            }
+            monte_carlo(path, &options, runs);
+            return;
+        }
+        _ => {}
+    }
+    while let Some(arg) = cli.next() {
+        if !options.parse(&arg, &mut cli) {
+            usage();
+        }
+    }
+    let (target, seed) = (options.target, options.seed);
+
     // config:
     let repetitions = 1; // try: 1, 10, 50, 100, 500
     let program = make_program(repetitions, true);
@@ -81,19 +542,48 @@ fn main() {
         }
     }
 
+    if !analyzer.warnings().is_empty() {
+        println!("semantic warnings ({}):", analyzer.warnings().len());
+        for w in analyzer.warnings() {
+            println!("  {:?}", w);
+        }
+    }
+
     // optimizer
     let optimize_start = Instant::now();
     let mut optimizer = Optimizer::new();
-    let optimized_ast = optimizer.optimize(ast);
+    let optimized_ast = match optimizer.optimize(ast) {
+        Ok(ast) => ast,
+        Err(errors) => {
+            println!("optimizer errors ({}):", errors.len());
+            for e in errors {
+                println!("  {:?}", e);
+            }
+            return;
+        }
+    };
     let optimize_time = optimize_start.elapsed();
 
     // codegen
     let codegen_start = Instant::now();
-    let asm = Codegen::new().generate(&optimized_ast);
+    let asm = target.generate("<generated>", options.paywall(), seed, &optimized_ast);
     let codegen_time = codegen_start.elapsed();
 
     std::fs::write("out.s", &asm).expect("failed to write out.s");
 
+    // bytecode, from the same optimized AST; the VM runs it with no input
+    let bytecode_start = Instant::now();
+    let bytecode = BytecodeCompiler::new()
+        .with_source_name("<generated>")
+        .compile(&optimized_ast);
+    let bytecode_time = bytecode_start.elapsed();
+
+    std::fs::write("out.qbc", bytecode.to_bytes()).expect("failed to write out.qbc");
+
+    let vm_start = Instant::now();
+    let vm_result = Vm::new().with_options(options.run_options()).run(&bytecode);
+    let vm_time = vm_start.elapsed();
+
      println!("\n generated assembly: \n");
 
     // prevent terminal nuking on huge outputs
@@ -110,19 +600,19 @@ fn main() {
 
     let assemble_start = Instant::now();
 
-    let assemble_status = Command::new("aarch64-linux-gnu-gcc")
-        .args(["-static","out.s", "-o", "out"])
-        .status();
+    let assembled = target.assemble();
 
     let assemble_time = assemble_start.elapsed();
 
-    if assemble_status.is_err() || !assemble_status.unwrap().success() {
+    if !assembled {
         println!("assembly failed");
         println!("\n timings: ");
         println!("lexing:        {:?}", lex_time);
         println!("parsing:       {:?}", parse_time);
         println!("semantic:      {:?}", semantic_time);
         println!("codegen:       {:?}", codegen_time);
+        println!("bytecode:      {:?}", bytecode_time);
+        println!("vm:            {:?} (exit status {})", vm_time, vm_result.status);
         println!("assemble:      FAILED");
         println!("total:         {:?}", total_start.elapsed());
         return;
@@ -131,15 +621,18 @@ fn main() {
     // ================= RUNTIME =================
     let run_start = Instant::now();
 
-    let run_status = Command::new("qemu-aarch64")
-    .args(["./out"])
-    .status();
+    let run_status = target.runner().status();
 
     let run_time = run_start.elapsed();
 
-    if run_status.is_err() || !run_status.unwrap().success() {
-        println!("runtime execution failed");
-    }
+    // the program's exit status is passed on as the driver's own
+    let exit_code = match run_status {
+        Ok(status) => status.code(),
+        Err(e) => {
+            println!("runtime execution failed: {}", e);
+            None
+        }
+    };
 
     // ================= TIMINGS =================
     println!("\n--- TIMINGS ---");
@@ -148,9 +641,22 @@ fn main() {
     println!("Semantic:      {:?}", semantic_time);
     println!("Optimizer:     {:?}", optimize_time); 
     println!("Codegen:       {:?}", codegen_time);
+    println!("Bytecode:      {:?}", bytecode_time);
+    println!("VM:            {:?} (exit status {})", vm_time, vm_result.status);
     println!("Assemble:      {:?}", assemble_time);
     println!("Runtime:       {:?}", run_time);
     println!("Total:         {:?}", total_start.elapsed());
 
     println!("\nexecutable: out.exe");
+
+    match exit_code {
+        Some(code) => {
+            println!("exit status: {}", code);
+            std::process::exit(code);
+        }
+        None => {
+            println!("exit status: none (killed by a signal or not run)");
+            std::process::exit(1);
+        }
+    }
 }
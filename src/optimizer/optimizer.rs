@@ -1,261 +1,812 @@
-use std::collections::{HashMap, HashSet};
-use crate::parsing::ast::{Expr, Stmt, BinOp};
-
-#[derive(Debug, Clone, PartialEq)]
-pub enum ConstValue {
-    Int(i32),
-    String(String),
-    Bool(bool),
-}
-
-pub struct Optimizer {
-    constants: HashMap<String, ConstValue>,
-    used_vars: HashSet<String>,
-}
-
-impl Optimizer {
-    pub fn new() -> Self {
-        Self {
-            constants: HashMap::new(),
-            used_vars: HashSet::new(),
-        }
-    }
-
-    // -------- ENTRY --------
-
-    pub fn optimize(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
-        let mut current = stmts;
-
-        // Run up to 10 passes to catch nested optimizations
-        for _ in 0..10 {
-            self.constants.clear();
-            self.used_vars.clear();
-
-            // Pass 1: Analyze usage
-            self.collect_used_vars(&current);
-            
-            // Pass 2: Optimize (Fold constants)
-            let optimized = self.optimize_stmts(current.clone());
-            
-            // Pass 3: Clean (Remove unused variables)
-            let cleaned = self.dead_code_elimination(optimized.clone());
-
-            if optimized == current {
-                break;
-            }
-
-            current = cleaned;
-        }
-
-        current
-    }
-
-    // -------- STATEMENTS --------
-
-    fn optimize_stmts(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
-        stmts.into_iter().flat_map(|s| self.optimize_stmt(s)).collect()
-    }
-
-    fn optimize_stmt(&mut self, stmt: Stmt) -> Vec<Stmt> {
-        match stmt {
-            Stmt::VarDeclaration { name, value } => {
-                let value = self.optimize_expr(value);
-
-                if let Some(c) = self.eval_const(&value) {
-                    self.constants.insert(name.clone(), c);
-                } else {
-                    self.constants.remove(&name);
-                }
-
-                vec![Stmt::VarDeclaration { name, value }]
-            }
-
-            Stmt::Print(expr) => {
-                vec![Stmt::Print(self.optimize_expr(expr))]
-            }
-
-            Stmt::Block(stmts) => {
-                vec![Stmt::Block(self.optimize_stmts(stmts))]
-            }
-
-            Stmt::If { condition, then_block, else_block } => {
-                self.optimize_if(condition, then_block, else_block)
-            }
-            
-            Stmt::ExprStmt(expr) => {
-                vec![Stmt::ExprStmt(self.optimize_expr(expr))]
-            }
-            
-            Stmt::Paywall(n) => vec![Stmt::Paywall(n)],
-        }
-    }
-
-    fn optimize_if(
-        &mut self,
-        condition: Expr,
-        then_block: Vec<Stmt>,
-        else_block: Option<Vec<Stmt>>,
-    ) -> Vec<Stmt> {
-        let cond = self.optimize_expr(condition);
-
-        // Control Flow Simplification
-        // If we know the boolean result at compile time, we delete the dead branch.
-        if let Some(ConstValue::Bool(b)) = self.eval_const(&cond) {
-            if b {
-                return self.optimize_stmts(then_block);
-            } else {
-                return else_block
-                    .map(|b| self.optimize_stmts(b))
-                    .unwrap_or_default();
-            }
-        }
-
-        vec![Stmt::If {
-            condition: cond,
-            then_block: self.optimize_stmts(then_block),
-            else_block: else_block.map(|b| self.optimize_stmts(b)),
-        }]
-    }
-
-    // -------- EXPRESSIONS --------
-
-    fn optimize_expr(&mut self, expr: Expr) -> Expr {
-        match expr {
-            Expr::Identifier(name) => {
-                if let Some(c) = self.constants.get(&name) {
-                    match c {
-                        ConstValue::Int(n) => Expr::IntegerLiteral(*n),
-                        ConstValue::String(s) => Expr::StringLiteral(s.clone()),
-                        ConstValue::Bool(b) => Expr::BooleanLiteral(*b),
-                    }
-                } else {
-                    Expr::Identifier(name)
-                }
-            }
-
-            Expr::Binary { left, op, right } => {
-                self.optimize_binary(*left, op, *right)
-            }
-
-            Expr::Assign { name, value } => {
-                let v = self.optimize_expr(*value);
-                // If a variable is reassigned, its known constant value is invalid
-                self.constants.remove(&name);
-                Expr::Assign { name, value: Box::new(v) }
-            }
-
-            _ => expr,
-        }
-    }
-
-    fn optimize_binary(&mut self, left: Expr, op: BinOp, right: Expr) -> Expr {
-        let l = self.optimize_expr(left);
-        let r = self.optimize_expr(right);
-
-        // Constant Folding (e.g., 2 + 2 -> 4)
-        if let (Some(lc), Some(rc)) = (self.eval_const(&l), self.eval_const(&r)) {
-            if let Some(result) = self.fold(lc, &op, rc) {
-                return result;
-            }
-        }
-
-        // Identity Optimization (e.g., x + 0 -> x)
-        match (&op, &l, &r) {
-            (BinOp::Add, Expr::IntegerLiteral(0), _) => r,
-            (BinOp::Add, _, Expr::IntegerLiteral(0)) => l,
-            (BinOp::Sub, _, Expr::IntegerLiteral(0)) => l,
-            _ => Expr::Binary {
-                left: Box::new(l),
-                op,
-                right: Box::new(r),
-            },
-        }
-    }
-
-    // -------- CONSTANT FOLDING --------
-
-    fn eval_const(&self, expr: &Expr) -> Option<ConstValue> {
-        match expr {
-            Expr::IntegerLiteral(n) => Some(ConstValue::Int(*n)),
-            Expr::StringLiteral(s) => Some(ConstValue::String(s.clone())),
-            Expr::BooleanLiteral(b) => Some(ConstValue::Bool(*b)),
-            _ => None,
-        }
-    }
-
-    fn fold(&self, l: ConstValue, op: &BinOp, r: ConstValue) -> Option<Expr> {
-        match (l, op, r) {
-            (ConstValue::Int(a), BinOp::Add, ConstValue::Int(b)) => 
-                Some(Expr::IntegerLiteral(a + b)),
-            
-            (ConstValue::Int(a), BinOp::Sub, ConstValue::Int(b)) => 
-                Some(Expr::IntegerLiteral(a - b)),
-
-            (ConstValue::Int(a), BinOp::GreaterThan, ConstValue::Int(b)) => 
-                Some(Expr::BooleanLiteral(a > b)),
-            
-            (ConstValue::Int(a), BinOp::LessThan, ConstValue::Int(b)) => 
-                Some(Expr::BooleanLiteral(a < b)),
-
-            (ConstValue::String(a), BinOp::Add, ConstValue::String(b)) => 
-                Some(Expr::StringLiteral(format!("{}{}", a, b))),
-
-            _ => None,
-        }
-    }
-
-    // -------- DEAD CODE ANALYSIS --------
-
-    fn collect_used_vars(&mut self, stmts: &[Stmt]) {
-        for s in stmts {
-            self.collect_stmt(s);
-        }
-    }
-
-    fn collect_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::VarDeclaration { value, .. } => self.collect_expr(value),
-            Stmt::Print(e) => self.collect_expr(e),
-            Stmt::If { condition, then_block, else_block } => {
-                self.collect_expr(condition);
-                then_block.iter().for_each(|s| self.collect_stmt(s));
-                if let Some(b) = else_block {
-                    b.iter().for_each(|s| self.collect_stmt(s));
-                }
-            }
-            Stmt::Block(stmts) => stmts.iter().for_each(|s| self.collect_stmt(s)),
-            Stmt::ExprStmt(expr) => {
-               self.collect_expr(expr);
-            }
-            Stmt::Paywall(_) => {} 
-        }
-    }
-
-    fn collect_expr(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Identifier(n) => {
-                self.used_vars.insert(n.clone());
-            }
-            Expr::Binary { left, right, .. } => {
-                self.collect_expr(left);
-                self.collect_expr(right);
-            }
-            Expr::Assign { name, value } => {
-                self.used_vars.insert(name.clone());
-                self.collect_expr(value);
-            }
-            _ => {}
-        }
-    }
-
-    fn dead_code_elimination(&self, stmts: Vec<Stmt>) -> Vec<Stmt> {
-        stmts.into_iter()
-            .filter(|s| match s {
-                // If a variable is declared but never used, DELETE IT.
-                Stmt::VarDeclaration { name, .. } => 
-                    self.used_vars.contains(name),
-                _ => true,
-            })
-            .collect()
-    }
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use crate::parsing::ast::{Expr, Stmt, BinOp, UnOp, TypeName, ArraySize, MatchArm, MatchPattern};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+    Int(i32),
+    String(String),
+    Bool(bool),
+    Enum {
+        enum_name: String,
+        variant: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptimizeError {
+    DivisionByZero,
+    // int(s) on a constant string that is not an integer
+    InvalidIntConversion(String),
+    // char_at(s, i) with constant arguments and i outside s
+    StringIndexOutOfBounds {
+        index: i32,
+        len: usize,
+    },
+    // an assert whose condition is always false
+    AssertionFailed {
+        line: usize,
+        message: Option<String>,
+    },
+}
+
+pub struct Optimizer {
+    constants: HashMap<String, ConstValue>,
+    used_vars: HashSet<String>,
+    errors: Vec<OptimizeError>,
+    // every run reaches the statement being folded: it is not inside a
+    // branch, nor after a statement that may have ended the program
+    always_reached: bool,
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Self {
+            constants: HashMap::new(),
+            used_vars: HashSet::new(),
+            errors: Vec::new(),
+            always_reached: true,
+        }
+    }
+
+    // an optimizer that already knows the value of some named constants
+    pub fn with_constants(constants: HashMap<String, ConstValue>) -> Self {
+        Self {
+            constants,
+            ..Self::new()
+        }
+    }
+
+    // folds a single expression, returning its value if it is a constant
+    pub fn evaluate(&mut self, expr: &Expr) -> Option<ConstValue> {
+        let folded = self.optimize_expr(expr.clone());
+        self.eval_const(&folded)
+    }
+
+    // folds a single expression as far as the known constants allow
+    pub fn simplify(&mut self, expr: Expr) -> Expr {
+        self.optimize_expr(expr)
+    }
+
+    // -------- ENTRY --------
+
+    pub fn optimize(&mut self, stmts: Vec<Stmt>) -> Result<Vec<Stmt>, Vec<OptimizeError>> {
+        let mut current = stmts;
+
+        // Run up to 10 passes to catch nested optimizations
+        for _ in 0..10 {
+            self.constants.clear();
+            self.used_vars.clear();
+            self.always_reached = true;
+
+            // Pass 1: Analyze usage
+            self.collect_used_vars(&current);
+            
+            // Pass 2: Optimize (Fold constants)
+            let optimized = self.optimize_stmts(current.clone());
+
+            // Errors found while folding are reported once, not per pass
+            if !self.errors.is_empty() {
+                return Err(self.errors.clone());
+            }
+            
+            // Pass 3: Clean (Remove unused variables)
+            let cleaned = self.dead_code_elimination(optimized.clone());
+
+            if optimized == current {
+                break;
+            }
+
+            current = cleaned;
+        }
+
+        Ok(current)
+    }
+
+    // -------- STATEMENTS --------
+
+    fn optimize_stmts(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        stmts.into_iter().flat_map(|s| self.optimize_stmt(s)).collect()
+    }
+
+    // statements of a nested block: whatever the block declares goes out of
+    // scope with it, and an outer constant only stays known if the block left
+    // it untouched (it may have been assigned or shadowed inside)
+    fn optimize_block(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        let mut outer = self.constants.clone();
+        let stmts = self.optimize_stmts(stmts);
+        outer.retain(|name, value| self.constants.get(name) == Some(value));
+        self.constants = outer;
+        stmts
+    }
+
+    fn optimize_stmt(&mut self, stmt: Stmt) -> Vec<Stmt> {
+        match stmt {
+            Stmt::VarDeclaration { name, ty, value } => {
+                let value = self.optimize_expr(value);
+
+                if let Some(c) = self.eval_const(&value) {
+                    self.constants.insert(name.clone(), c);
+                } else {
+                    self.constants.remove(&name);
+                }
+
+                let ty = self.inline_type(ty);
+                vec![Stmt::VarDeclaration { name, ty, value }]
+            }
+
+            // Constant Inlining
+            // Every use of a const is replaced by its value, so the declaration itself goes away.
+            Stmt::ConstDeclaration { name, ty, value } => {
+                let value = self.optimize_expr(value);
+
+                match self.eval_const(&value) {
+                    Some(c) => {
+                        self.constants.insert(name, c);
+                        vec![]
+                    }
+                    None => vec![Stmt::ConstDeclaration { name, ty, value }],
+                }
+            }
+
+            // a global can be written from anywhere, so its value is never propagated
+            Stmt::GlobalDeclaration { name, ty, value } => {
+                let value = self.optimize_expr(value);
+                self.constants.remove(&name);
+                vec![Stmt::GlobalDeclaration { name, ty, value }]
+            }
+
+            Stmt::Print(expr) => {
+                vec![Stmt::Print(self.optimize_expr(expr))]
+            }
+
+            Stmt::PrintFormatted { format, args } => {
+                let args = args.into_iter().map(|a| self.optimize_expr(a)).collect();
+                vec![Stmt::PrintFormatted { format, args }]
+            }
+
+            Stmt::Block(stmts) => {
+                vec![Stmt::Block(self.optimize_block(stmts))]
+            }
+
+            Stmt::If { condition, then_block, else_block } => {
+                self.optimize_if(condition, then_block, else_block)
+            }
+            
+            Stmt::ExprStmt(expr) => {
+                vec![Stmt::ExprStmt(self.optimize_expr(expr))]
+            }
+            
+            Stmt::StructDeclaration { name, fields } => {
+                let fields = fields.into_iter()
+                    .map(|(f, ty)| (f, self.inline_type(ty)))
+                    .collect();
+                vec![Stmt::StructDeclaration { name, fields }]
+            }
+
+            Stmt::EnumDeclaration { .. } => vec![stmt],
+
+            Stmt::Match { scrutinee, arms } => self.optimize_match(scrutinee, arms),
+
+            Stmt::Paywall(n) => vec![Stmt::Paywall(n)],
+
+            Stmt::Exit(code) => {
+                let code = self.optimize_expr(code);
+                self.always_reached = false;
+                vec![Stmt::Exit(code)]
+            }
+
+            Stmt::Return(code) => {
+                let code = self.optimize_expr(code);
+                self.always_reached = false;
+                vec![Stmt::Return(code)]
+            }
+
+            // Assert Folding
+            // An assert that always holds is dropped; one that never holds is an error now,
+            // unless only some runs reach it (`assert(false, "unreachable")` in a branch).
+            Stmt::Assert { condition, message, line } => {
+                let condition = self.optimize_expr(condition);
+                match condition {
+                    Expr::BooleanLiteral(true) => vec![],
+                    Expr::BooleanLiteral(false) if self.always_reached => {
+                        self.errors.push(OptimizeError::AssertionFailed { line, message });
+                        vec![]
+                    }
+                    _ => vec![Stmt::Assert { condition, message, line }],
+                }
+            }
+        }
+    }
+
+    fn optimize_if(
+        &mut self,
+        condition: Expr,
+        then_block: Vec<Stmt>,
+        else_block: Option<Vec<Stmt>>,
+    ) -> Vec<Stmt> {
+        let cond = self.optimize_expr(condition);
+
+        // Control Flow Simplification
+        // If we know the boolean result at compile time, we delete the dead branch.
+        // The surviving branch stays a block so its declarations keep their scope.
+        if let Some(ConstValue::Bool(b)) = self.eval_const(&cond) {
+            if b {
+                return vec![Stmt::Block(self.optimize_block(then_block))];
+            } else {
+                return else_block
+                    .map(|b| vec![Stmt::Block(self.optimize_block(b))])
+                    .unwrap_or_default();
+            }
+        }
+
+        let reached = self.always_reached;
+        self.always_reached = false;
+        let stmt = Stmt::If {
+            condition: cond,
+            then_block: self.optimize_block(then_block),
+            else_block: else_block.map(|b| self.optimize_block(b)),
+        };
+        self.always_reached = reached && !may_exit(&stmt);
+        vec![stmt]
+    }
+
+    fn optimize_match(&mut self, scrutinee: Expr, arms: Vec<MatchArm>) -> Vec<Stmt> {
+        let scrutinee = self.optimize_expr(scrutinee);
+
+        // Control Flow Simplification
+        // A match on a known variant is replaced by the body of the arm it selects.
+        if let Some(ConstValue::Enum { variant, .. }) = self.eval_const(&scrutinee) {
+            let chosen = arms.into_iter().find(|arm| match &arm.pattern {
+                MatchPattern::Variant(v) => *v == variant,
+                MatchPattern::Wildcard => true,
+            });
+            return chosen
+                .map(|arm| vec![Stmt::Block(self.optimize_block(arm.body))])
+                .unwrap_or_default();
+        }
+
+        let reached = self.always_reached;
+        self.always_reached = false;
+        let arms = arms.into_iter()
+            .map(|arm| MatchArm {
+                pattern: arm.pattern,
+                body: self.optimize_block(arm.body),
+            })
+            .collect();
+        let stmt = Stmt::Match { scrutinee, arms };
+        self.always_reached = reached && !may_exit(&stmt);
+        vec![stmt]
+    }
+
+    // replaces a const array size (int[N]) with the constant's value
+    fn inline_type(&self, ty: TypeName) -> TypeName {
+        match ty {
+            TypeName::Array(elem, ArraySize::Constant(name)) => {
+                let size = match self.constants.get(&name) {
+                    Some(ConstValue::Int(n)) => ArraySize::Literal(*n as usize),
+                    _ => ArraySize::Constant(name),
+                };
+                TypeName::Array(elem, size)
+            }
+            _ => ty,
+        }
+    }
+
+    // -------- EXPRESSIONS --------
+
+    fn optimize_expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Identifier(name) => {
+                if let Some(c) = self.constants.get(&name) {
+                    match c {
+                        ConstValue::Int(n) => Expr::IntegerLiteral(*n),
+                        ConstValue::String(s) => Expr::StringLiteral(s.clone()),
+                        ConstValue::Bool(b) => Expr::BooleanLiteral(*b),
+                        ConstValue::Enum { enum_name, variant } => Expr::EnumVariant {
+                            enum_name: enum_name.clone(),
+                            variant: variant.clone(),
+                        },
+                    }
+                } else {
+                    Expr::Identifier(name)
+                }
+            }
+
+            Expr::Unary { op, operand } => {
+                self.optimize_unary(op, *operand)
+            }
+
+            Expr::Binary { left, op, right } => {
+                self.optimize_binary(*left, op, *right)
+            }
+
+            Expr::Assign { name, value } => {
+                let v = self.optimize_expr(*value);
+                // If a variable is reassigned, its known constant value is invalid
+                self.constants.remove(&name);
+                Expr::Assign { name, value: Box::new(v) }
+            }
+
+            Expr::ArrayLiteral(elements) => {
+                Expr::ArrayLiteral(elements.into_iter().map(|e| self.optimize_expr(e)).collect())
+            }
+
+            Expr::Index { name, index } => {
+                Expr::Index { name, index: Box::new(self.optimize_expr(*index)) }
+            }
+
+            Expr::IndexAssign { name, index, value } => {
+                let index = self.optimize_expr(*index);
+                let value = self.optimize_expr(*value);
+                Expr::IndexAssign { name, index: Box::new(index), value: Box::new(value) }
+            }
+
+            Expr::StructLiteral { name, fields } => {
+                let fields = fields.into_iter()
+                    .map(|(f, e)| (f, self.optimize_expr(e)))
+                    .collect();
+                Expr::StructLiteral { name, fields }
+            }
+
+            Expr::FieldAssign { name, field, value } => {
+                let value = self.optimize_expr(*value);
+                Expr::FieldAssign { name, field, value: Box::new(value) }
+            }
+
+            Expr::Conditional { condition, then_expr, else_expr } => {
+                self.optimize_conditional(*condition, *then_expr, *else_expr)
+            }
+
+            Expr::Conversion { target, operand } => {
+                self.optimize_conversion(target, *operand)
+            }
+
+            Expr::Call { name, args } => self.optimize_call(name, args),
+
+            _ => expr,
+        }
+    }
+
+    fn optimize_unary(&mut self, op: UnOp, operand: Expr) -> Expr {
+        let v = self.optimize_expr(operand);
+
+        // Constant Folding (e.g., -(5) -> -5, !true -> false)
+        if let Some(c) = self.eval_const(&v)
+            && let Some(result) = self.fold_unary(&op, c)
+        {
+            return result;
+        }
+
+        // Double Negation (e.g., --x -> x, !!x -> x)
+        match v {
+            Expr::Unary { op: inner, operand } if inner == op => *operand,
+            _ => Expr::Unary {
+                op,
+                operand: Box::new(v),
+            },
+        }
+    }
+
+    fn optimize_conditional(&mut self, condition: Expr, then_expr: Expr, else_expr: Expr) -> Expr {
+        let c = self.optimize_expr(condition);
+        let t = self.optimize_expr(then_expr);
+        let e = self.optimize_expr(else_expr);
+
+        match c {
+            // Condition Folding (e.g., true ? a : b -> a)
+            Expr::BooleanLiteral(true) => t,
+            Expr::BooleanLiteral(false) => e,
+            // Identical Branches (e.g., c ? x : x -> x), unless c has effects
+            _ if t == e && is_pure(&c) => t,
+            _ => Expr::Conditional {
+                condition: Box::new(c),
+                then_expr: Box::new(t),
+                else_expr: Box::new(e),
+            },
+        }
+    }
+
+    fn optimize_conversion(&mut self, target: TypeName, operand: Expr) -> Expr {
+        let v = self.optimize_expr(operand);
+
+        // Parsing a constant string that is not a number can never succeed at runtime
+        if let (TypeName::Int, Expr::StringLiteral(s)) = (&target, &v)
+            && parse_int(s).is_none()
+        {
+            self.errors.push(OptimizeError::InvalidIntConversion(s.clone()));
+        }
+
+        // Constant Folding (e.g., string(42) -> "42", int("7") -> 7)
+        if let Some(c) = self.eval_const(&v)
+            && let Some(result) = self.fold_conversion(&target, c)
+        {
+            return result;
+        }
+
+        Expr::Conversion {
+            target,
+            operand: Box::new(v),
+        }
+    }
+
+    fn optimize_call(&mut self, name: String, args: Vec<Expr>) -> Expr {
+        let args: Vec<Expr> = args.into_iter().map(|a| self.optimize_expr(a)).collect();
+        let values: Option<Vec<ConstValue>> = args.iter().map(|a| self.eval_const(a)).collect();
+
+        // Constant Folding (e.g., len("abc") -> 3)
+        if let Some(values) = values {
+            // An index outside a constant string can never succeed at runtime
+            if let ("char_at", [ConstValue::String(s), ConstValue::Int(i)]) = (name.as_str(), values.as_slice())
+                && (*i < 0 || *i as usize >= s.len())
+            {
+                self.errors.push(OptimizeError::StringIndexOutOfBounds { index: *i, len: s.len() });
+            } else if let Some(result) = self.fold_call(&name, values) {
+                return result;
+            }
+        }
+
+        Expr::Call { name, args }
+    }
+
+    fn optimize_binary(&mut self, left: Expr, op: BinOp, right: Expr) -> Expr {
+        let l = self.optimize_expr(left);
+        let r = self.optimize_expr(right);
+
+        // Division by a literal zero can never succeed at runtime
+        if matches!(op, BinOp::Div | BinOp::Mod) && r == Expr::IntegerLiteral(0) {
+            self.errors.push(OptimizeError::DivisionByZero);
+            return Expr::Binary {
+                left: Box::new(l),
+                op,
+                right: Box::new(r),
+            };
+        }
+
+        // Constant Folding (e.g., 2 + 2 -> 4)
+        if let (Some(lc), Some(rc)) = (self.eval_const(&l), self.eval_const(&r))
+            && let Some(result) = self.fold(lc, &op, rc)
+        {
+            return result;
+        }
+
+        // Identity Optimization (e.g., x + 0 -> x, x * 1 -> x)
+        // Annihilation (x * 0 -> 0) only applies when x has no side effects
+        match (&op, &l, &r) {
+            (BinOp::Add, Expr::IntegerLiteral(0), _) => r,
+            (BinOp::Add, _, Expr::IntegerLiteral(0)) => l,
+            (BinOp::Add, Expr::StringLiteral(s), _) if s.is_empty() => r,
+            (BinOp::Add, _, Expr::StringLiteral(s)) if s.is_empty() => l,
+            (BinOp::Sub, _, Expr::IntegerLiteral(0)) => l,
+            (BinOp::Mul, Expr::IntegerLiteral(1), _) => r,
+            (BinOp::Mul, _, Expr::IntegerLiteral(1)) => l,
+            (BinOp::Div, _, Expr::IntegerLiteral(1)) => l,
+            (BinOp::Mul, Expr::IntegerLiteral(0), _) if is_pure(&r) => l,
+            (BinOp::Mul, _, Expr::IntegerLiteral(0)) if is_pure(&l) => r,
+            (BinOp::Mod, _, Expr::IntegerLiteral(1)) if is_pure(&l) => Expr::IntegerLiteral(0),
+
+            // Short-circuit Simplification: a constant on the left decides
+            // whether the right side is ever evaluated, so it can always go;
+            // a deciding constant on the right only removes a pure left side
+            (BinOp::And, Expr::BooleanLiteral(false), _) => l,
+            (BinOp::And, Expr::BooleanLiteral(true), _) => r,
+            (BinOp::And, _, Expr::BooleanLiteral(true)) => l,
+            (BinOp::And, _, Expr::BooleanLiteral(false)) if is_pure(&l) => r,
+            (BinOp::Or, Expr::BooleanLiteral(true), _) => l,
+            (BinOp::Or, Expr::BooleanLiteral(false), _) => r,
+            (BinOp::Or, _, Expr::BooleanLiteral(false)) => l,
+            (BinOp::Or, _, Expr::BooleanLiteral(true)) if is_pure(&l) => r,
+            _ => Expr::Binary {
+                left: Box::new(l),
+                op,
+                right: Box::new(r),
+            },
+        }
+    }
+
+    // -------- CONSTANT FOLDING --------
+
+    fn eval_const(&self, expr: &Expr) -> Option<ConstValue> {
+        match expr {
+            Expr::IntegerLiteral(n) => Some(ConstValue::Int(*n)),
+            Expr::StringLiteral(s) => Some(ConstValue::String(s.clone())),
+            Expr::BooleanLiteral(b) => Some(ConstValue::Bool(*b)),
+            Expr::EnumVariant { enum_name, variant } => Some(ConstValue::Enum {
+                enum_name: enum_name.clone(),
+                variant: variant.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn fold(&self, l: ConstValue, op: &BinOp, r: ConstValue) -> Option<Expr> {
+        match (l, op, r) {
+            (ConstValue::Int(a), BinOp::Add, ConstValue::Int(b)) => 
+                Some(Expr::IntegerLiteral(a.wrapping_add(b))),
+            
+            (ConstValue::Int(a), BinOp::Sub, ConstValue::Int(b)) => 
+                Some(Expr::IntegerLiteral(a.wrapping_sub(b))),
+
+            (ConstValue::Int(a), BinOp::Mul, ConstValue::Int(b)) => 
+                Some(Expr::IntegerLiteral(a.wrapping_mul(b))),
+
+            (ConstValue::Int(a), BinOp::Div, ConstValue::Int(b)) if b != 0 => 
+                Some(Expr::IntegerLiteral(a.wrapping_div(b))),
+
+            (ConstValue::Int(a), BinOp::Mod, ConstValue::Int(b)) if b != 0 => 
+                Some(Expr::IntegerLiteral(a.wrapping_rem(b))),
+
+            (ConstValue::Int(a), op, ConstValue::Int(b)) if is_comparison(op) => 
+                Some(Expr::BooleanLiteral(compare(op, a.cmp(&b)))),
+
+            (ConstValue::Bool(a), op, ConstValue::Bool(b)) if is_comparison(op) => 
+                Some(Expr::BooleanLiteral(compare(op, a.cmp(&b)))),
+
+            // byte-wise ordering, the same order strcmp uses at runtime
+            (ConstValue::String(a), op, ConstValue::String(b)) if is_comparison(op) => 
+                Some(Expr::BooleanLiteral(compare(op, a.as_bytes().cmp(b.as_bytes())))),
+
+            (ConstValue::Enum { variant: a, .. }, BinOp::Equal, ConstValue::Enum { variant: b, .. }) => 
+                Some(Expr::BooleanLiteral(a == b)),
+
+            (ConstValue::Enum { variant: a, .. }, BinOp::NotEqual, ConstValue::Enum { variant: b, .. }) => 
+                Some(Expr::BooleanLiteral(a != b)),
+
+            (ConstValue::Bool(a), BinOp::And, ConstValue::Bool(b)) => 
+                Some(Expr::BooleanLiteral(a && b)),
+
+            (ConstValue::Bool(a), BinOp::Or, ConstValue::Bool(b)) => 
+                Some(Expr::BooleanLiteral(a || b)),
+
+            (ConstValue::String(a), BinOp::Add, ConstValue::String(b)) => 
+                Some(Expr::StringLiteral(format!("{}{}", a, b))),
+
+            _ => None,
+        }
+    }
+
+    fn fold_unary(&self, op: &UnOp, v: ConstValue) -> Option<Expr> {
+        match (op, v) {
+            (UnOp::Neg, ConstValue::Int(n)) => 
+                Some(Expr::IntegerLiteral(n.wrapping_neg())),
+
+            (UnOp::Not, ConstValue::Bool(b)) => 
+                Some(Expr::BooleanLiteral(!b)),
+
+            _ => None,
+        }
+    }
+
+    fn fold_conversion(&self, target: &TypeName, v: ConstValue) -> Option<Expr> {
+        match (target, v) {
+            (TypeName::Int, ConstValue::Int(n)) => Some(Expr::IntegerLiteral(n)),
+            (TypeName::Int, ConstValue::Bool(b)) => Some(Expr::IntegerLiteral(b as i32)),
+            (TypeName::Int, ConstValue::String(s)) => parse_int(&s).map(Expr::IntegerLiteral),
+
+            (TypeName::Bool, ConstValue::Bool(b)) => Some(Expr::BooleanLiteral(b)),
+            (TypeName::Bool, ConstValue::Int(n)) => Some(Expr::BooleanLiteral(n != 0)),
+
+            (TypeName::String, ConstValue::String(s)) => Some(Expr::StringLiteral(s)),
+            (TypeName::String, ConstValue::Int(n)) => Some(Expr::StringLiteral(n.to_string())),
+            (TypeName::String, ConstValue::Bool(b)) => Some(Expr::StringLiteral(b.to_string())),
+
+            _ => None,
+        }
+    }
+
+    // strings are treated as bytes, as the runtime helpers do; a result that
+    // would not be valid UTF-8 is left for the runtime to compute
+    fn fold_call(&self, name: &str, args: Vec<ConstValue>) -> Option<Expr> {
+        match (name, args.as_slice()) {
+            ("len", [ConstValue::String(s)]) => Some(Expr::IntegerLiteral(s.len() as i32)),
+
+            // start and length are clamped to the string
+            ("substr", [ConstValue::String(s), ConstValue::Int(start), ConstValue::Int(len)]) => {
+                let start = (*start).clamp(0, s.len() as i32) as usize;
+                let len = (*len).clamp(0, (s.len() - start) as i32) as usize;
+                let bytes = s.as_bytes()[start..start + len].to_vec();
+                String::from_utf8(bytes).ok().map(Expr::StringLiteral)
+            }
+
+            ("index_of", [ConstValue::String(s), ConstValue::String(t)]) => {
+                Some(Expr::IntegerLiteral(s.find(t.as_str()).map_or(-1, |i| i as i32)))
+            }
+
+            ("to_upper", [ConstValue::String(s)]) => Some(Expr::StringLiteral(s.to_ascii_uppercase())),
+
+            ("char_at", [ConstValue::String(s), ConstValue::Int(i)]) => {
+                let byte = *s.as_bytes().get(*i as usize)?;
+                String::from_utf8(vec![byte]).ok().map(Expr::StringLiteral)
+            }
+
+            _ => None,
+        }
+    }
+
+    // -------- DEAD CODE ANALYSIS --------
+
+    fn collect_used_vars(&mut self, stmts: &[Stmt]) {
+        for s in stmts {
+            self.collect_stmt(s);
+        }
+    }
+
+    fn collect_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::VarDeclaration { value, .. }
+            | Stmt::ConstDeclaration { value, .. }
+            | Stmt::GlobalDeclaration { value, .. } => self.collect_expr(value),
+            Stmt::Print(e) => self.collect_expr(e),
+            Stmt::PrintFormatted { args, .. } => args.iter().for_each(|a| self.collect_expr(a)),
+            Stmt::If { condition, then_block, else_block } => {
+                self.collect_expr(condition);
+                then_block.iter().for_each(|s| self.collect_stmt(s));
+                if let Some(b) = else_block {
+                    b.iter().for_each(|s| self.collect_stmt(s));
+                }
+            }
+            Stmt::Block(stmts) => stmts.iter().for_each(|s| self.collect_stmt(s)),
+            Stmt::ExprStmt(expr) => {
+               self.collect_expr(expr);
+            }
+            Stmt::StructDeclaration { .. } | Stmt::EnumDeclaration { .. } => {}
+            Stmt::Match { scrutinee, arms } => {
+                self.collect_expr(scrutinee);
+                arms.iter().for_each(|arm| arm.body.iter().for_each(|s| self.collect_stmt(s)));
+            }
+            Stmt::Paywall(_) => {} 
+            Stmt::Exit(code) | Stmt::Return(code) => self.collect_expr(code),
+            Stmt::Assert { condition, .. } => self.collect_expr(condition),
+        }
+    }
+
+    fn collect_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(n) => {
+                self.used_vars.insert(n.clone());
+            }
+            Expr::Unary { operand, .. } => self.collect_expr(operand),
+            Expr::Binary { left, right, .. } => {
+                self.collect_expr(left);
+                self.collect_expr(right);
+            }
+            Expr::Assign { name, value } => {
+                self.used_vars.insert(name.clone());
+                self.collect_expr(value);
+            }
+            Expr::ArrayLiteral(elements) => elements.iter().for_each(|e| self.collect_expr(e)),
+            Expr::Index { name, index } => {
+                self.used_vars.insert(name.clone());
+                self.collect_expr(index);
+            }
+            Expr::IndexAssign { name, index, value } => {
+                self.used_vars.insert(name.clone());
+                self.collect_expr(index);
+                self.collect_expr(value);
+            }
+            Expr::StructLiteral { fields, .. } => fields.iter().for_each(|(_, e)| self.collect_expr(e)),
+            Expr::Field { name, .. } => {
+                self.used_vars.insert(name.clone());
+            }
+            Expr::FieldAssign { name, value, .. } => {
+                self.used_vars.insert(name.clone());
+                self.collect_expr(value);
+            }
+            Expr::Conditional { condition, then_expr, else_expr } => {
+                self.collect_expr(condition);
+                self.collect_expr(then_expr);
+                self.collect_expr(else_expr);
+            }
+            Expr::Conversion { operand, .. } => self.collect_expr(operand),
+            Expr::Call { args, .. } => args.iter().for_each(|a| self.collect_expr(a)),
+            _ => {}
+        }
+    }
+
+    fn dead_code_elimination(&self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        stmts.into_iter()
+            .filter(|s| match s {
+                // If a variable is declared but never used, DELETE IT.
+                Stmt::VarDeclaration { name, .. } => 
+                    self.used_vars.contains(name),
+                _ => true,
+            })
+            .collect()
+    }
+}
+
+fn is_comparison(op: &BinOp) -> bool {
+    matches!(
+        op,
+        BinOp::GreaterThan
+            | BinOp::LessThan
+            | BinOp::GreaterEqual
+            | BinOp::LessEqual
+            | BinOp::Equal
+            | BinOp::NotEqual
+    )
+}
+
+fn compare(op: &BinOp, ord: Ordering) -> bool {
+    match op {
+        BinOp::GreaterThan => ord == Ordering::Greater,
+        BinOp::LessThan => ord == Ordering::Less,
+        BinOp::GreaterEqual => ord != Ordering::Less,
+        BinOp::LessEqual => ord != Ordering::Greater,
+        BinOp::Equal => ord == Ordering::Equal,
+        BinOp::NotEqual => ord != Ordering::Equal,
+        _ => unreachable!("not a comparison operator"),
+    }
+}
+
+// an expression is pure when evaluating it has no observable effect, so it
+// may be dropped or evaluated out of order without changing the program
+// whether running the statement may end the program
+fn may_exit(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Exit(_) | Stmt::Return(_) => true,
+        Stmt::Block(stmts) => stmts.iter().any(may_exit),
+        Stmt::If { then_block, else_block, .. } => {
+            then_block.iter().any(may_exit) || else_block.iter().flatten().any(may_exit)
+        }
+        Stmt::Match { arms, .. } => arms.iter().any(|arm| arm.body.iter().any(may_exit)),
+        _ => false,
+    }
+}
+
+pub fn is_pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::IntegerLiteral(_)
+        | Expr::StringLiteral(_)
+        | Expr::BooleanLiteral(_)
+        | Expr::Identifier(_)
+        | Expr::Field { .. }
+        | Expr::EnumVariant { .. } => true,
+        Expr::Maybe
+        | Expr::Assign { .. }
+        | Expr::IndexAssign { .. }
+        | Expr::FieldAssign { .. } => false,
+        Expr::ArrayLiteral(elements) => elements.iter().all(is_pure),
+        Expr::StructLiteral { fields, .. } => fields.iter().all(|(_, e)| is_pure(e)),
+        // indexing can trap on a bad index, so it is never dropped
+        Expr::Index { .. } => false,
+        Expr::Unary { operand, .. } => is_pure(operand),
+        Expr::Conditional { condition, then_expr, else_expr } => {
+            is_pure(condition) && is_pure(then_expr) && is_pure(else_expr)
+        }
+        // parsing a string into an int can trap on bad input
+        Expr::Conversion { target: TypeName::Int, .. } => false,
+        Expr::Conversion { operand, .. } => is_pure(operand),
+        // char_at and args can trap on a bad index, the read_ builtins consume input
+        Expr::Call { name, args } => {
+            matches!(name.as_str(), "len" | "substr" | "index_of" | "to_upper" | "arg_count")
+                && args.iter().all(is_pure)
+        }
+        // division can trap at runtime, so it is never dropped
+        Expr::Binary { left, op, right } => {
+            !matches!(op, BinOp::Div | BinOp::Mod) && is_pure(left) && is_pure(right)
+        }
+    }
+}
+
+// the same rules as the runtime's string -> int conversion: an optional '-'
+// followed by at least one digit and nothing else, wrapping to 32 bits
+pub fn parse_int(s: &str) -> Option<i32> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let n = digits.bytes().fold(0i32, |n, b| n.wrapping_mul(10).wrapping_add((b - b'0') as i32));
+    Some(if negative { n.wrapping_neg() } else { n })
 }
\ No newline at end of file
@@ -1,53 +1,196 @@
-#[derive(Debug, Clone, PartialEq)]
-pub enum Expr {
-    IntegerLiteral(i32),
-    StringLiteral(String),
-    BooleanLiteral(bool), 
-    Identifier(String),
-    Maybe,
-    Binary {
-        left: Box<Expr>,
-        op: BinOp,
-        right: Box<Expr>,
-    },
-    Assign {
-        name: String,
-        value: Box<Expr>,
-    },
-}
-
-#[derive(Debug, Clone, PartialEq)]
-pub enum BinOp {
-    Add,
-    Sub,
-    GreaterThan,
-    LessThan,
-}
-
-#[derive(Debug, Clone, PartialEq)]
-pub enum Stmt {
-    Print(Expr),
-    VarDeclaration {
-        name: String,
-        value: Expr,
-    },
-    If {
-        condition: Expr,
-        then_block: Vec<Stmt>,
-        else_block: Option<Vec<Stmt>>,
-    },
-    Block(Vec<Stmt>),
-    ExprStmt(Expr),
-    Paywall(i64),
-}
-
-
-// first we create an enum of Expr, which has all type of Expr we can face in the code and 
-// a binary Expr which has operators in it, then a statement enum which has print and it takes Expr as 
-// its input.
-// variable declaration; which declares any variables based on their names and their values,it stores names as strings
-// and value as any of the Expr we defined above.
-// if block is where the condition is defined aka the Expr, then it moves to then_block if the defined 
-// Expr is true or to else_block of the Expr is untrue.
-// 
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    IntegerLiteral(i32),
+    StringLiteral(String),
+    BooleanLiteral(bool), 
+    Identifier(String),
+    Maybe,
+    Unary {
+        op: UnOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        left: Box<Expr>,
+        op: BinOp,
+        right: Box<Expr>,
+    },
+    Assign {
+        name: String,
+        value: Box<Expr>,
+    },
+    ArrayLiteral(Vec<Expr>),
+    Index {
+        name: String,
+        index: Box<Expr>,
+    },
+    IndexAssign {
+        name: String,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    StructLiteral {
+        name: String,
+        fields: Vec<(String, Expr)>,
+    },
+    Field {
+        name: String,
+        field: String,
+    },
+    FieldAssign {
+        name: String,
+        field: String,
+        value: Box<Expr>,
+    },
+    EnumVariant {
+        enum_name: String,
+        variant: String,
+    },
+    Conditional {
+        condition: Box<Expr>,
+        then_expr: Box<Expr>,
+        else_expr: Box<Expr>,
+    },
+    Conversion {
+        target: TypeName,
+        operand: Box<Expr>,
+    },
+    Call {
+        name: String,
+        args: Vec<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    GreaterThan,
+    LessThan,
+    GreaterEqual,
+    LessEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeName {
+    Int,
+    Bool,
+    String,
+    Array(Box<TypeName>, ArraySize),
+    // a struct or enum, told apart by the semantic phase
+    Named(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArraySize {
+    Literal(usize),
+    // the name of an int `const`, replaced by its value in the optimizer
+    Constant(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchPattern {
+    Variant(String),
+    Wildcard,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: MatchPattern,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Print(Expr),
+    // print("x = {}, y = {}", x, y): each `{}` is replaced by the next argument
+    PrintFormatted {
+        format: String,
+        args: Vec<Expr>,
+    },
+    VarDeclaration {
+        name: String,
+        ty: TypeName,
+        value: Expr,
+    },
+    ConstDeclaration {
+        name: String,
+        ty: TypeName,
+        value: Expr,
+    },
+    GlobalDeclaration {
+        name: String,
+        ty: TypeName,
+        value: Expr,
+    },
+    If {
+        condition: Expr,
+        then_block: Vec<Stmt>,
+        else_block: Option<Vec<Stmt>>,
+    },
+    Block(Vec<Stmt>),
+    ExprStmt(Expr),
+    StructDeclaration {
+        name: String,
+        fields: Vec<(String, TypeName)>,
+    },
+    EnumDeclaration {
+        name: String,
+        variants: Vec<String>,
+    },
+    Match {
+        scrutinee: Expr,
+        arms: Vec<MatchArm>,
+    },
+    Paywall(i64),
+    // ends the program immediately with the given status
+    Exit(Expr),
+    // ends the program like reaching its end, with the given result as status
+    Return(Expr),
+    Assert {
+        condition: Expr,
+        message: Option<String>,
+        line: usize,
+    },
+}
+
+
+// first we create an enum of Expr, which has all type of Expr we can face in the code and 
+// a binary Expr which has operators in it, then a statement enum which has print and it takes Expr as 
+// its input.
+// a unary Expr applies a prefix operator (- or !) to a single operand.
+// arrays are fixed-size: an array literal lists every element, and an element is read with
+// Index (a[i]) or written with IndexAssign (a[i] = v).
+// structs are declared once with named, typed fields; a struct literal names its type and sets
+// every field, and a field is read with Field (p.x) or written with FieldAssign (p.x = v).
+// enums list their variants; a value is written Color::Red, and a match statement picks the arm
+// whose variant equals the scrutinee (or the `_` wildcard arm).
+// a Conditional (cond ? a : b) evaluates to then_expr when the condition holds and else_expr otherwise.
+// a Conversion (int(x), bool(x), string(x)) turns a value of one scalar type into another.
+// a Call invokes one of the builtin functions (len, substr, ...) by name; there are no user functions.
+// variable declaration; which declares any variables based on their names and their values,it stores names as strings
+// , the declared type (int, bool or string) and value as any of the Expr we defined above.
+// const and global declarations only appear at the top level: a const is a named constant
+// inlined wherever it is used (including array sizes, int[N]), a global is a variable with
+// static storage instead of a slot in main's frame. both need a constant initializer.
+// exit(code) stops the program on the spot; return code finishes it normally and makes code its
+// result. either way code is the process exit status, and a program that just runs off its end
+// exits with 0.
+// assert(cond) or assert(cond, "message") stops the program with a message naming its source line
+// when cond is false.
+// if block is where the condition is defined aka the Expr, then it moves to then_block if the defined 
+// Expr is true or to else_block of the Expr is untrue.
+// 
 // 
\ No newline at end of file
@@ -1,235 +1,667 @@
-use crate::lexing::token::Token;
-use crate::parsing::ast::{Expr, Stmt, BinOp};
-
-
-pub struct Parser {
-    tokens: Vec<Token>,
-    pos: usize,
-}
-
-#[derive(Debug)]
-pub enum ParseError {
-    UnexpectedToken,
-}
-
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, pos: 0 }
-    }
-
-    // ----------------- utilities -----------------
-
-    fn current(&self) -> Option<&Token> {
-        self.tokens.get(self.pos)
-    }
-
-    fn advance(&mut self) {
-        self.pos += 1;
-    }
-
-    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
-        if self.current() == Some(&expected) {
-            self.advance();
-            Ok(())
-        } else {
-            Err(ParseError::UnexpectedToken)
-        }
-    }
-
-    // ----------------- entry -----------------
-
-    pub fn parse(&mut self) -> Result<Vec<Stmt>, ParseError> {
-        let mut stmts = Vec::new();
-
-        while self.pos < self.tokens.len() {
-            stmts.push(self.parse_stmt()?);
-        }
-
-        Ok(vec![Stmt::Block(stmts)])
-    }
-
-    // ----------------- statements -----------------
-
-    fn parse_stmt(&mut self) -> Result<Stmt, ParseError> {
-        match self.current() {
-            Some(Token::Print) => self.parse_print(),
-            Some(Token::If) => self.parse_if(),
-            Some(Token::Int) => self.parse_var_decl(),
-            Some(Token::LeftBrace) => self.parse_block_stmt(),
-            Some(Token::Paywall) => self.parse_paywall(),
-            _ => Err(ParseError::UnexpectedToken),
-        }
-    }
-
-    fn parse_block_stmt(&mut self) -> Result<Stmt, ParseError> {
-        self.expect(Token::LeftBrace)?;
-
-        let mut stmts = Vec::new();
-        while self.current() != Some(&Token::RightBrace) {
-            stmts.push(self.parse_stmt()?);
-        }
-
-        self.expect(Token::RightBrace)?;
-        Ok(Stmt::Block(stmts))
-    }
-
-    fn parse_print(&mut self) -> Result<Stmt, ParseError> {
-        self.advance(); // consume 'print'
-        self.expect(Token::LeftParen)?;
-        let expr = self.parse_expr()?;
-        self.expect(Token::RightParen)?;
-        self.expect(Token::SemiColon)?;
-        Ok(Stmt::Print(expr))
-    }
-
-    fn parse_var_decl(&mut self) -> Result<Stmt, ParseError> {
-        self.advance(); // consume 'int'
-
-        let name = match self.current() {
-            Some(Token::Identifier(id)) => {
-                let n = id.clone();
-                self.advance();
-                n
-            }
-            _ => return Err(ParseError::UnexpectedToken),
-        };
-
-        self.expect(Token::Assign)?;
-        let value = self.parse_expr()?;
-        self.expect(Token::SemiColon)?;
-
-        Ok(Stmt::VarDeclaration { name, value })
-    }
-
-    fn parse_if(&mut self) -> Result<Stmt, ParseError> {
-        self.advance(); // consume 'if'
-        self.expect(Token::LeftParen)?;
-        let condition = self.parse_expr()?;
-        self.expect(Token::RightParen)?;
-
-        let then_block = self.parse_block_stmt()?;
-
-        let else_block = if self.current() == Some(&Token::Else) {
-            self.advance();
-            Some(self.parse_block_stmt()?)
-        } else {
-            None
-        };
-
-        Ok(Stmt::If {
-            condition,
-            then_block: unwrap_block(then_block),
-            else_block: else_block.map(unwrap_block),
-        })
-    }
-
-    fn parse_paywall(&mut self) -> Result<Stmt, ParseError> {
-        self.advance(); // consume 'paywall'
-        self.expect(Token::LeftParen)?;
-        
-        // We expect a simple integer literal inside
-        let amount = match self.current() {
-            Some(Token::IntegerLiteral(n)) => *n as i64,
-            _ => return Err(ParseError::UnexpectedToken),
-        };
-        self.advance(); // consume the number
-        
-        self.expect(Token::RightParen)?;
-        self.expect(Token::SemiColon)?;
-        
-        Ok(Stmt::Paywall(amount))
-    }
-
-    // ----------------- expressions -----------------
-
-    fn parse_expr(&mut self) -> Result<Expr, ParseError> {
-        self.parse_comparison()
-    }
-
-    fn parse_comparison(&mut self) -> Result<Expr, ParseError> {
-        let mut left = self.parse_addition()?;
-
-        while matches!(self.current(), Some(Token::GreaterThan | Token::LessThan)) {
-            let op = match self.current().unwrap() {
-                Token::GreaterThan => BinOp::GreaterThan,
-                Token::LessThan => BinOp::LessThan,
-                _ => unreachable!(),
-            };
-
-            self.advance();
-            let right = self.parse_addition()?;
-
-            left = Expr::Binary {
-                left: Box::new(left),
-                op,
-                right: Box::new(right),
-            };
-        }
-
-        Ok(left)
-    }
-
-    fn parse_addition(&mut self) -> Result<Expr, ParseError> {
-        let mut left = self.parse_primary()?;
-
-        while matches!(self.current(), Some(Token::Plus | Token::Minus)) {
-            let op = match self.current().unwrap() {
-                Token::Plus => BinOp::Add,
-                Token::Minus => BinOp::Sub,
-                _ => unreachable!(),
-            };
-
-            self.advance();
-            let right = self.parse_primary()?;
-
-            left = Expr::Binary {
-                left: Box::new(left),
-                op,
-                right: Box::new(right),
-            };
-        }
-
-        Ok(left)
-    }
-
-    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
-        match self.current() {
-            Some(Token::IntegerLiteral(n)) => {
-                let v = *n as i32; // i64 → i32
-                self.advance();
-                Ok(Expr::IntegerLiteral(v))
-            }
-            Some(Token::StringLiteral(s)) => {
-                let v = s.clone();
-                self.advance();
-                Ok(Expr::StringLiteral(v))
-            }
-            Some(Token::Identifier(id)) => {
-                let v = id.clone();
-                self.advance();
-                Ok(Expr::Identifier(v))
-            }
-            Some(Token::Maybe) => {
-                self.advance();
-                Ok(Expr::Maybe)
-            }
-            Some(Token::LeftParen) => {
-                self.advance();
-                let expr = self.parse_expr()?;
-                self.expect(Token::RightParen)?;
-                Ok(expr)
-            }
-            _ => Err(ParseError::UnexpectedToken),
-        }
-    }
-}
-
-// ----------------- helpers -----------------
-
-fn unwrap_block(stmt: Stmt) -> Vec<Stmt> {
-    if let Stmt::Block(v) = stmt {
-        v
-    } else {
-        unreachable!()
-    }
-}
+use crate::lexing::token::Token;
+use crate::parsing::ast::{Expr, Stmt, BinOp, UnOp, TypeName, ArraySize, MatchArm, MatchPattern};
+
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    UnexpectedToken,
+}
+
+impl Parser {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self { tokens, pos: 0 }
+    }
+
+    // ----------------- utilities -----------------
+
+    fn current(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos + 1)
+    }
+
+    fn advance(&mut self) {
+        self.pos += 1;
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        if self.current() == Some(&expected) {
+            self.advance();
+            Ok(())
+        } else {
+            Err(ParseError::UnexpectedToken)
+        }
+    }
+
+    fn expect_identifier(&mut self) -> Result<String, ParseError> {
+        match self.current() {
+            Some(Token::Identifier(id)) => {
+                let n = id.clone();
+                self.advance();
+                Ok(n)
+            }
+            _ => Err(ParseError::UnexpectedToken),
+        }
+    }
+
+    // ----------------- entry -----------------
+
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut stmts = Vec::new();
+
+        while self.pos < self.tokens.len() {
+            stmts.push(self.parse_top_level()?);
+        }
+
+        Ok(vec![Stmt::Block(stmts)])
+    }
+
+    // ----------------- statements -----------------
+
+    // consts and globals may only be declared outside any block
+    fn parse_top_level(&mut self) -> Result<Stmt, ParseError> {
+        match self.current() {
+            Some(Token::Const) => {
+                self.advance(); // consume 'const'
+                let (name, ty, value) = self.parse_binding()?;
+                Ok(Stmt::ConstDeclaration { name, ty, value })
+            }
+            Some(Token::Global) => {
+                self.advance(); // consume 'global'
+                let (name, ty, value) = self.parse_binding()?;
+                Ok(Stmt::GlobalDeclaration { name, ty, value })
+            }
+            _ => self.parse_stmt(),
+        }
+    }
+
+    fn parse_stmt(&mut self) -> Result<Stmt, ParseError> {
+        match self.current() {
+            Some(Token::Print) => self.parse_print(),
+            Some(Token::If) => self.parse_if(),
+            Some(Token::Int | Token::Bool | Token::String) => self.parse_var_decl(),
+            Some(Token::LeftBrace) => self.parse_block_stmt(),
+            Some(Token::Paywall) => self.parse_paywall(),
+            Some(Token::Exit) => self.parse_exit(),
+            Some(Token::Assert(_)) => self.parse_assert(),
+            Some(Token::Return) => self.parse_return(),
+            Some(Token::Struct) => self.parse_struct_decl(),
+            Some(Token::Enum) => self.parse_enum_decl(),
+            Some(Token::Match) => self.parse_match(),
+            // `Point p = ...` declares a struct variable, anything else assigns
+            Some(Token::Identifier(_)) if matches!(self.peek(), Some(Token::Identifier(_))) => {
+                self.parse_var_decl()
+            }
+            // a builtin called for its effect: f(...);
+            Some(Token::Identifier(_)) if self.peek() == Some(&Token::LeftParen) => {
+                let expr = self.parse_expr()?;
+                self.expect(Token::SemiColon)?;
+                Ok(Stmt::ExprStmt(expr))
+            }
+            Some(Token::Identifier(_)) => self.parse_assignment(),
+            _ => Err(ParseError::UnexpectedToken),
+        }
+    }
+
+    fn parse_block_stmt(&mut self) -> Result<Stmt, ParseError> {
+        self.expect(Token::LeftBrace)?;
+
+        let mut stmts = Vec::new();
+        while self.current() != Some(&Token::RightBrace) {
+            stmts.push(self.parse_stmt()?);
+        }
+
+        self.expect(Token::RightBrace)?;
+        Ok(Stmt::Block(stmts))
+    }
+
+    fn parse_print(&mut self) -> Result<Stmt, ParseError> {
+        self.advance(); // consume 'print'
+        self.expect(Token::LeftParen)?;
+        let expr = self.parse_expr()?;
+
+        // further arguments make the first one a format string
+        let mut args = Vec::new();
+        while self.current() == Some(&Token::Comma) {
+            self.advance();
+            args.push(self.parse_expr()?);
+        }
+
+        self.expect(Token::RightParen)?;
+        self.expect(Token::SemiColon)?;
+
+        if args.is_empty() {
+            return Ok(Stmt::Print(expr));
+        }
+        match expr {
+            Expr::StringLiteral(format) => Ok(Stmt::PrintFormatted { format, args }),
+            _ => Err(ParseError::UnexpectedToken),
+        }
+    }
+
+    fn parse_var_decl(&mut self) -> Result<Stmt, ParseError> {
+        let (name, ty, value) = self.parse_binding()?;
+        Ok(Stmt::VarDeclaration { name, ty, value })
+    }
+
+    // `<type> <name> = <expr>;`, shared by variable, const and global declarations
+    fn parse_binding(&mut self) -> Result<(String, TypeName, Expr), ParseError> {
+        let ty = self.parse_type()?;
+
+        let name = match self.current() {
+            Some(Token::Identifier(id)) => {
+                let n = id.clone();
+                self.advance();
+                n
+            }
+            _ => return Err(ParseError::UnexpectedToken),
+        };
+
+        self.expect(Token::Assign)?;
+        let value = self.parse_expr()?;
+        self.expect(Token::SemiColon)?;
+
+        Ok((name, ty, value))
+    }
+
+    fn parse_struct_decl(&mut self) -> Result<Stmt, ParseError> {
+        self.advance(); // consume 'struct'
+        let name = self.expect_identifier()?;
+        self.expect(Token::LeftBrace)?;
+
+        let mut fields = Vec::new();
+        while self.current() != Some(&Token::RightBrace) {
+            let ty = self.parse_type()?;
+            let field = self.expect_identifier()?;
+            self.expect(Token::SemiColon)?;
+            fields.push((field, ty));
+        }
+
+        self.expect(Token::RightBrace)?;
+        Ok(Stmt::StructDeclaration { name, fields })
+    }
+
+    fn parse_enum_decl(&mut self) -> Result<Stmt, ParseError> {
+        self.advance(); // consume 'enum'
+        let name = self.expect_identifier()?;
+        self.expect(Token::LeftBrace)?;
+
+        let mut variants = vec![self.expect_identifier()?];
+        while self.current() == Some(&Token::Comma) {
+            self.advance();
+            // allow a trailing comma
+            if self.current() == Some(&Token::RightBrace) {
+                break;
+            }
+            variants.push(self.expect_identifier()?);
+        }
+
+        self.expect(Token::RightBrace)?;
+        Ok(Stmt::EnumDeclaration { name, variants })
+    }
+
+    fn parse_match(&mut self) -> Result<Stmt, ParseError> {
+        self.advance(); // consume 'match'
+        self.expect(Token::LeftParen)?;
+        let scrutinee = self.parse_expr()?;
+        self.expect(Token::RightParen)?;
+        self.expect(Token::LeftBrace)?;
+
+        let mut arms = Vec::new();
+        while self.current() != Some(&Token::RightBrace) {
+            let pattern = match self.expect_identifier()?.as_str() {
+                "_" => MatchPattern::Wildcard,
+                variant => MatchPattern::Variant(variant.to_string()),
+            };
+            self.expect(Token::FatArrow)?;
+            let body = unwrap_block(self.parse_block_stmt()?);
+            arms.push(MatchArm { pattern, body });
+        }
+
+        self.expect(Token::RightBrace)?;
+        Ok(Stmt::Match { scrutinee, arms })
+    }
+
+    fn parse_type(&mut self) -> Result<TypeName, ParseError> {
+        let ty = match self.current() {
+            Some(Token::Int) => TypeName::Int,
+            Some(Token::Bool) => TypeName::Bool,
+            Some(Token::String) => TypeName::String,
+            Some(Token::Identifier(name)) => {
+                let ty = TypeName::Named(name.clone());
+                self.advance();
+                return Ok(ty);
+            }
+            _ => return Err(ParseError::UnexpectedToken),
+        };
+        self.advance();
+
+        // optional fixed size: int[5] or int[N]
+        if self.current() != Some(&Token::LeftBracket) {
+            return Ok(ty);
+        }
+        self.advance();
+
+        let size = match self.current() {
+            Some(Token::IntegerLiteral(n)) if *n > 0 => ArraySize::Literal(*n as usize),
+            Some(Token::Identifier(name)) => ArraySize::Constant(name.clone()),
+            _ => return Err(ParseError::UnexpectedToken),
+        };
+        self.advance();
+        self.expect(Token::RightBracket)?;
+
+        Ok(TypeName::Array(Box::new(ty), size))
+    }
+
+    fn parse_assignment(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.expect_identifier()?;
+
+        let target = match self.current() {
+            Some(Token::LeftBracket) => {
+                self.advance();
+                let index = self.parse_expr()?;
+                self.expect(Token::RightBracket)?;
+                Expr::Index { name, index: Box::new(index) }
+            }
+            Some(Token::Dot) => {
+                self.advance();
+                let field = self.expect_identifier()?;
+                Expr::Field { name, field }
+            }
+            _ => Expr::Identifier(name),
+        };
+
+        self.expect(Token::Assign)?;
+        let value = Box::new(self.parse_expr()?);
+        self.expect(Token::SemiColon)?;
+
+        let expr = match target {
+            Expr::Index { name, index } => Expr::IndexAssign { name, index, value },
+            Expr::Field { name, field } => Expr::FieldAssign { name, field, value },
+            Expr::Identifier(name) => Expr::Assign { name, value },
+            _ => unreachable!(),
+        };
+
+        Ok(Stmt::ExprStmt(expr))
+    }
+
+    fn parse_if(&mut self) -> Result<Stmt, ParseError> {
+        self.advance(); // consume 'if'
+        self.expect(Token::LeftParen)?;
+        let condition = self.parse_expr()?;
+        self.expect(Token::RightParen)?;
+
+        let then_block = self.parse_block_stmt()?;
+
+        let else_block = if self.current() == Some(&Token::Else) {
+            self.advance();
+            Some(self.parse_block_stmt()?)
+        } else {
+            None
+        };
+
+        Ok(Stmt::If {
+            condition,
+            then_block: unwrap_block(then_block),
+            else_block: else_block.map(unwrap_block),
+        })
+    }
+
+    fn parse_exit(&mut self) -> Result<Stmt, ParseError> {
+        self.advance(); // consume 'exit'
+        self.expect(Token::LeftParen)?;
+        let code = self.parse_expr()?;
+        self.expect(Token::RightParen)?;
+        self.expect(Token::SemiColon)?;
+        Ok(Stmt::Exit(code))
+    }
+
+    fn parse_return(&mut self) -> Result<Stmt, ParseError> {
+        self.advance(); // consume 'return'
+        let code = self.parse_expr()?;
+        self.expect(Token::SemiColon)?;
+        Ok(Stmt::Return(code))
+    }
+
+    fn parse_assert(&mut self) -> Result<Stmt, ParseError> {
+        let line = match self.current() {
+            Some(Token::Assert(line)) => *line,
+            _ => return Err(ParseError::UnexpectedToken),
+        };
+        self.advance(); // consume 'assert'
+        self.expect(Token::LeftParen)?;
+        let condition = self.parse_expr()?;
+
+        let message = if self.current() == Some(&Token::Comma) {
+            self.advance();
+            match self.current() {
+                Some(Token::StringLiteral(s)) => {
+                    let s = s.clone();
+                    self.advance();
+                    Some(s)
+                }
+                _ => return Err(ParseError::UnexpectedToken),
+            }
+        } else {
+            None
+        };
+
+        self.expect(Token::RightParen)?;
+        self.expect(Token::SemiColon)?;
+        Ok(Stmt::Assert { condition, message, line })
+    }
+
+    fn parse_paywall(&mut self) -> Result<Stmt, ParseError> {
+        self.advance(); // consume 'paywall'
+        self.expect(Token::LeftParen)?;
+        
+        // We expect a simple integer literal inside
+        let amount = match self.current() {
+            Some(Token::IntegerLiteral(n)) => *n,
+            _ => return Err(ParseError::UnexpectedToken),
+        };
+        self.advance(); // consume the number
+        
+        self.expect(Token::RightParen)?;
+        self.expect(Token::SemiColon)?;
+        
+        Ok(Stmt::Paywall(amount))
+    }
+
+    // ----------------- expressions -----------------
+
+    fn parse_expr(&mut self) -> Result<Expr, ParseError> {
+        self.parse_conditional()
+    }
+
+    // `?:` binds loosest and groups to the right: a ? b : c ? d : e
+    fn parse_conditional(&mut self) -> Result<Expr, ParseError> {
+        let condition = self.parse_or()?;
+
+        if self.current() != Some(&Token::Question) {
+            return Ok(condition);
+        }
+        self.advance();
+
+        let then_expr = self.parse_expr()?;
+        self.expect(Token::Colon)?;
+        let else_expr = self.parse_conditional()?;
+
+        Ok(Expr::Conditional {
+            condition: Box::new(condition),
+            then_expr: Box::new(then_expr),
+            else_expr: Box::new(else_expr),
+        })
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.parse_and()?;
+
+        while self.current() == Some(&Token::Or) {
+            self.advance();
+            let right = self.parse_and()?;
+
+            left = Expr::Binary {
+                left: Box::new(left),
+                op: BinOp::Or,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.parse_comparison()?;
+
+        while self.current() == Some(&Token::And) {
+            self.advance();
+            let right = self.parse_comparison()?;
+
+            left = Expr::Binary {
+                left: Box::new(left),
+                op: BinOp::And,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.parse_addition()?;
+
+        while matches!(
+            self.current(),
+            Some(
+                Token::GreaterThan
+                    | Token::LessThan
+                    | Token::GreaterEqual
+                    | Token::LessEqual
+                    | Token::Equal
+                    | Token::NotEqual
+            )
+        ) {
+            let op = match self.current().unwrap() {
+                Token::GreaterThan => BinOp::GreaterThan,
+                Token::LessThan => BinOp::LessThan,
+                Token::GreaterEqual => BinOp::GreaterEqual,
+                Token::LessEqual => BinOp::LessEqual,
+                Token::Equal => BinOp::Equal,
+                Token::NotEqual => BinOp::NotEqual,
+                _ => unreachable!(),
+            };
+
+            self.advance();
+            let right = self.parse_addition()?;
+
+            left = Expr::Binary {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn parse_addition(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.parse_multiplication()?;
+
+        while matches!(self.current(), Some(Token::Plus | Token::Minus)) {
+            let op = match self.current().unwrap() {
+                Token::Plus => BinOp::Add,
+                Token::Minus => BinOp::Sub,
+                _ => unreachable!(),
+            };
+
+            self.advance();
+            let right = self.parse_multiplication()?;
+
+            left = Expr::Binary {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn parse_multiplication(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.parse_unary()?;
+
+        while matches!(self.current(), Some(Token::Star | Token::Slash | Token::Percent)) {
+            let op = match self.current().unwrap() {
+                Token::Star => BinOp::Mul,
+                Token::Slash => BinOp::Div,
+                Token::Percent => BinOp::Mod,
+                _ => unreachable!(),
+            };
+
+            self.advance();
+            let right = self.parse_unary()?;
+
+            left = Expr::Binary {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        let op = match self.current() {
+            Some(Token::Minus) => UnOp::Neg,
+            Some(Token::Not) => UnOp::Not,
+            _ => return self.parse_primary(),
+        };
+
+        self.advance();
+        let operand = self.parse_unary()?;
+
+        Ok(Expr::Unary {
+            op,
+            operand: Box::new(operand),
+        })
+    }
+
+    fn parse_struct_literal(&mut self, name: String) -> Result<Expr, ParseError> {
+        self.expect(Token::LeftBrace)?;
+
+        let mut fields = Vec::new();
+        while self.current() != Some(&Token::RightBrace) {
+            let field = self.expect_identifier()?;
+            self.expect(Token::Colon)?;
+            fields.push((field, self.parse_expr()?));
+
+            if self.current() != Some(&Token::Comma) {
+                break;
+            }
+            self.advance();
+        }
+
+        self.expect(Token::RightBrace)?;
+        Ok(Expr::StructLiteral { name, fields })
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        match self.current() {
+            Some(Token::IntegerLiteral(n)) => {
+                let v = *n as i32; // i64 → i32
+                self.advance();
+                Ok(Expr::IntegerLiteral(v))
+            }
+            Some(Token::StringLiteral(s)) => {
+                let v = s.clone();
+                self.advance();
+                Ok(Expr::StringLiteral(v))
+            }
+            Some(Token::Identifier(id)) => {
+                let v = id.clone();
+                self.advance();
+
+                match self.current() {
+                    Some(Token::LeftBracket) => {
+                        self.advance();
+                        let index = self.parse_expr()?;
+                        self.expect(Token::RightBracket)?;
+                        Ok(Expr::Index {
+                            name: v,
+                            index: Box::new(index),
+                        })
+                    }
+                    Some(Token::Dot) => {
+                        self.advance();
+                        let field = self.expect_identifier()?;
+                        Ok(Expr::Field { name: v, field })
+                    }
+                    Some(Token::LeftBrace) => self.parse_struct_literal(v),
+                    Some(Token::DoubleColon) => {
+                        self.advance();
+                        let variant = self.expect_identifier()?;
+                        Ok(Expr::EnumVariant { enum_name: v, variant })
+                    }
+                    Some(Token::LeftParen) => {
+                        self.advance();
+                        let mut args = Vec::new();
+                        if self.current() != Some(&Token::RightParen) {
+                            args.push(self.parse_expr()?);
+                            while self.current() == Some(&Token::Comma) {
+                                self.advance();
+                                args.push(self.parse_expr()?);
+                            }
+                        }
+                        self.expect(Token::RightParen)?;
+                        Ok(Expr::Call { name: v, args })
+                    }
+                    _ => Ok(Expr::Identifier(v)),
+                }
+            }
+            // int(x), bool(x), string(x)
+            Some(Token::Int | Token::Bool | Token::String) if self.peek() == Some(&Token::LeftParen) => {
+                let target = match self.current() {
+                    Some(Token::Int) => TypeName::Int,
+                    Some(Token::Bool) => TypeName::Bool,
+                    _ => TypeName::String,
+                };
+                self.advance();
+                self.expect(Token::LeftParen)?;
+                let operand = self.parse_expr()?;
+                self.expect(Token::RightParen)?;
+                Ok(Expr::Conversion {
+                    target,
+                    operand: Box::new(operand),
+                })
+            }
+            Some(Token::LeftBracket) => {
+                self.advance();
+                let mut elements = vec![self.parse_expr()?];
+                while self.current() == Some(&Token::Comma) {
+                    self.advance();
+                    elements.push(self.parse_expr()?);
+                }
+                self.expect(Token::RightBracket)?;
+                Ok(Expr::ArrayLiteral(elements))
+            }
+            Some(Token::Maybe) => {
+                self.advance();
+                Ok(Expr::Maybe)
+            }
+            Some(Token::True) => {
+                self.advance();
+                Ok(Expr::BooleanLiteral(true))
+            }
+            Some(Token::False) => {
+                self.advance();
+                Ok(Expr::BooleanLiteral(false))
+            }
+            Some(Token::LeftParen) => {
+                self.advance();
+                let expr = self.parse_expr()?;
+                self.expect(Token::RightParen)?;
+                Ok(expr)
+            }
+            _ => Err(ParseError::UnexpectedToken),
+        }
+    }
+}
+
+// ----------------- helpers -----------------
+
+fn unwrap_block(stmt: Stmt) -> Vec<Stmt> {
+    if let Stmt::Block(v) = stmt {
+        v
+    } else {
+        unreachable!()
+    }
+}
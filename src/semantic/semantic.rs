@@ -1,255 +1,984 @@
-use std::collections::HashMap;
-use std::io::{self, Write};
-use crate::parsing::ast::{Expr, Stmt, BinOp};
-
-#[derive(Debug, Clone, PartialEq)]
-pub enum Type {
-    Int,
-    String,
-    Bool,
-    Unknown,
-}
-
-#[derive(Debug, Clone)]
-pub enum SemanticError {
-    UndeclaredVariable(String),
-    Redeclaration(String),
-    TypeMismatch {
-        expected: Type,
-        found: Type,
-        context: String,
-    },
-}
-
-pub struct SemanticAnalyzer {
-    scopes: Vec<HashMap<String, Type>>,
-    errors: Vec<SemanticError>,
-}
-
-impl SemanticAnalyzer {
-    pub fn new() -> Self {
-        Self {
-            scopes: vec![HashMap::new()],
-            errors: Vec::new(),
-        }
-    }
-
-    // ---------- scopes ----------
-
-    fn enter_scope(&mut self) {
-        self.scopes.push(HashMap::new());
-    }
-
-    fn exit_scope(&mut self) {
-        self.scopes.pop();
-    }
-
-    fn current_scope(&mut self) -> &mut HashMap<String, Type> {
-        self.scopes.last_mut().unwrap()
-    }
-
-    fn lookup(&self, name: &str) -> Option<Type> {
-        for scope in self.scopes.iter().rev() {
-            if let Some(t) = scope.get(name) {
-                return Some(t.clone());
-            }
-        }
-        None
-    }
-
-    fn error(&mut self, err: SemanticError) {
-        self.errors.push(err);
-    }
-
-    // ---------- entry ----------
-
-    pub fn analyze(&mut self, stmts: &[Stmt]) -> Result<(), Vec<SemanticError>> {
-        for s in stmts {
-            self.check_stmt(s);
-        }
-
-        if self.errors.is_empty() {
-            Ok(())
-        } else {
-            Err(self.errors.clone())
-        }
-    }
-
-    // ---------- statements ----------
-
-    fn check_stmt(&mut self, stmt: &Stmt) {
-    match stmt {
-        Stmt::Block(stmts) => {
-            self.enter_scope();
-            for s in stmts {
-                self.check_stmt(s);
-            }
-            self.exit_scope();
-        }
-
-        Stmt::VarDeclaration { name, value } => {
-            self.check_var_decl(name, value);
-        }
-
-        Stmt::Print(expr) => {
-            self.check_expr(expr);
-        }
-
-        Stmt::If { condition, then_block, else_block } => {
-            self.check_if(condition, then_block, else_block);
-        }
-
-        Stmt::ExprStmt(expr) => {
-            self.check_expr(expr);
-        }
-
-        Stmt::Paywall(_) => {
-        }
-    }
-}
-
-
-    fn check_var_decl(&mut self, name: &str, value: &Expr) {
-        if self.current_scope().contains_key(name) {
-            self.error(SemanticError::Redeclaration(name.to_string()));
-            return;
-        }
-
-        let value_type = self.check_expr(value);
-
-        if value_type != Type::Int && value_type != Type::Unknown {
-            self.error(SemanticError::TypeMismatch {
-                expected: Type::Int,
-                found: value_type,
-                context: format!("initializer for '{}' must be Int", name),
-            });
-        }
-
-        self.current_scope().insert(name.to_string(), Type::Int);
-    }
-
-    fn check_if(&mut self, cond: &Expr, then_block: &[Stmt], else_block: &Option<Vec<Stmt>>) {
-        let cond_type = self.check_expr(cond);
-
-        if cond_type != Type::Bool && cond_type != Type::Unknown {
-            self.error(SemanticError::TypeMismatch {
-                expected: Type::Bool,
-                found: cond_type,
-                context: "if condition must be boolean".to_string(),
-            });
-        }
-
-        self.enter_scope();
-        for s in then_block {
-            self.check_stmt(s);
-        }
-        self.exit_scope();
-
-        if let Some(stmts) = else_block {
-            self.enter_scope();
-            for s in stmts {
-                self.check_stmt(s);
-            }
-            self.exit_scope();
-        }
-    }
-
-    // ---------- expressions ----------
-
-    fn check_expr(&mut self, expr: &Expr) -> Type {
-        match expr {
-            Expr::IntegerLiteral(_) => Type::Int,
-            Expr::StringLiteral(_) => Type::String,
-            Expr::BooleanLiteral(_) => Type::Bool,
-            Expr::Maybe => Type::Bool,
-            Expr::Identifier(name) => {
-                if let Some(t) = self.lookup(name) {
-                    t 
-                } else {
-                    println!("wait, variable {} is undefined. \n should i pretend its an INT? (y/n:)", name);
-                    io::stdout().flush().unwrap();
-
-                    let mut input = String::new();
-                    io::stdin().read_line(&mut input).unwrap();
-                    if input.trim() == "y" {
-                        println!("okay, i'll treat {} as an INT for now", name);
-                        self.current_scope().insert(name.clone(), Type::Int);
-                        Type::Int
-                    } else {
-                        self.error(SemanticError::UndeclaredVariable(name.clone()));
-                        Type::Unknown
-                    }
-                }
-            }
-
-            Expr::Assign { name, value } => {
-                let var_type = self.lookup(name).unwrap_or_else(|| {
-                    self.error(SemanticError::UndeclaredVariable(name.clone()));
-                    Type::Unknown
-                });
-
-                let value_type = self.check_expr(value);
-
-                if var_type != Type::Unknown && value_type != Type::Unknown && var_type != value_type {
-                    self.error(SemanticError::TypeMismatch {
-                        expected: var_type.clone(),
-                        found: value_type.clone(),
-                        context: format!("cannot assign to '{}'", name),
-                    });
-                }
-
-                var_type
-            }
-
-            Expr::Binary { left, op, right } => self.check_binary(left, op, right),
-        }
-    }
-
-    fn check_binary(&mut self, left: &Expr, op: &BinOp, right: &Expr) -> Type {
-        let lt = self.check_expr(left);
-        let rt = self.check_expr(right);
-
-        match op {
-            BinOp::Add => {
-                if lt == Type::Int && rt == Type::Int {
-                    Type::Int
-                } else if lt == Type::String && rt == Type::String {
-                    Type::String
-                } else {
-                    self.error(SemanticError::TypeMismatch {
-                        expected: lt,
-                        found: rt,
-                        context: "invalid '+' operands".to_string(),
-                    });
-                    Type::Unknown
-                }
-            }
-
-            BinOp::Sub => {
-                if lt == Type::Int && rt == Type::Int {
-                    Type::Int
-                } else {
-                    self.error(SemanticError::TypeMismatch {
-                        expected: Type::Int,
-                        found: if lt != Type::Int { lt } else { rt },
-                        context: "subtraction requires Int".to_string(),
-                    });
-                    Type::Unknown
-                }
-            }
-
-            BinOp::GreaterThan | BinOp::LessThan => {
-                if lt == Type::Int && rt == Type::Int {
-                    Type::Bool
-                } else {
-                    self.error(SemanticError::TypeMismatch {
-                        expected: Type::Int,
-                        found: if lt != Type::Int { lt } else { rt },
-                        context: "comparison requires Int".to_string(),
-                    });
-                    Type::Unknown
-                }
-            }
-        }
-    }
-}
+use std::collections::HashMap;
+use std::io::{self, Write};
+use crate::optimizer::optimizer::{ConstValue, Optimizer};
+use crate::parsing::ast::{Expr, Stmt, BinOp, UnOp, TypeName, ArraySize, MatchArm, MatchPattern};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    String,
+    Bool,
+    Array(Box<Type>, usize),
+    Struct(String),
+    Enum(String),
+    Unknown,
+}
+
+// a named type is an enum when one of that name was declared, else a struct
+pub fn resolve_type_name(ty: &TypeName, enums: &HashMap<String, Vec<String>>) -> Type {
+    match ty {
+        TypeName::Int => Type::Int,
+        TypeName::Bool => Type::Bool,
+        TypeName::String => Type::String,
+        TypeName::Array(elem, ArraySize::Literal(len)) => {
+            Type::Array(Box::new(resolve_type_name(elem, enums)), *len)
+        }
+        TypeName::Array(_, ArraySize::Constant(name)) => {
+            unreachable!("array size '{}' is inlined before types are resolved", name)
+        }
+        TypeName::Named(name) if enums.contains_key(name) => Type::Enum(name.clone()),
+        TypeName::Named(name) => Type::Struct(name.clone()),
+    }
+}
+
+// the builtin function table: parameter types and return type by name
+pub fn builtin_signature(name: &str) -> Option<(Vec<Type>, Type)> {
+    let signature = match name {
+        "len" => (vec![Type::String], Type::Int),
+        "substr" => (vec![Type::String, Type::Int, Type::Int], Type::String),
+        "index_of" => (vec![Type::String, Type::String], Type::Int),
+        "to_upper" => (vec![Type::String], Type::String),
+        "char_at" => (vec![Type::String, Type::Int], Type::String),
+        "read_int" => (vec![], Type::Int),
+        "read_line" => (vec![], Type::String),
+        "args" => (vec![Type::Int], Type::String),
+        "arg_count" => (vec![], Type::Int),
+        _ => return None,
+    };
+    Some(signature)
+}
+
+// what a later phase knows about the names in scope, enough to type an
+// expression that passed analysis again
+pub trait TypeEnv {
+    fn var_type(&self, name: &str) -> Option<Type>;
+    fn field_type(&self, struct_name: &str, field: &str) -> Option<Type>;
+    fn resolve(&self, ty: &TypeName) -> Type;
+}
+
+// the type of an expression that passed analysis, by the same rules
+// check_expr applies; None for one it would have rejected
+pub fn expr_type(expr: &Expr, env: &impl TypeEnv) -> Option<Type> {
+    match expr {
+        Expr::IntegerLiteral(_) => Some(Type::Int),
+        Expr::StringLiteral(_) => Some(Type::String),
+        Expr::BooleanLiteral(_) | Expr::Maybe => Some(Type::Bool),
+        Expr::Identifier(name) | Expr::Assign { name, .. } => env.var_type(name),
+        Expr::ArrayLiteral(elements) => {
+            Some(Type::Array(Box::new(expr_type(elements.first()?, env)?), elements.len()))
+        }
+        Expr::Index { name, .. } | Expr::IndexAssign { name, .. } => match env.var_type(name)? {
+            Type::Array(elem, _) => Some(*elem),
+            _ => None,
+        },
+        Expr::StructLiteral { name, .. } => Some(Type::Struct(name.clone())),
+        Expr::EnumVariant { enum_name, .. } => Some(Type::Enum(enum_name.clone())),
+        Expr::Field { name, field } | Expr::FieldAssign { name, field, .. } => {
+            match env.var_type(name)? {
+                Type::Struct(struct_name) => env.field_type(&struct_name, field),
+                _ => None,
+            }
+        }
+        Expr::Conditional { then_expr, else_expr, .. } => {
+            let then_type = expr_type(then_expr, env)?;
+            (expr_type(else_expr, env)? == then_type).then_some(then_type)
+        }
+        Expr::Conversion { target, operand } => {
+            conversion_type(env.resolve(target), &expr_type(operand, env)?)
+        }
+        Expr::Call { name, .. } => builtin_signature(name).map(|(_, ret)| ret),
+        Expr::Unary { op, operand } => unary_type(op, &expr_type(operand, env)?),
+        Expr::Binary { left, op, right } => {
+            binary_type(op, &expr_type(left, env)?, &expr_type(right, env)?)
+        }
+    }
+}
+
+// int accepts int, bool (0 / 1) and string (parsed, may fail at runtime);
+// bool accepts bool and int (non-zero); string accepts all three
+fn conversion_type(target: Type, from: &Type) -> Option<Type> {
+    let allowed = match target {
+        Type::Int | Type::String => matches!(from, Type::Int | Type::Bool | Type::String),
+        Type::Bool => matches!(from, Type::Int | Type::Bool),
+        _ => false,
+    };
+    allowed.then_some(target)
+}
+
+fn unary_type(op: &UnOp, operand: &Type) -> Option<Type> {
+    let expected = match op {
+        UnOp::Neg => Type::Int,
+        UnOp::Not => Type::Bool,
+    };
+    (*operand == expected).then_some(expected)
+}
+
+fn binary_type(op: &BinOp, left: &Type, right: &Type) -> Option<Type> {
+    let both = |t: Type| (*left == t && *right == t).then_some(t);
+    match op {
+        BinOp::Add => both(Type::Int).or_else(|| both(Type::String)),
+        BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => both(Type::Int),
+        BinOp::GreaterThan
+        | BinOp::LessThan
+        | BinOp::GreaterEqual
+        | BinOp::LessEqual
+        | BinOp::Equal
+        | BinOp::NotEqual => {
+            // enums only have identity, not an order
+            let equality = matches!(op, BinOp::Equal | BinOp::NotEqual);
+            let comparable = matches!(left, Type::Int | Type::Bool | Type::String)
+                || equality && matches!(left, Type::Enum(_));
+            (left == right && comparable).then_some(Type::Bool)
+        }
+        BinOp::And | BinOp::Or => both(Type::Bool),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SemanticError {
+    UndeclaredVariable(String),
+    Redeclaration(String),
+    TypeMismatch {
+        expected: Type,
+        found: Type,
+        context: String,
+    },
+    NotAnArray(String),
+    IndexOutOfBounds {
+        name: String,
+        index: i64,
+        len: usize,
+    },
+    UndeclaredType(String),
+    NotAStruct(String),
+    UnknownField {
+        struct_name: String,
+        field: String,
+    },
+    MissingField {
+        struct_name: String,
+        field: String,
+    },
+    UnknownVariant {
+        enum_name: String,
+        variant: String,
+    },
+    NonExhaustiveMatch {
+        enum_name: String,
+        missing: Vec<String>,
+    },
+    // a const or global initializer that cannot be evaluated at compile time
+    NotConstant(String),
+    // consts and globals hold a single int, bool, string or enum value
+    InvalidStaticType(String),
+    AssignToConstant(String),
+    InvalidArraySize(String),
+    // a formatted print whose `{}` count differs from its argument count
+    PlaceholderCount {
+        placeholders: usize,
+        args: usize,
+    },
+    UnknownFunction(String),
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum SemanticWarning {
+    UnreachableArm {
+        enum_name: String,
+        pattern: String,
+    },
+}
+
+pub struct SemanticAnalyzer {
+    scopes: Vec<HashMap<String, Type>>,
+    // struct name -> fields in declaration order
+    structs: HashMap<String, Vec<(String, Type)>>,
+    // enum name -> variants in declaration order
+    enums: HashMap<String, Vec<String>>,
+    // const name -> value; consts cannot be shadowed, so the names are global
+    consts: HashMap<String, ConstValue>,
+    errors: Vec<SemanticError>,
+    warnings: Vec<SemanticWarning>,
+}
+
+impl Default for SemanticAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SemanticAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            consts: HashMap::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    // ---------- scopes ----------
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn exit_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn current_scope(&mut self) -> &mut HashMap<String, Type> {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn lookup(&self, name: &str) -> Option<Type> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(t) = scope.get(name) {
+                return Some(t.clone());
+            }
+        }
+        None
+    }
+
+    // converts a written type, reporting structs that were never declared
+    // and array sizes that are not a positive int const
+    fn resolve_type(&mut self, ty: &TypeName) -> Type {
+        if let TypeName::Array(elem, ArraySize::Constant(name)) = ty {
+            return match self.consts.get(name) {
+                Some(ConstValue::Int(n)) if *n > 0 => {
+                    let elem = resolve_type_name(elem, &self.enums);
+                    Type::Array(Box::new(elem), *n as usize)
+                }
+                _ => {
+                    self.error(SemanticError::InvalidArraySize(name.clone()));
+                    Type::Unknown
+                }
+            };
+        }
+
+        let t = resolve_type_name(ty, &self.enums);
+        if let Type::Struct(name) = &t
+            && !self.structs.contains_key(name)
+        {
+            self.error(SemanticError::UndeclaredType(name.clone()));
+            return Type::Unknown;
+        }
+        t
+    }
+
+    fn error(&mut self, err: SemanticError) {
+        self.errors.push(err);
+    }
+
+    fn warn(&mut self, warning: SemanticWarning) {
+        self.warnings.push(warning);
+    }
+
+    // warnings never fail analysis; they are available once it has run
+    pub fn warnings(&self) -> &[SemanticWarning] {
+        &self.warnings
+    }
+
+    // ---------- entry ----------
+
+    pub fn analyze(&mut self, stmts: &[Stmt]) -> Result<(), Vec<SemanticError>> {
+        for s in stmts {
+            self.check_stmt(s);
+        }
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors.clone())
+        }
+    }
+
+    // ---------- statements ----------
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+    match stmt {
+        Stmt::Block(stmts) => {
+            self.enter_scope();
+            for s in stmts {
+                self.check_stmt(s);
+            }
+            self.exit_scope();
+        }
+
+        Stmt::VarDeclaration { name, ty, value } => {
+            self.check_var_decl(name, ty, value);
+        }
+
+        Stmt::ConstDeclaration { name, ty, value } => {
+            if let Some(c) = self.check_static_decl(name, ty, value) {
+                self.consts.insert(name.clone(), c);
+            }
+        }
+
+        Stmt::GlobalDeclaration { name, ty, value } => {
+            self.check_static_decl(name, ty, value);
+        }
+
+        Stmt::Print(expr) => {
+            let t = self.check_expr(expr);
+            if let Type::Array(..) | Type::Struct(_) | Type::Enum(_) = t {
+                self.error(SemanticError::TypeMismatch {
+                    expected: Type::Int,
+                    found: t,
+                    context: "print takes an Int, Bool or String".to_string(),
+                });
+            }
+        }
+
+        Stmt::PrintFormatted { format, args } => {
+            let placeholders = format.matches("{}").count();
+            if placeholders != args.len() {
+                self.error(SemanticError::PlaceholderCount {
+                    placeholders,
+                    args: args.len(),
+                });
+            }
+
+            for arg in args {
+                let t = self.check_expr(arg);
+                if let Type::Array(..) | Type::Struct(_) | Type::Enum(_) = t {
+                    self.error(SemanticError::TypeMismatch {
+                        expected: Type::Int,
+                        found: t,
+                        context: "print arguments must be Int, Bool or String".to_string(),
+                    });
+                }
+            }
+        }
+
+        Stmt::If { condition, then_block, else_block } => {
+            self.check_if(condition, then_block, else_block);
+        }
+
+        Stmt::ExprStmt(expr) => {
+            self.check_expr(expr);
+        }
+
+        Stmt::StructDeclaration { name, fields } => {
+            self.check_struct_decl(name, fields);
+        }
+
+        Stmt::EnumDeclaration { name, variants } => {
+            self.check_enum_decl(name, variants);
+        }
+
+        Stmt::Match { scrutinee, arms } => {
+            self.check_match(scrutinee, arms);
+        }
+
+        Stmt::Paywall(_) => {
+        }
+
+        Stmt::Assert { condition, .. } => {
+            let t = self.check_expr(condition);
+            if t != Type::Bool && t != Type::Unknown {
+                self.error(SemanticError::TypeMismatch {
+                    expected: Type::Bool,
+                    found: t,
+                    context: "assert condition must be boolean".to_string(),
+                });
+            }
+        }
+
+        Stmt::Exit(code) | Stmt::Return(code) => {
+            let t = self.check_expr(code);
+            if t != Type::Int && t != Type::Unknown {
+                let statement = if matches!(stmt, Stmt::Exit(_)) { "exit" } else { "return" };
+                self.error(SemanticError::TypeMismatch {
+                    expected: Type::Int,
+                    found: t,
+                    context: format!("{} status must be Int", statement),
+                });
+            }
+        }
+    }
+}
+
+    fn check_struct_decl(&mut self, name: &str, fields: &[(String, TypeName)]) {
+        if self.structs.contains_key(name) || self.enums.contains_key(name) {
+            self.error(SemanticError::Redeclaration(name.to_string()));
+            return;
+        }
+
+        let mut checked: Vec<(String, Type)> = Vec::new();
+        for (field, ty) in fields {
+            if checked.iter().any(|(f, _)| f == field) {
+                self.error(SemanticError::Redeclaration(format!("{}.{}", name, field)));
+                continue;
+            }
+
+            let t = self.resolve_type(ty);
+            if !matches!(t, Type::Int | Type::Bool | Type::String | Type::Enum(_) | Type::Unknown) {
+                self.error(SemanticError::TypeMismatch {
+                    expected: Type::Int,
+                    found: t.clone(),
+                    context: format!("field '{}.{}' must be Int, Bool, String or an enum", name, field),
+                });
+            }
+            checked.push((field.clone(), t));
+        }
+
+        self.structs.insert(name.to_string(), checked);
+    }
+
+    fn check_enum_decl(&mut self, name: &str, variants: &[String]) {
+        if self.structs.contains_key(name) || self.enums.contains_key(name) {
+            self.error(SemanticError::Redeclaration(name.to_string()));
+            return;
+        }
+
+        let mut checked: Vec<String> = Vec::new();
+        for v in variants {
+            if checked.contains(v) {
+                self.error(SemanticError::Redeclaration(format!("{}::{}", name, v)));
+            } else {
+                checked.push(v.clone());
+            }
+        }
+
+        self.enums.insert(name.to_string(), checked);
+    }
+
+    fn check_match(&mut self, scrutinee: &Expr, arms: &[MatchArm]) {
+        let scrutinee_type = self.check_expr(scrutinee);
+
+        let enum_name = match scrutinee_type {
+            Type::Enum(name) => Some(name),
+            Type::Unknown => None,
+            other => {
+                self.error(SemanticError::TypeMismatch {
+                    expected: Type::Enum("_".to_string()),
+                    found: other,
+                    context: "match scrutinee must be an enum".to_string(),
+                });
+                None
+            }
+        };
+
+        if let Some(enum_name) = enum_name {
+            self.check_match_arms(&enum_name, arms);
+        }
+
+        for arm in arms {
+            self.enter_scope();
+            for s in &arm.body {
+                self.check_stmt(s);
+            }
+            self.exit_scope();
+        }
+    }
+
+    // every variant needs an arm; an arm that can never be chosen (a repeated
+    // variant, or anything after `_`) is only worth a warning
+    fn check_match_arms(&mut self, enum_name: &str, arms: &[MatchArm]) {
+        let variants = self.enums[enum_name].clone();
+        let mut covered: Vec<&str> = Vec::new();
+        let mut wildcard = false;
+
+        for arm in arms {
+            let (reachable, pattern) = match &arm.pattern {
+                MatchPattern::Variant(v) => {
+                    if !variants.contains(v) {
+                        self.error(SemanticError::UnknownVariant {
+                            enum_name: enum_name.to_string(),
+                            variant: v.clone(),
+                        });
+                        continue;
+                    }
+                    // a repeated variant must not count twice towards `_`
+                    let repeated = covered.contains(&v.as_str());
+                    if !repeated {
+                        covered.push(v);
+                    }
+                    let reachable = !wildcard && !repeated;
+                    (reachable, v.clone())
+                }
+                MatchPattern::Wildcard => {
+                    let reachable = !wildcard && covered.len() < variants.len();
+                    wildcard = true;
+                    (reachable, "_".to_string())
+                }
+            };
+
+            if !reachable {
+                self.warn(SemanticWarning::UnreachableArm {
+                    enum_name: enum_name.to_string(),
+                    pattern,
+                });
+            }
+        }
+
+        let missing: Vec<String> = variants.iter()
+            .filter(|v| !covered.contains(&v.as_str()))
+            .cloned()
+            .collect();
+
+        if !wildcard && !missing.is_empty() {
+            self.error(SemanticError::NonExhaustiveMatch {
+                enum_name: enum_name.to_string(),
+                missing,
+            });
+        }
+    }
+
+
+
+    // consts and globals: a scalar variable whose initializer must fold to a
+    // constant, using only the consts declared before it
+    fn check_static_decl(&mut self, name: &str, ty: &TypeName, value: &Expr) -> Option<ConstValue> {
+        self.check_var_decl(name, ty, value);
+
+        let declared = self.lookup(name)?;
+        if let Type::Array(..) | Type::Struct(_) = declared {
+            self.error(SemanticError::InvalidStaticType(name.to_string()));
+            return None;
+        }
+
+        let value = Optimizer::with_constants(self.consts.clone()).evaluate(value);
+        if value.is_none() {
+            self.error(SemanticError::NotConstant(name.to_string()));
+        }
+        value
+    }
+
+    fn check_var_decl(&mut self, name: &str, ty: &TypeName, value: &Expr) {
+        if self.current_scope().contains_key(name) || self.consts.contains_key(name) {
+            self.error(SemanticError::Redeclaration(name.to_string()));
+            return;
+        }
+
+        let declared = self.resolve_type(ty);
+        let value_type = self.check_expr(value);
+
+        if value_type != declared && value_type != Type::Unknown && declared != Type::Unknown {
+            self.error(SemanticError::TypeMismatch {
+                expected: declared.clone(),
+                found: value_type,
+                context: format!("initializer for '{}' must be {:?}", name, declared),
+            });
+        }
+
+        self.current_scope().insert(name.to_string(), declared);
+    }
+
+    fn check_if(&mut self, cond: &Expr, then_block: &[Stmt], else_block: &Option<Vec<Stmt>>) {
+        let cond_type = self.check_expr(cond);
+
+        if cond_type != Type::Bool && cond_type != Type::Unknown {
+            self.error(SemanticError::TypeMismatch {
+                expected: Type::Bool,
+                found: cond_type,
+                context: "if condition must be boolean".to_string(),
+            });
+        }
+
+        self.enter_scope();
+        for s in then_block {
+            self.check_stmt(s);
+        }
+        self.exit_scope();
+
+        if let Some(stmts) = else_block {
+            self.enter_scope();
+            for s in stmts {
+                self.check_stmt(s);
+            }
+            self.exit_scope();
+        }
+    }
+
+    // ---------- expressions ----------
+
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::IntegerLiteral(_) => Type::Int,
+            Expr::StringLiteral(_) => Type::String,
+            Expr::BooleanLiteral(_) => Type::Bool,
+            Expr::Maybe => Type::Bool,
+            Expr::Identifier(name) => {
+                if let Some(t) = self.lookup(name) {
+                    t 
+                } else {
+                    println!("wait, variable {} is undefined. \n should i pretend its an INT? (y/n:)", name);
+                    io::stdout().flush().unwrap();
+
+                    let mut input = String::new();
+                    io::stdin().read_line(&mut input).unwrap();
+                    if input.trim() == "y" {
+                        println!("okay, i'll treat {} as an INT for now", name);
+                        self.current_scope().insert(name.clone(), Type::Int);
+                        Type::Int
+                    } else {
+                        self.error(SemanticError::UndeclaredVariable(name.clone()));
+                        Type::Unknown
+                    }
+                }
+            }
+
+            Expr::Assign { name, value } => {
+                if self.consts.contains_key(name) {
+                    self.error(SemanticError::AssignToConstant(name.clone()));
+                }
+
+                let var_type = self.lookup(name).unwrap_or_else(|| {
+                    self.error(SemanticError::UndeclaredVariable(name.clone()));
+                    Type::Unknown
+                });
+
+                let value_type = self.check_expr(value);
+
+                if var_type != Type::Unknown && value_type != Type::Unknown && var_type != value_type {
+                    self.error(SemanticError::TypeMismatch {
+                        expected: var_type.clone(),
+                        found: value_type.clone(),
+                        context: format!("cannot assign to '{}'", name),
+                    });
+                }
+
+                var_type
+            }
+
+            Expr::Unary { op, operand } => self.check_unary(op, operand),
+
+            Expr::ArrayLiteral(elements) => {
+                let elem_type = self.check_expr(&elements[0]);
+
+                for e in &elements[1..] {
+                    let t = self.check_expr(e);
+                    if t != elem_type && t != Type::Unknown && elem_type != Type::Unknown {
+                        self.error(SemanticError::TypeMismatch {
+                            expected: elem_type.clone(),
+                            found: t,
+                            context: "array elements must all have the same type".to_string(),
+                        });
+                    }
+                }
+
+                Type::Array(Box::new(elem_type), elements.len())
+            }
+
+            Expr::Index { name, index } => self.check_index(name, index),
+
+            Expr::StructLiteral { name, fields } => self.check_struct_literal(name, fields),
+
+            Expr::Field { name, field } => self.check_field(name, field),
+
+            Expr::EnumVariant { enum_name, variant } => {
+                match self.enums.get(enum_name) {
+                    Some(variants) if variants.contains(variant) => Type::Enum(enum_name.clone()),
+                    Some(_) => {
+                        self.error(SemanticError::UnknownVariant {
+                            enum_name: enum_name.clone(),
+                            variant: variant.clone(),
+                        });
+                        Type::Unknown
+                    }
+                    None => {
+                        self.error(SemanticError::UndeclaredType(enum_name.clone()));
+                        Type::Unknown
+                    }
+                }
+            }
+
+            Expr::FieldAssign { name, field, value } => {
+                let field_type = self.check_field(name, field);
+                let value_type = self.check_expr(value);
+
+                if field_type != Type::Unknown && value_type != Type::Unknown && field_type != value_type {
+                    self.error(SemanticError::TypeMismatch {
+                        expected: field_type.clone(),
+                        found: value_type,
+                        context: format!("cannot assign to '{}.{}'", name, field),
+                    });
+                }
+
+                field_type
+            }
+
+            Expr::IndexAssign { name, index, value } => {
+                let elem_type = self.check_index(name, index);
+                let value_type = self.check_expr(value);
+
+                if elem_type != Type::Unknown && value_type != Type::Unknown && elem_type != value_type {
+                    self.error(SemanticError::TypeMismatch {
+                        expected: elem_type.clone(),
+                        found: value_type,
+                        context: format!("cannot assign to an element of '{}'", name),
+                    });
+                }
+
+                elem_type
+            }
+
+            Expr::Binary { left, op, right } => self.check_binary(left, op, right),
+
+            Expr::Conditional { condition, then_expr, else_expr } => {
+                self.check_conditional(condition, then_expr, else_expr)
+            }
+
+            Expr::Conversion { target, operand } => self.check_conversion(target, operand),
+
+            Expr::Call { name, args } => self.check_call(name, args),
+        }
+    }
+
+    fn check_call(&mut self, name: &str, args: &[Expr]) -> Type {
+        let arg_types: Vec<Type> = args.iter().map(|a| self.check_expr(a)).collect();
+
+        let Some((params, ret)) = builtin_signature(name) else {
+            self.error(SemanticError::UnknownFunction(name.to_string()));
+            return Type::Unknown;
+        };
+
+        if params.len() != args.len() {
+            self.error(SemanticError::ArgumentCount {
+                name: name.to_string(),
+                expected: params.len(),
+                found: args.len(),
+            });
+            return ret;
+        }
+
+        for (expected, found) in params.into_iter().zip(arg_types) {
+            if found != expected && found != Type::Unknown {
+                self.error(SemanticError::TypeMismatch {
+                    expected,
+                    found,
+                    context: format!("argument to '{}'", name),
+                });
+            }
+        }
+
+        ret
+    }
+
+    fn check_struct_literal(&mut self, name: &str, fields: &[(String, Expr)]) -> Type {
+        let Some(declared) = self.structs.get(name).cloned() else {
+            self.error(SemanticError::UndeclaredType(name.to_string()));
+            for (_, value) in fields {
+                self.check_expr(value);
+            }
+            return Type::Unknown;
+        };
+
+        let mut seen: Vec<&str> = Vec::new();
+        for (field, value) in fields {
+            let value_type = self.check_expr(value);
+
+            if seen.contains(&field.as_str()) {
+                self.error(SemanticError::Redeclaration(format!("{}.{}", name, field)));
+                continue;
+            }
+            seen.push(field);
+
+            match declared.iter().find(|(f, _)| f == field) {
+                Some((_, t)) => {
+                    if value_type != *t && value_type != Type::Unknown {
+                        self.error(SemanticError::TypeMismatch {
+                            expected: t.clone(),
+                            found: value_type,
+                            context: format!("field '{}.{}'", name, field),
+                        });
+                    }
+                }
+                None => self.error(SemanticError::UnknownField {
+                    struct_name: name.to_string(),
+                    field: field.clone(),
+                }),
+            }
+        }
+
+        for (field, _) in &declared {
+            if !seen.contains(&field.as_str()) {
+                self.error(SemanticError::MissingField {
+                    struct_name: name.to_string(),
+                    field: field.clone(),
+                });
+            }
+        }
+
+        Type::Struct(name.to_string())
+    }
+
+    fn check_field(&mut self, name: &str, field: &str) -> Type {
+        match self.lookup(name) {
+            Some(Type::Struct(struct_name)) => {
+                let field_type = self.structs.get(&struct_name)
+                    .and_then(|fields| fields.iter().find(|(f, _)| f == field))
+                    .map(|(_, t)| t.clone());
+
+                field_type.unwrap_or_else(|| {
+                    self.error(SemanticError::UnknownField {
+                        struct_name,
+                        field: field.to_string(),
+                    });
+                    Type::Unknown
+                })
+            }
+            Some(Type::Unknown) => Type::Unknown,
+            Some(_) => {
+                self.error(SemanticError::NotAStruct(name.to_string()));
+                Type::Unknown
+            }
+            None => {
+                self.error(SemanticError::UndeclaredVariable(name.to_string()));
+                Type::Unknown
+            }
+        }
+    }
+
+    fn check_index(&mut self, name: &str, index: &Expr) -> Type {
+        let index_type = self.check_expr(index);
+        if index_type != Type::Int && index_type != Type::Unknown {
+            self.error(SemanticError::TypeMismatch {
+                expected: Type::Int,
+                found: index_type,
+                context: format!("index into '{}' must be Int", name),
+            });
+        }
+
+        match self.lookup(name) {
+            Some(Type::Array(elem, len)) => {
+                // constant indices are checked here; the rest at runtime
+                if let Some(i) = literal_index(index)
+                    && (i < 0 || i >= len as i64)
+                {
+                    self.error(SemanticError::IndexOutOfBounds {
+                        name: name.to_string(),
+                        index: i,
+                        len,
+                    });
+                }
+                *elem
+            }
+            Some(Type::Unknown) => Type::Unknown,
+            Some(_) => {
+                self.error(SemanticError::NotAnArray(name.to_string()));
+                Type::Unknown
+            }
+            None => {
+                self.error(SemanticError::UndeclaredVariable(name.to_string()));
+                Type::Unknown
+            }
+        }
+    }
+
+    fn check_unary(&mut self, op: &UnOp, operand: &Expr) -> Type {
+        let t = self.check_expr(operand);
+
+        let (expected, context) = match op {
+            UnOp::Neg => (Type::Int, "negation requires Int"),
+            UnOp::Not => (Type::Bool, "'!' requires Bool"),
+        };
+
+        if let Some(t) = unary_type(op, &t) {
+            t
+        } else {
+            if t != Type::Unknown {
+                self.error(SemanticError::TypeMismatch {
+                    expected,
+                    found: t,
+                    context: context.to_string(),
+                });
+            }
+            Type::Unknown
+        }
+    }
+
+    fn check_conditional(&mut self, cond: &Expr, then_expr: &Expr, else_expr: &Expr) -> Type {
+        let cond_type = self.check_expr(cond);
+        let then_type = self.check_expr(then_expr);
+        let else_type = self.check_expr(else_expr);
+
+        if cond_type != Type::Bool && cond_type != Type::Unknown {
+            self.error(SemanticError::TypeMismatch {
+                expected: Type::Bool,
+                found: cond_type,
+                context: "'?' condition must be boolean".to_string(),
+            });
+        }
+
+        if then_type == Type::Unknown || else_type == Type::Unknown {
+            Type::Unknown
+        } else if then_type != else_type {
+            self.error(SemanticError::TypeMismatch {
+                expected: then_type,
+                found: else_type,
+                context: "both branches of '?:' must have the same type".to_string(),
+            });
+            Type::Unknown
+        } else {
+            then_type
+        }
+    }
+
+    fn check_conversion(&mut self, target: &TypeName, operand: &Expr) -> Type {
+        let target = self.resolve_type(target);
+        let from = self.check_expr(operand);
+
+        if conversion_type(target.clone(), &from).is_some() || from == Type::Unknown {
+            target
+        } else {
+            self.error(SemanticError::TypeMismatch {
+                expected: target.clone(),
+                found: from.clone(),
+                context: format!("cannot convert {:?} to {:?}", from, target),
+            });
+            Type::Unknown
+        }
+    }
+
+    fn check_binary(&mut self, left: &Expr, op: &BinOp, right: &Expr) -> Type {
+        let lt = self.check_expr(left);
+        let rt = self.check_expr(right);
+
+        if let Some(t) = binary_type(op, &lt, &rt) {
+            return t;
+        }
+
+        // the operand that is not of the type required, for the error
+        let wrong = |expected: Type| if lt != expected { lt.clone() } else { rt.clone() };
+        let (expected, found, context) = match op {
+            BinOp::Add => (lt.clone(), rt.clone(), "invalid '+' operands"),
+            BinOp::Sub => (Type::Int, wrong(Type::Int), "subtraction requires Int"),
+            BinOp::Mul | BinOp::Div | BinOp::Mod => {
+                (Type::Int, wrong(Type::Int), "'*', '/' and '%' require Int")
+            }
+            BinOp::GreaterThan
+            | BinOp::LessThan
+            | BinOp::GreaterEqual
+            | BinOp::LessEqual
+            | BinOp::Equal
+            | BinOp::NotEqual => {
+                (lt.clone(), rt.clone(), "comparison requires two Int, Bool or String operands")
+            }
+            BinOp::And | BinOp::Or => (Type::Bool, wrong(Type::Bool), "'&&' and '||' require Bool"),
+        };
+        self.error(SemanticError::TypeMismatch {
+            expected,
+            found,
+            context: context.to_string(),
+        });
+        Type::Unknown
+    }
+}
+
+fn literal_index(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::IntegerLiteral(n) => Some(*n as i64),
+        Expr::Unary { op: UnOp::Neg, operand } => literal_index(operand).map(|n| -n),
+        _ => None,
+    }
+}
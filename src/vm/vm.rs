@@ -0,0 +1,370 @@
+use crate::codegen::bytecode::{Const, Instr, Program};
+use crate::interp::runtime::{Halt, Runtime, Value};
+use crate::parsing::ast::{BinOp, TypeName};
+
+pub use crate::interp::runtime::{RunOptions, RunResult};
+
+// Runs a bytecode program on a value stack, sharing the interpreter's
+// runtime so both produce the same output and exit status for a seed.
+pub struct Vm {
+    runtime: Runtime,
+    stack: Vec<Value>,
+    slots: Vec<Value>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self {
+            runtime: Runtime::new(),
+            stack: Vec::new(),
+            slots: Vec::new(),
+        }
+    }
+
+    pub fn with_options(mut self, options: RunOptions) -> Self {
+        self.runtime.options = options;
+        self
+    }
+
+    // expects a program produced by BytecodeCompiler, directly or read back
+    // from its file format, where Program::from_bytes verifies the code
+    pub fn run(mut self, program: &Program) -> RunResult {
+        self.runtime.source_name = program.source_name.clone();
+        self.slots = vec![Value::Int(0); program.slots as usize];
+        self.runtime.start();
+        let result = self.execute(program);
+        self.runtime.finish(result)
+    }
+
+    fn execute(&mut self, program: &Program) -> Result<(), Halt> {
+        let mut pc = 0;
+        while let Some(instr) = program.code.get(pc) {
+            pc += 1;
+            match instr {
+                Instr::Push(c) => {
+                    let value = constant(program, *c);
+                    self.stack.push(value);
+                }
+                Instr::Load(slot) => self.stack.push(self.slots[*slot as usize].clone()),
+                Instr::Store(slot) => self.slots[*slot as usize] = self.pop(),
+
+                Instr::LoadIndex(slot) => {
+                    let i = self.pop().int();
+                    let Value::Array(elements) = &self.slots[*slot as usize] else {
+                        unreachable!("compiled or verified bytecode only indexes arrays")
+                    };
+                    match usize::try_from(i).ok().and_then(|i| elements.get(i)) {
+                        Some(value) => self.stack.push(value.clone()),
+                        None => return self.runtime.trap("runtime error: array index out of bounds"),
+                    }
+                }
+                Instr::StoreIndex(slot) => {
+                    let value = self.pop();
+                    let i = self.pop().int();
+                    let Value::Array(elements) = &mut self.slots[*slot as usize] else {
+                        unreachable!("compiled or verified bytecode only indexes arrays")
+                    };
+                    match usize::try_from(i).ok().and_then(|i| elements.get_mut(i)) {
+                        Some(element) => *element = value,
+                        None => return self.runtime.trap("runtime error: array index out of bounds"),
+                    }
+                }
+
+                Instr::LoadField(slot, field) => {
+                    let field = string(program, *field);
+                    let Value::Struct(fields) = &self.slots[*slot as usize] else {
+                        unreachable!("compiled or verified bytecode only accesses fields of structs")
+                    };
+                    let (_, value) = fields
+                        .iter()
+                        .find(|(f, _)| f == field)
+                        .expect("compiled or verified bytecode only names fields the struct has");
+                    self.stack.push(value.clone());
+                }
+                Instr::StoreField(slot, field) => {
+                    let field = string(program, *field);
+                    let value = self.pop();
+                    let Value::Struct(fields) = &mut self.slots[*slot as usize] else {
+                        unreachable!("compiled or verified bytecode only accesses fields of structs")
+                    };
+                    let entry = fields
+                        .iter_mut()
+                        .find(|(f, _)| f == field)
+                        .expect("compiled or verified bytecode only names fields the struct has");
+                    entry.1 = value;
+                }
+
+                Instr::MakeArray(n) => {
+                    let elements = self.pop_n(*n as usize);
+                    self.stack.push(Value::Array(elements));
+                }
+                Instr::MakeStruct(names) => {
+                    let values = self.pop_n(names.len());
+                    let fields = names
+                        .iter()
+                        .map(|name| string(program, *name).to_string())
+                        .zip(values)
+                        .collect();
+                    self.stack.push(Value::Struct(fields));
+                }
+
+                Instr::Add => self.binary(BinOp::Add)?,
+                Instr::Sub => self.binary(BinOp::Sub)?,
+                Instr::Mul => self.binary(BinOp::Mul)?,
+                Instr::Div => self.binary(BinOp::Div)?,
+                Instr::Mod => self.binary(BinOp::Mod)?,
+                Instr::Eq => self.binary(BinOp::Equal)?,
+                Instr::Ne => self.binary(BinOp::NotEqual)?,
+                Instr::Lt => self.binary(BinOp::LessThan)?,
+                Instr::Gt => self.binary(BinOp::GreaterThan)?,
+                Instr::Le => self.binary(BinOp::LessEqual)?,
+                Instr::Ge => self.binary(BinOp::GreaterEqual)?,
+                Instr::Neg => {
+                    let n = self.pop().int();
+                    self.stack.push(Value::Int(n.wrapping_neg()));
+                }
+                Instr::Not => {
+                    let b = self.pop().bool();
+                    self.stack.push(Value::Bool(!b));
+                }
+
+                Instr::Jump(target) => pc = *target as usize,
+                Instr::Jz(target) => {
+                    if !self.pop().bool() {
+                        pc = *target as usize;
+                    }
+                }
+                Instr::Jnz(target) => {
+                    if self.pop().bool() {
+                        pc = *target as usize;
+                    }
+                }
+                Instr::Dup => {
+                    let top = self.stack.last().expect("bytecode stack underflow").clone();
+                    self.stack.push(top);
+                }
+                Instr::Pop => {
+                    self.pop();
+                }
+
+                Instr::Maybe => {
+                    let b = self.runtime.flip();
+                    self.stack.push(Value::Bool(b));
+                }
+
+                Instr::ToInt => self.convert(TypeName::Int)?,
+                Instr::ToBool => self.convert(TypeName::Bool)?,
+                Instr::ToString => self.convert(TypeName::String)?,
+
+                Instr::Call(name, argc) => {
+                    let args = self.pop_n(*argc as usize);
+                    let value = self.runtime.call(string(program, *name), &args)?;
+                    self.stack.push(value);
+                }
+
+                Instr::Print => {
+                    let value = self.pop();
+                    let text = self.runtime.display(&value);
+                    self.runtime.print(&text);
+                }
+                Instr::PrintFormatted(format, argc) => {
+                    let args = self.pop_n(*argc as usize);
+                    let mut pieces = string(program, *format).split("{}");
+                    let mut text = pieces.next().unwrap_or_default().to_string();
+                    for (arg, piece) in args.iter().zip(pieces) {
+                        text.push_str(&self.runtime.display(arg));
+                        text.push_str(piece);
+                    }
+                    self.runtime.print(&text);
+                }
+
+                Instr::Paywall(n) => self.runtime.paywall(*n)?,
+
+                Instr::Assert { line, message } => {
+                    if !self.pop().bool() {
+                        let message = message.map(|m| string(program, m));
+                        self.runtime.assert_failed(*line as usize, message)?;
+                    }
+                }
+
+                Instr::Exit => {
+                    let status = self.pop().int();
+                    return Err(Halt::Exit(status));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("bytecode stack underflow")
+    }
+
+    // the top n values, deepest first
+    fn pop_n(&mut self, n: usize) -> Vec<Value> {
+        let at = self.stack.len().checked_sub(n).expect("bytecode stack underflow");
+        self.stack.split_off(at)
+    }
+
+    fn binary(&mut self, op: BinOp) -> Result<(), Halt> {
+        let r = self.pop();
+        let l = self.pop();
+        let value = self.runtime.binary(l, &op, r)?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn convert(&mut self, target: TypeName) -> Result<(), Halt> {
+        let value = self.pop();
+        let value = self.runtime.convert(&target, value)?;
+        self.stack.push(value);
+        Ok(())
+    }
+}
+
+fn constant(program: &Program, index: u32) -> Value {
+    match &program.constants[index as usize] {
+        Const::Int(n) => Value::Int(*n),
+        Const::Bool(b) => Value::Bool(*b),
+        Const::String(s) => Value::String(s.clone()),
+        Const::Enum { enum_name, index } => Value::Enum(enum_name.clone(), *index as usize),
+    }
+}
+
+fn string(program: &Program, index: u32) -> &str {
+    match &program.constants[index as usize] {
+        Const::String(s) => s,
+        _ => unreachable!("compiled or verified bytecode names things with string constants"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::bytecode::BytecodeCompiler;
+    use crate::interp::interp::Interpreter;
+    use crate::lexing::lexer::lex_program;
+    use crate::optimizer::optimizer::Optimizer;
+    use crate::parsing::ast::Stmt;
+    use crate::parsing::parser::Parser;
+    use crate::semantic::semantic::SemanticAnalyzer;
+
+    const PROGRAMS: [&str; 6] = [
+        // strings, structs, arrays, input and arguments, ending on a trap
+        r#"
+        enum Color { Red, Green, Blue }
+        struct P { int x; int y; }
+        const int N = 3;
+        global int g = 5;
+        int[N] a = [1, 2, 3];
+        P p = P { x: 1, y: 2 };
+        p.x = 40;
+        a[2] = a[0] + p.x;
+        Color c = Color::Blue;
+        match (c) { Red => { print("red"); } Blue => { print("blue {}", 7); } _ => {} }
+        print(2147483647 + g);
+        string s = "hello" + " " + read_line();
+        print(to_upper(substr(s, 1, 3)));
+        print(index_of(s, "lo"));
+        print(char_at(s, 0) == "h");
+        int n = read_int();
+        print("n={}, args={}, first={}", n, arg_count(), args(1));
+        print(int("12") * -1 / 5);
+        print(10 % (n - n));
+        "#,
+        // maybe in every position, asserts and exit
+        r#"
+        enum Coin { Heads, Tails }
+        int[3] a = [1, 2, 3];
+        int n = 0;
+        bool both = maybe && maybe;
+        if (both) { n = n + 1; }
+        a[1] = maybe ? 10 : 20;
+        Coin c = maybe ? Coin::Heads : Coin::Tails;
+        match (c) {
+            Heads => { print("h {}", a[1]); }
+            _ => { assert(n == 0, "no"); print(a[0]); }
+        }
+        if (n > 0 && maybe) { exit(2); }
+        int k = 3;
+        a[k - n] = 1;
+        print("done");
+        "#,
+        // a dense match, wrapping division and a bounds trap
+        r#"
+        enum Dir { North, East, South, West, Up }
+        Dir d = Dir::South;
+        match (d) { North => { print("n"); } East => { print("e"); } South => { print("s"); } West => { print("w"); } _ => { print("other"); } }
+        int a = arg_count();
+        print(-2147483648 / (a - 2));
+        print(-7 % 3);
+        print(string(a > 1) + string(a));
+        int[3] arr = [4, 5, 6];
+        print(arr[a + 5]);
+        "#,
+        // the paywall, answered from the input
+        r#"
+        print(1);
+        paywall(1);
+        print(2);
+        "#,
+        // a status outside a byte
+        r#"
+        if (maybe) { exit(256 + 3); }
+        return -1;
+        "#,
+        // a failed assert
+        r#"
+        int n = read_int();
+        assert(n < 3, "n too big");
+        "#,
+    ];
+
+    fn compile(source: &str) -> Vec<Stmt> {
+        let ast = Parser::new(lex_program(source)).parse().expect("parses");
+        SemanticAnalyzer::new().analyze(&ast).expect("checks");
+        Optimizer::new().optimize(ast).expect("optimizes")
+    }
+
+    #[test]
+    fn runs_like_the_interpreter() {
+        let input = "world\n4\nnope\nhaha\n";
+        let args = vec!["foo".to_string(), "bar".to_string()];
+        for source in PROGRAMS {
+            let ast = compile(source);
+            let program = BytecodeCompiler::new().with_source_name("test.q").compile(&ast);
+            for seed in 0..8 {
+                let options = RunOptions::new()
+                    .with_seed(seed)
+                    .with_input(input)
+                    .with_args(args.clone())
+                    .with_paywall_retries(2);
+                let expected = Interpreter::new()
+                    .with_source_name("test.q")
+                    .with_options(options.clone())
+                    .run(&ast);
+                let result = Vm::new().with_options(options).run(&program);
+                assert_eq!(result.stdout, expected.stdout, "seed {} of {}", seed, source);
+                assert_eq!(result.status, expected.status, "seed {} of {}", seed, source);
+            }
+        }
+    }
+
+    #[test]
+    fn runs_a_loaded_program_like_the_compiled_one() {
+        for source in PROGRAMS {
+            let program = BytecodeCompiler::new().with_source_name("test.q").compile(&compile(source));
+            let loaded = Program::from_bytes(&program.to_bytes()).expect("loads");
+            let options = RunOptions::new().with_seed(3).with_input("x\n1\n");
+            let run = |program: &Program| Vm::new().with_options(options.clone()).run(program);
+            let (a, b) = (run(&program), run(&loaded));
+            assert_eq!((a.stdout, a.status), (b.stdout, b.status));
+        }
+    }
+}